@@ -254,14 +254,21 @@ struct Config {
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
 enum Chip {
-    STM32L562QEIxQ,
+    /// Any TrustZone enabled member of the STM32L5 family (STM32L552 / STM32L562), holding the full part name
+    STM32L5(String),
+    /// The STM32U575 / STM32U585, holding the full part name
+    STM32U5(String),
     NRF5340,
+    NRF9160,
+    LPC55S69,
+    ATSAML11E16A,
 }
 
 impl Chip {
     fn enable_trustzone(&self, probe: &Option<DebugProbeSelector>) -> anyhow::Result<()> {
-        if let Chip::STM32L562QEIxQ = self {
+        if let Chip::STM32L5(_) | Chip::STM32U5(_) = self {
             Command::new("STM32_Programmer_CLI")
                 .arg(stm32_probe_connect(probe))
                 .arg("-ob")
@@ -274,7 +281,7 @@ impl Chip {
     }
 
     fn wipe_chip(&self, probe: &Option<DebugProbeSelector>) -> anyhow::Result<()> {
-        if let Chip::STM32L562QEIxQ = self {
+        if let Chip::STM32L5(_) | Chip::STM32U5(_) = self {
             let output = Command::new("STM32_Programmer_CLI")
                 .arg(stm32_probe_connect(probe))
                 .arg("-e")
@@ -290,10 +297,13 @@ impl Chip {
         Ok(())
     }
 
-    fn chip_name(&self) -> &'static str {
+    fn chip_name(&self) -> &str {
         match self {
-            Chip::STM32L562QEIxQ => "STM32L562QEIxQ",
+            Chip::STM32L5(name) | Chip::STM32U5(name) => name,
             Chip::NRF5340 => "nRF5340_xxAA",
+            Chip::NRF9160 => "nRF9160_xxAA",
+            Chip::LPC55S69 => "LPC55S69JBD100",
+            Chip::ATSAML11E16A => "ATSAML11E16A",
         }
     }
 }
@@ -317,13 +327,28 @@ impl std::str::FromStr for Chip {
     type Err = ();
     fn from_str(s: &str) -> Result<Chip, ()> {
         match s {
-            "STM32L562QEIxQ" => Ok(Chip::STM32L562QEIxQ),
+            s if s.starts_with("STM32L552") || s.starts_with("STM32L562") => {
+                Ok(Chip::STM32L5(s.to_string()))
+            }
+            s if s.starts_with("STM32U575") || s.starts_with("STM32U585") => {
+                Ok(Chip::STM32U5(s.to_string()))
+            }
             "nRF5340_xxAA" => Ok(Chip::NRF5340),
+            "nRF9160_xxAA" => Ok(Chip::NRF9160),
+            "LPC55S69JBD100" => Ok(Chip::LPC55S69),
+            "ATSAML11E16A" => Ok(Chip::ATSAML11E16A),
             _ => Err(()),
         }
     }
 }
 
+impl TryFrom<String> for Chip {
+    type Error = String;
+    fn try_from(s: String) -> Result<Chip, String> {
+        Chip::from_str(&s).map_err(|_| format!("{} not supported", s))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Image {
     release: bool,
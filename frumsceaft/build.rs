@@ -0,0 +1,14 @@
+use std::env;
+
+fn main() {
+    let target = env::var("TARGET").unwrap();
+    // ARMv8-M Baseline (thumbv8m.base) has no FPU, only the eabihf Mainline targets hand it to the non-secure world
+    if target.ends_with("-eabihf") {
+        println!("cargo:rustc-cfg=has_fpu");
+    }
+    // the TrustZone parts (`boot`, the TT checks) only build for ARMv8-M, the services' tests run on the host
+    if target.starts_with("thumbv8m") {
+        println!("cargo:rustc-cfg=armv8m");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
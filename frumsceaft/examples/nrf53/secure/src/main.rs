@@ -6,11 +6,13 @@
 use rtt_target::{rprintln, rtt_init_print};
 
 const NON_SECURE_START: u32 = 0x00050000u32;
-const NON_SECURE_SRAM_START: u32 = 0x10000u32;
+const NON_SECURE_SRAM_START: u32 = 0x20010000u32;
 const ROM_SIZE: u32 = 0x100000;
-const RAM_SIZE: u32 = 0x80000;
+const RAM_START: u32 = 0x20000000;
+const RAM_END: u32 = 0x20080000;
 
-use frumsceaft::nrf53::PerphExt;
+use frumsceaft::nrf53::AccessError;
+use nrf5340_app_pac::interrupt;
 
 #[panic_handler] // panicking behavior
 fn panic(_: &core::panic::PanicInfo) -> ! {
@@ -32,25 +34,34 @@ fn main() -> ! {
     rprintln!("secure start");
     let sg_start = unsafe { &__sg_start as *const u8 as u32 };
     let sg_end = unsafe { &__sg_end as *const u8 as u32 };
+    let p = nrf5340_app_pac::Peripherals::take().unwrap();
     let spu = unsafe { &*nrf5340_app_pac::SPU_S::ptr() };
+    let mut spu = frumsceaft::nrf53::SPU::new(spu);
+    spu.access_error_hook = Some(on_access_error);
     frumsceaft::boot(
-        spu,
+        &spu,
         frumsceaft::MemoryLayout {
             secure_flash_region: 0..NON_SECURE_START,
             non_secure_flash_region: NON_SECURE_START..ROM_SIZE,
-            secure_ram_region: 0..NON_SECURE_SRAM_START,
-            non_secure_ram_region: NON_SECURE_SRAM_START..RAM_SIZE,
+            secure_ram_region: RAM_START..NON_SECURE_SRAM_START,
+            non_secure_ram_region: NON_SECURE_SRAM_START..RAM_END,
             nsc_flash_region: Some(sg_start..sg_end),
+            non_secure_vector_table: None,
         },
-        &[
-            nrf5340_app_pac::P0_NS::perph(),
-            nrf5340_app_pac::MUTEX_NS::perph(),
-            nrf5340_app_pac::UARTE0_NS::perph(),
-            nrf5340_app_pac::TIMER0_NS::perph(),
-        ],
+        (p.P0_S, p.MUTEX_S, p.UARTE0_S, p.TIMER0_S),
     )
 }
 
+#[interrupt]
+fn SPU() {
+    frumsceaft::nrf53::handle_access_error();
+}
+
+fn on_access_error(err: AccessError) {
+    rprintln!("non-secure access violation: {:?}", err);
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
 #[no_mangle]
 #[cmse_nonsecure_entry]
 pub extern "C" fn secure_test_fn(input: u32) -> u32 {
@@ -7,7 +7,7 @@ use core::ops::Range;
 use cortex_m_rt::{exception, ExceptionFrame};
 use defmt::println;
 use defmt_rtt as _;
-use frumsceaft::stm32l562::{Peripheral, GTZC};
+use frumsceaft::stm32l5::{Peripheral, GTZC};
 use panic_probe as _;
 use stm32_hal2::gpio::{Pin, PinMode, Port};
 
@@ -24,13 +24,14 @@ fn main() -> ! {
     let _ = Pin::new(Port::D, 3, PinMode::Output);
 
     frumsceaft::boot(
-        &GTZC,
+        &GTZC::new(),
         frumsceaft::MemoryLayout {
             secure_flash_region: 0..NON_SECURE_START,
             non_secure_flash_region: NON_SECURE_START..NON_SECURE_STOP,
             secure_ram_region: 0..NON_SECURE_SRAM_START,
             non_secure_ram_region: NON_SECURE_SRAM_START..NON_SECURE_SRAM_STOP,
             nsc_flash_region: Some(NSC_RANGE),
+            non_secure_vector_table: None,
         },
         &[Peripheral::GPIOG(12), Peripheral::GPIOD(3)],
     )
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+use panic_probe as _;
+
+use cortex_m_rt::entry;
+use defmt_rtt as _;
+use stm32u5::stm32u575 as pac;
+
+#[link(name = "nsclib")]
+extern "C" {
+    pub fn secure_test_fn(input: u32) -> u32;
+}
+
+#[entry]
+fn main() -> ! {
+    let p = pac::Peripherals::take().unwrap();
+    defmt::println!("Hello, world!");
+
+    // enable the clocks for GPIOB and GPIOC, and set PB7 and PC7 as outputs
+    p.RCC
+        .ahb2enr1
+        .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 1) | (1 << 2)) });
+    p.GPIOB
+        .moder
+        .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 14)) | (0b01 << 14)) });
+    p.GPIOC
+        .moder
+        .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 14)) | (0b01 << 14)) });
+
+    defmt::println!("Our demo is alive");
+    let res = unsafe { secure_test_fn(10) };
+    defmt::println!("secure value: {:?}", res);
+    // Now, enjoy the lightshow!
+    let mut i = 0;
+    loop {
+        i = (i + 1) % 1000;
+        if i > 500 {
+            p.GPIOC.bsrr.write(|w| unsafe { w.bits(1 << (7 + 16)) });
+            p.GPIOB.bsrr.write(|w| unsafe { w.bits(1 << 7) });
+        } else {
+            p.GPIOC.bsrr.write(|w| unsafe { w.bits(1 << 7) });
+            p.GPIOB.bsrr.write(|w| unsafe { w.bits(1 << (7 + 16)) });
+        }
+    }
+}
@@ -0,0 +1,16 @@
+MEMORY
+{
+  FLASH : ORIGIN = 0x0C000000, LENGTH = 1016K
+  RAM : ORIGIN = 0x30000000, LENGTH = 256K
+  ROM_NSC : ORIGIN = 0x0C0FE000, LENGTH = 8K
+}
+
+SECTIONS
+{
+  .gnu.sgstubs : ALIGN(64)
+  {
+    __sg_start = .;
+   *(.gnu.sgstubs*)
+  } > ROM_NSC
+  __sg_end = .;
+} INSERT AFTER .rodata;
@@ -0,0 +1,52 @@
+#![feature(abi_c_cmse_nonsecure_call)]
+#![feature(cmse_nonsecure_entry)]
+#![no_main]
+#![no_std]
+
+use core::ops::Range;
+use cortex_m_rt::{exception, ExceptionFrame};
+use defmt::println;
+use defmt_rtt as _;
+use frumsceaft::stm32u5::{Peripheral, GTZC};
+use panic_probe as _;
+
+const NON_SECURE_START: u32 = 0x08100000;
+const NON_SECURE_STOP: u32 = 0x081FFFFF;
+const NON_SECURE_SRAM_START: u32 = 0x20040000;
+const NON_SECURE_SRAM_STOP: u32 = 0x200BFFFF;
+const NSC_RANGE: Range<u32> = 0x0C0FE000..0x0C0FFFFF;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    println!("boot");
+
+    frumsceaft::boot(
+        &GTZC::new(),
+        frumsceaft::MemoryLayout {
+            secure_flash_region: 0..NON_SECURE_START,
+            non_secure_flash_region: NON_SECURE_START..NON_SECURE_STOP,
+            secure_ram_region: 0x20000000..NON_SECURE_SRAM_START,
+            non_secure_ram_region: NON_SECURE_SRAM_START..NON_SECURE_SRAM_STOP,
+            nsc_flash_region: Some(NSC_RANGE),
+            non_secure_vector_table: None,
+        },
+        // LD1 (green) and LD2 (blue) on the NUCLEO-U575ZI-Q
+        &[Peripheral::GPIOC(7), Peripheral::GPIOB(7)],
+    )
+}
+
+#[no_mangle]
+#[cmse_nonsecure_entry]
+pub extern "C" fn secure_test_fn(input: u32) -> u32 {
+    input + 6
+}
+
+#[exception]
+unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
+    println!("pc {:?}", ef.pc());
+    println!("r0 {:?}", ef.r0());
+    println!("r1 {:?}", ef.r1());
+    println!("r2 {:?}", ef.r2());
+    println!("r3 {:?}", ef.r3());
+    loop {}
+}
@@ -0,0 +1,306 @@
+//! Reads back the security configuration, so what was actually programmed can be checked before jumping to the
+//! non-secure world.
+#[cfg(armv8m)]
+use crate::MemoryLayout;
+use core::ops::Range;
+use core::ptr::read_volatile;
+#[cfg(armv8m)]
+use cortex_m::cmse::{AccessType, TestTarget};
+
+const AIRCR: u32 = 0xE000ED0C;
+const NSACR: u32 = 0xE000ED8C;
+const ITNS: u32 = 0xE000E380;
+const SAU_CTRL: u32 = 0xE000EDD0;
+const SAU_TYPE: u32 = 0xE000EDD4;
+const SAU_RNR: u32 = 0xE000EDD8;
+const SAU_RBAR: u32 = 0xE000EDDC;
+const SAU_RLAR: u32 = 0xE000EDE0;
+
+/// Maximum number of SAU regions read back
+pub const MAX_SAU_REGIONS: usize = 8;
+/// Maximum number of memory attributions a [`SecurityMap`] holds, contiguous regions with the same
+/// attributes are merged into one.
+pub const MAX_MEMORY_ATTRIBUTIONS: usize = 32;
+/// Step between the addresses checked by [`verify_layout`]
+#[cfg(armv8m)]
+const VERIFY_STEP: u32 = 0x1000;
+
+fn read(addr: u32) -> u32 {
+    unsafe { read_volatile(addr as *const u32) }
+}
+
+/// A single enabled SAU region
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct SauRegionInfo {
+    pub base_address: u32,
+    /// The last address covered by the region
+    pub limit_address: u32,
+    pub non_secure_callable: bool,
+}
+
+/// The security attributes of a range of memory, as programmed into the chip's SPU / GTZC
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct MemoryAttribution {
+    pub start: u32,
+    pub end: u32,
+    pub secure: bool,
+    pub non_secure_callable: bool,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub locked: bool,
+}
+
+/// A chip-independent snapshot of the security configuration, returned by [`crate::IDAU::report`].
+#[derive(Clone, Debug, defmt::Format)]
+pub struct SecurityMap {
+    pub sau_enabled: bool,
+    /// SAU_CTRL.ALLNS, memory not covered by a region is non-secure when the SAU is disabled
+    pub sau_all_non_secure: bool,
+    pub sau_regions: [Option<SauRegionInfo>; MAX_SAU_REGIONS],
+    /// Memory attributions from the chip's SPU / GTZC, empty for backends that don't report them
+    pub memory: [Option<MemoryAttribution>; MAX_MEMORY_ATTRIBUTIONS],
+    /// Set if there were more attributions than fit in `memory`
+    pub memory_truncated: bool,
+    /// Bit `n` is set if the chip's peripheral `n` is non-secure, which is the SPU ID on the nRF, the
+    /// TZSC SECCFGR bit (32 per register, in register order) on the STM32, the AHB secure controller rule
+    /// (8 per rule register, in register order) on the LPC55, and the NONSEC bit (32 per bridge) on the
+    /// SAM L11. Reserved SECCFGR bits read as non-secure.
+    pub non_secure_peripherals: [u32; 8],
+    /// NVIC_ITNS, bit `n` is set if interrupt `n` targets the non-secure world
+    pub itns: [u32; 16],
+    pub aircr: u32,
+    pub nsacr: u32,
+}
+
+impl SecurityMap {
+    /// Reads the core's security registers: the SAU, NVIC_ITNS, AIRCR and NSACR
+    pub fn read_core() -> Self {
+        let ctrl = read(SAU_CTRL);
+        let count = ((read(SAU_TYPE) & 0xFF) as usize).min(MAX_SAU_REGIONS);
+        let mut sau_regions = [None; MAX_SAU_REGIONS];
+        for (i, region) in sau_regions.iter_mut().enumerate().take(count) {
+            unsafe { core::ptr::write_volatile(SAU_RNR as *mut u32, i as u32) };
+            let rlar = read(SAU_RLAR);
+            if rlar & 1 != 0 {
+                *region = Some(SauRegionInfo {
+                    base_address: read(SAU_RBAR) & !0x1F,
+                    limit_address: rlar | 0x1F,
+                    non_secure_callable: rlar & (1 << 1) != 0,
+                });
+            }
+        }
+        let mut itns = [0; 16];
+        for (i, word) in itns.iter_mut().enumerate() {
+            *word = read(ITNS + i as u32 * 4);
+        }
+        SecurityMap {
+            sau_enabled: ctrl & 1 != 0,
+            sau_all_non_secure: ctrl & (1 << 1) != 0,
+            sau_regions,
+            memory: [None; MAX_MEMORY_ATTRIBUTIONS],
+            memory_truncated: false,
+            non_secure_peripherals: [0; 8],
+            itns,
+            aircr: read(AIRCR),
+            nsacr: read(NSACR),
+        }
+    }
+
+    /// Adds a memory attribution, merging it into the previous one if it continues it with the same attributes
+    pub fn push_memory(&mut self, attribution: MemoryAttribution) {
+        let used = self.memory.iter().take_while(|m| m.is_some()).count();
+        if let Some(Some(last)) = used.checked_sub(1).map(|i| &mut self.memory[i]) {
+            let same_attributes = MemoryAttribution {
+                start: last.start,
+                end: last.end,
+                ..attribution
+            } == *last;
+            if last.end == attribution.start && same_attributes {
+                last.end = attribution.end;
+                return;
+            }
+        }
+        match self.memory.get_mut(used) {
+            Some(slot) => *slot = Some(attribution),
+            None => self.memory_truncated = true,
+        }
+    }
+
+    /// Adds a range of memory with `secure` and `locked`, for controllers that only set security (e.g. the
+    /// STM32 GTZC), so the range can be read, written, and executed from the world that owns it
+    pub fn push_range(&mut self, range: Range<u32>, secure: bool, locked: bool) {
+        if range.start >= range.end {
+            return;
+        }
+        self.push_memory(MemoryAttribution {
+            start: range.start,
+            end: range.end,
+            secure,
+            non_secure_callable: false,
+            read: true,
+            write: true,
+            execute: true,
+            locked,
+        });
+    }
+
+    /// Adds the blocks of a block based controller (e.g. the STM32 GTZC MPCBB) starting at `base`. Bit `n` of
+    /// `secure[i]` is set if block `32 * i + n` is secure, and bit `i` of `locked` if those 32 blocks are locked.
+    pub fn push_blocks(&mut self, base: u32, block_size: u32, secure: &[u32], locked: u32) {
+        for (i, bits) in secure.iter().enumerate() {
+            let locked = locked & (1 << i) != 0;
+            for bit in 0..32 {
+                let start = base + (i as u32 * 32 + bit) * block_size;
+                self.push_range(start..start + block_size, bits & (1 << bit) != 0, locked);
+            }
+        }
+    }
+
+    /// Adds a bank guarded by a watermark controller (e.g. the STM32 GTZC MPCWM), the `non_secure` windows
+    /// are non-secure and the rest of the bank is secure
+    pub fn push_watermarks<const N: usize>(
+        &mut self,
+        bank: Range<u32>,
+        mut non_secure: [Range<u32>; N],
+        locked: bool,
+    ) {
+        non_secure.sort_unstable_by_key(|window| window.start);
+        let mut next = bank.start;
+        for window in non_secure.iter().filter(|window| window.start < window.end) {
+            let start = window.start.clamp(next, bank.end);
+            let end = window.end.clamp(start, bank.end);
+            self.push_range(next..start, true, locked);
+            self.push_range(start..end, false, locked);
+            next = end;
+        }
+        self.push_range(next..bank.end, true, locked);
+    }
+
+    /// Marks peripheral `n` as non-secure
+    pub fn set_non_secure_peripheral(&mut self, n: usize) {
+        if let Some(word) = self.non_secure_peripherals.get_mut(n / 32) {
+            *word |= 1 << (n % 32);
+        }
+    }
+
+    /// Prints the map
+    pub fn print(&self) {
+        defmt::println!(
+            "SAU enabled: {}, ALLNS: {}",
+            self.sau_enabled,
+            self.sau_all_non_secure
+        );
+        for (i, region) in self.sau_regions.iter().enumerate() {
+            if let Some(region) = region {
+                defmt::println!(
+                    "SAU region {}: {:x}..={:x} {}",
+                    i,
+                    region.base_address,
+                    region.limit_address,
+                    if region.non_secure_callable {
+                        "NSC"
+                    } else {
+                        "NS"
+                    }
+                );
+            }
+        }
+        for m in self.memory.iter().flatten() {
+            defmt::println!(
+                "{:x}..{:x}: {} r:{} w:{} x:{} locked:{}",
+                m.start,
+                m.end,
+                if m.non_secure_callable {
+                    "NSC"
+                } else if m.secure {
+                    "S"
+                } else {
+                    "NS"
+                },
+                m.read,
+                m.write,
+                m.execute,
+                m.locked
+            );
+        }
+        if self.memory_truncated {
+            defmt::println!("(memory attributions truncated)");
+        }
+        defmt::println!("non-secure peripherals: {:x}", self.non_secure_peripherals);
+        defmt::println!("ITNS: {:x}", self.itns);
+        defmt::println!("AIRCR: {:x} NSACR: {:x}", self.aircr, self.nsacr);
+    }
+}
+
+/// Sweeps each range of `layout` with `TestTarget`, checking that the security attribution seen by the core
+/// matches the layout. Addresses are checked every 4 KiB, and at the last 32 bytes of each range.
+///
+/// Each mismatched run of addresses is reported through defmt, and the number of mismatched runs is returned.
+/// `boot` calls this before jumping to the non-secure world.
+#[cfg(armv8m)]
+pub fn verify_layout(layout: &MemoryLayout) -> usize {
+    let mut mismatches = 0;
+    let ranges = [
+        ("secure flash", Some(&layout.secure_flash_region), true),
+        (
+            "non-secure flash",
+            Some(&layout.non_secure_flash_region),
+            false,
+        ),
+        ("secure RAM", Some(&layout.secure_ram_region), true),
+        ("non-secure RAM", Some(&layout.non_secure_ram_region), false),
+        // NSC memory is secure memory that can be called into, TT reports it as secure
+        ("NSC", layout.nsc_flash_region.as_ref(), true),
+    ];
+    for (name, range, secure) in ranges {
+        if let Some(range) = range {
+            mismatches += verify_range(name, range, secure);
+        }
+    }
+    mismatches
+}
+
+#[cfg(armv8m)]
+fn verify_range(name: &str, range: &Range<u32>, secure: bool) -> usize {
+    if range.start >= range.end {
+        return 0;
+    }
+    let mut mismatches = 0;
+    let mut run_start = None;
+    let last = (range.end - 1) & !0x1F;
+    let mut addr = range.start;
+    loop {
+        let found = TestTarget::check(addr as *mut u32, AccessType::Current).secure();
+        match (found == secure, run_start) {
+            (false, None) => run_start = Some(addr),
+            (true, Some(start)) => {
+                report_mismatch(name, start..addr, secure);
+                mismatches += 1;
+                run_start = None;
+            }
+            _ => {}
+        }
+        if addr == last {
+            break;
+        }
+        addr = addr.saturating_add(VERIFY_STEP).min(last);
+    }
+    if let Some(start) = run_start {
+        report_mismatch(name, start..range.end, secure);
+        mismatches += 1;
+    }
+    mismatches
+}
+
+#[cfg(armv8m)]
+fn report_mismatch(name: &str, range: Range<u32>, secure: bool) {
+    defmt::warn!(
+        "{} {:x}..{:x} should be {} but is {}",
+        name,
+        range.start,
+        range.end,
+        if secure { "secure" } else { "non-secure" },
+        if secure { "non-secure" } else { "secure" }
+    );
+}
@@ -0,0 +1,1143 @@
+//! Crypto service for the non-secure world, a subset of the PSA Crypto API.
+//!
+//! Keys are referred to by opaque [`KeyId`]s. Their material stays in secure RAM, and in protected storage for
+//! persistent keys, and is never handed to the non-secure world: only the public half of an ECC key can be exported.
+//!
+//! | Key type | Operations |
+//! |----------|------------|
+//! | AES-128, AES-256 | AES-GCM with a 12 byte nonce and a 16 byte tag (`aead_encrypt`, `aead_decrypt`) |
+//! | HMAC | HMAC-SHA-256 (`mac_compute`, `mac_verify`) |
+//! | ECC P-256 key pair | ECDSA over SHA-256 hashes (`sign_hash`, `verify_hash`), ECDH (`raw_key_agreement`) |
+//! | ECC P-256 public key | `verify_hash` |
+//!
+//! SHA-256 is available without a key through `hash_compute`.
+//!
+//! The primitives run on a [`Backend`]. Every method but `random` defaults to the software implementation, so a
+//! hardware backend only overrides what its hardware accelerates. The non-secure world reaches the service through
+//! the veneers generated by [`crypto_veneers`](crate::crypto_veneers).
+use crate::nsc::{self, status};
+use crate::storage;
+use core::ops::Range;
+
+pub mod software;
+
+pub type KeyId = u32;
+
+// key types, these are the PSA values
+pub const KEY_TYPE_AES: u32 = 0x2400;
+pub const KEY_TYPE_HMAC: u32 = 0x1100;
+/// An ECC key pair on secp256r1
+pub const KEY_TYPE_P256_KEY_PAIR: u32 = 0x7112;
+/// An ECC public key on secp256r1
+pub const KEY_TYPE_P256_PUBLIC_KEY: u32 = 0x4112;
+
+// usage flags, these are the PSA values
+pub const USAGE_ENCRYPT: u32 = 0x0100;
+pub const USAGE_DECRYPT: u32 = 0x0200;
+/// Allows `mac_compute`
+pub const USAGE_SIGN_MESSAGE: u32 = 0x0400;
+/// Allows `mac_verify`
+pub const USAGE_VERIFY_MESSAGE: u32 = 0x0800;
+pub const USAGE_SIGN_HASH: u32 = 0x1000;
+pub const USAGE_VERIFY_HASH: u32 = 0x2000;
+/// Allows `raw_key_agreement`
+pub const USAGE_DERIVE: u32 = 0x4000;
+
+pub const LIFETIME_VOLATILE: u32 = 0;
+/// The key is kept in protected storage, and survives a reset
+pub const LIFETIME_PERSISTENT: u32 = 1;
+
+/// Persistent keys are given ids from 1 up to this
+pub const MAX_PERSISTENT_KEY_ID: KeyId = 0x3FFF_FFFF;
+/// Volatile keys are given ids from this range
+const VOLATILE_KEY_IDS: Range<KeyId> = 0x7FFF_0000..0x8000_0000;
+/// Persistent keys are stored under `KEY_UID_BASE | id`
+const KEY_UID_BASE: storage::Uid = storage::SECURE_UID_BASE | 1 << 32;
+
+pub const MAX_KEY_SIZE: usize = 65;
+/// Size of an uncompressed SEC1 P-256 public key
+pub const P256_PUBLIC_KEY_SIZE: usize = 65;
+pub const P256_SIGNATURE_SIZE: usize = 64;
+pub const SHA256_SIZE: usize = 32;
+pub const GCM_NONCE_SIZE: usize = 12;
+pub const GCM_TAG_SIZE: usize = 16;
+/// Largest message AES-GCM can be used on. Messages are copied into secure memory first, so they can't be changed
+/// between the tag being checked and the message being decrypted.
+pub const MAX_AEAD_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// The key id doesn't refer to a key
+    InvalidHandle,
+    /// The key's usage flags don't allow the operation
+    NotPermitted,
+    NotSupported,
+    InvalidArgument,
+    /// A signature or MAC doesn't verify, or an AEAD tag doesn't match
+    InvalidSignature,
+    BufferTooSmall,
+    /// Every key slot holds a volatile key
+    InsufficientMemory,
+    /// A persistent key with the id already exists
+    AlreadyExists,
+    /// The random source failed
+    InsufficientEntropy,
+    Storage(storage::Error),
+    /// The service hasn't been initialised, or is already handling a request
+    BadState,
+}
+
+impl From<storage::Error> for Error {
+    fn from(err: storage::Error) -> Self {
+        Error::Storage(err)
+    }
+}
+
+impl Error {
+    /// Returns the PSA status code for the error
+    pub fn status(self) -> i32 {
+        match self {
+            Error::InvalidHandle => status::INVALID_HANDLE,
+            Error::NotPermitted => status::NOT_PERMITTED,
+            Error::NotSupported => status::NOT_SUPPORTED,
+            Error::InvalidArgument => status::INVALID_ARGUMENT,
+            Error::InvalidSignature => status::INVALID_SIGNATURE,
+            Error::BufferTooSmall => status::BUFFER_TOO_SMALL,
+            Error::InsufficientMemory => status::INSUFFICIENT_MEMORY,
+            Error::AlreadyExists => status::ALREADY_EXISTS,
+            Error::InsufficientEntropy => status::INSUFFICIENT_ENTROPY,
+            Error::Storage(err) => err.status(),
+            Error::BadState => status::BAD_STATE,
+        }
+    }
+}
+
+/// Runs the service's primitives. Every method but `random` defaults to the [`software`] implementation.
+pub trait Backend {
+    /// Fills `buf` from a cryptographically secure random source
+    fn random(&self, buf: &mut [u8]) -> Result<(), Error>;
+
+    fn sha256(&self, data: &[u8]) -> [u8; 32] {
+        software::sha256(data)
+    }
+
+    fn hmac_sha256(&self, key: &[u8], data: &[u8]) -> [u8; 32] {
+        software::hmac_sha256(key, data)
+    }
+
+    /// Encrypts `buf` in place with AES-GCM, and returns the tag. `key` is 16 or 32 bytes.
+    fn aes_gcm_encrypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<[u8; 16], Error> {
+        software::aes_gcm_encrypt(key, nonce, aad, buf)
+    }
+
+    /// Decrypts `buf` in place with AES-GCM. `buf` must be left untouched if `tag` doesn't match.
+    fn aes_gcm_decrypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8; 16],
+    ) -> Result<(), Error> {
+        software::aes_gcm_decrypt(key, nonce, aad, buf, tag)
+    }
+
+    /// Signs `hash`, returning the raw r || s signature
+    fn ecdsa_p256_sign(&self, private_key: &[u8; 32], hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        software::ecdsa_p256_sign(private_key, hash)
+    }
+
+    fn ecdsa_p256_verify(&self, public_key: &[u8], hash: &[u8; 32], signature: &[u8]) -> bool {
+        crate::ecdsa::verify_prehash(public_key, hash, signature)
+    }
+
+    /// Returns the x coordinate of the shared point
+    fn ecdh_p256(&self, private_key: &[u8; 32], peer_public_key: &[u8]) -> Result<[u8; 32], Error> {
+        software::ecdh_p256(private_key, peer_public_key)
+    }
+
+    /// Returns the uncompressed SEC1 public key for `private_key`, or an error if it isn't a valid scalar
+    fn p256_public_key(&self, private_key: &[u8; 32]) -> Result<[u8; 65], Error> {
+        software::p256_public_key(private_key)
+    }
+}
+
+/// Software runs every primitive in software, with random numbers from `entropy` (e.g. the chip's TRNG)
+pub struct Software {
+    pub entropy: fn(&mut [u8]) -> Result<(), Error>,
+}
+
+impl Backend for Software {
+    fn random(&self, buf: &mut [u8]) -> Result<(), Error> {
+        (self.entropy)(buf)
+    }
+}
+
+/// Keeps persistent keys. This is implemented for the protected storage service, where keys are stored under UIDs
+/// reserved for the secure world.
+pub trait KeyStorage {
+    fn save(&self, id: KeyId, data: &[u8]) -> Result<(), Error>;
+    /// Loads the key into `buf`, returning its length
+    fn load(&self, id: KeyId, buf: &mut [u8]) -> Result<usize, Error>;
+    fn remove(&self, id: KeyId) -> Result<(), Error>;
+}
+
+impl<F: crate::flash::Flash> KeyStorage for storage::Service<'_, F> {
+    fn save(&self, id: KeyId, data: &[u8]) -> Result<(), Error> {
+        self.with(|storage| storage.set(KEY_UID_BASE | id as u64, data, 0))
+            .ok_or(Error::BadState)?
+            .map_err(Error::from)
+    }
+
+    fn load(&self, id: KeyId, buf: &mut [u8]) -> Result<usize, Error> {
+        self.with(|storage| storage.get(KEY_UID_BASE | id as u64, 0, buf))
+            .ok_or(Error::BadState)?
+            .map_err(Error::from)
+    }
+
+    fn remove(&self, id: KeyId) -> Result<(), Error> {
+        self.with(|storage| storage.remove(KEY_UID_BASE | id as u64))
+            .ok_or(Error::BadState)?
+            .map_err(Error::from)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct KeyAttributes {
+    pub key_type: u32,
+    /// Size of the key in bits. This is needed to generate AES and HMAC keys, and can be left as 0 when importing.
+    pub bits: u32,
+    pub usage: u32,
+    pub lifetime: u32,
+    /// The id of a persistent key, ignored for volatile keys
+    pub id: KeyId,
+}
+
+/// A key held in secure RAM. It isn't `Copy`, so its material is only ever borrowed from its slot, and is
+/// overwritten when the slot is freed.
+struct Key {
+    id: KeyId,
+    attributes: KeyAttributes,
+    material: [u8; MAX_KEY_SIZE],
+    len: usize,
+}
+
+impl Key {
+    fn material(&self) -> &[u8] {
+        &self.material[..self.len]
+    }
+
+    fn private_key(&self) -> Result<&[u8; 32], Error> {
+        if self.attributes.key_type != KEY_TYPE_P256_KEY_PAIR {
+            return Err(Error::InvalidArgument);
+        }
+        self.material[..32]
+            .try_into()
+            .map_err(|_| Error::InvalidArgument)
+    }
+
+    fn persistent(&self) -> bool {
+        self.attributes.lifetime == LIFETIME_PERSISTENT
+    }
+}
+
+impl Drop for Key {
+    /// Overwrites the key material, so it's gone as soon as the slot is freed or reused
+    fn drop(&mut self) {
+        for byte in self.material.iter_mut() {
+            unsafe { core::ptr::write_volatile(byte, 0) };
+        }
+    }
+}
+
+/// Header stored in front of a persistent key's material: key type, bits, and usage
+const PERSISTED_HEADER_SIZE: usize = 12;
+
+/// Returns the key's size in bytes, checking it against `data` when a key is imported
+fn key_size(attributes: &KeyAttributes, data: Option<&[u8]>) -> Result<usize, Error> {
+    if attributes.key_type == KEY_TYPE_P256_PUBLIC_KEY {
+        // public keys can only be imported, and their size is the size of the curve
+        return match data {
+            Some(data)
+                if matches!(attributes.bits, 0 | 256) && data.len() == P256_PUBLIC_KEY_SIZE =>
+            {
+                Ok(P256_PUBLIC_KEY_SIZE)
+            }
+            Some(_) => Err(Error::InvalidArgument),
+            None => Err(Error::NotSupported),
+        };
+    }
+    let bits = match (attributes.bits, data) {
+        (0, Some(data)) => data.len() as u32 * 8,
+        (0, None) => return Err(Error::InvalidArgument),
+        (bits, Some(data)) if bits != data.len() as u32 * 8 => return Err(Error::InvalidArgument),
+        (bits, _) => bits,
+    };
+    let valid = match attributes.key_type {
+        KEY_TYPE_AES => bits == 128 || bits == 256,
+        KEY_TYPE_HMAC => bits % 8 == 0 && (8..=512).contains(&bits),
+        KEY_TYPE_P256_KEY_PAIR => bits == 256,
+        _ => return Err(Error::NotSupported),
+    };
+    if !valid {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(bits as usize / 8)
+}
+
+/// Compares `a` and `b` in constant time
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Crypto holds the service's keys, and runs operations on them with a [`Backend`].
+/// Up to `N` keys are held in secure RAM at a time, persistent keys are loaded from storage as they are used.
+pub struct Crypto<'a, B, const N: usize> {
+    backend: B,
+    storage: Option<&'a (dyn KeyStorage + Sync)>,
+    keys: [Option<Key>; N],
+    next_volatile_id: KeyId,
+}
+
+impl<'a, B: Backend, const N: usize> Crypto<'a, B, N> {
+    /// Creates the service, persistent keys are only supported if `storage` is set
+    pub fn new(backend: B, storage: Option<&'a (dyn KeyStorage + Sync)>) -> Self {
+        const NO_KEY: Option<Key> = None;
+        Crypto {
+            backend,
+            storage,
+            keys: [NO_KEY; N],
+            next_volatile_id: VOLATILE_KEY_IDS.start,
+        }
+    }
+
+    fn find(&self, id: KeyId) -> Option<usize> {
+        self.keys
+            .iter()
+            .position(|k| k.as_ref().map_or(false, |k| k.id == id))
+    }
+
+    /// Returns a free key slot, evicting a persistent key if every slot is taken
+    fn free_slot(&mut self) -> Result<usize, Error> {
+        if let Some(index) = self.keys.iter().position(|k| k.is_none()) {
+            return Ok(index);
+        }
+        let index = self
+            .keys
+            .iter()
+            .position(|k| k.as_ref().map_or(false, |k| k.persistent()))
+            .ok_or(Error::InsufficientMemory)?;
+        self.evict(index);
+        Ok(index)
+    }
+
+    /// Frees the slot `index`, dropping its key overwrites the material
+    fn evict(&mut self, index: usize) {
+        self.keys[index] = None;
+    }
+
+    fn allocate_volatile_id(&mut self) -> KeyId {
+        loop {
+            let id = self.next_volatile_id;
+            self.next_volatile_id = if id + 1 == VOLATILE_KEY_IDS.end {
+                VOLATILE_KEY_IDS.start
+            } else {
+                id + 1
+            };
+            if self.find(id).is_none() {
+                return id;
+            }
+        }
+    }
+
+    /// Borrows the key `id` from its slot, loading it from storage if needed, and checks it allows `usage`.
+    /// The backend is borrowed along with it, so the key can be used without being copied out of its slot.
+    fn key(&mut self, id: KeyId, usage: u32) -> Result<(&Key, &B), Error> {
+        let index = match self.find(id) {
+            Some(index) => index,
+            None => self.load(id)?,
+        };
+        let key = self.keys[index].as_ref().ok_or(Error::InvalidHandle)?;
+        if key.attributes.usage & usage != usage {
+            return Err(Error::NotPermitted);
+        }
+        Ok((key, &self.backend))
+    }
+
+    fn load(&mut self, id: KeyId) -> Result<usize, Error> {
+        let storage = match self.storage {
+            Some(storage) if (1..=MAX_PERSISTENT_KEY_ID).contains(&id) => storage,
+            _ => return Err(Error::InvalidHandle),
+        };
+        let mut buf = [0; PERSISTED_HEADER_SIZE + MAX_KEY_SIZE];
+        let len = match storage.load(id, &mut buf) {
+            Ok(len) => len,
+            Err(Error::Storage(storage::Error::DoesNotExist)) => return Err(Error::InvalidHandle),
+            Err(err) => return Err(err),
+        };
+        if len < PERSISTED_HEADER_SIZE {
+            return Err(Error::Storage(storage::Error::DataCorrupt));
+        }
+        let word = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let attributes = KeyAttributes {
+            key_type: word(0),
+            bits: word(4),
+            usage: word(8),
+            lifetime: LIFETIME_PERSISTENT,
+            id,
+        };
+        let index = match self.free_slot() {
+            Ok(index) => index,
+            Err(err) => {
+                buf.iter_mut().for_each(|b| *b = 0);
+                return Err(err);
+            }
+        };
+        self.fill_slot(index, attributes, &buf[PERSISTED_HEADER_SIZE..len]);
+        buf.iter_mut().for_each(|b| *b = 0);
+        Ok(index)
+    }
+
+    fn insert(&mut self, mut attributes: KeyAttributes, material: &[u8]) -> Result<KeyId, Error> {
+        attributes.bits = match attributes.key_type {
+            KEY_TYPE_P256_PUBLIC_KEY => 256,
+            _ => material.len() as u32 * 8,
+        };
+        let id = match attributes.lifetime {
+            LIFETIME_VOLATILE => self.allocate_volatile_id(),
+            LIFETIME_PERSISTENT => {
+                let id = attributes.id;
+                if !(1..=MAX_PERSISTENT_KEY_ID).contains(&id) {
+                    return Err(Error::InvalidArgument);
+                }
+                let storage = self.storage.ok_or(Error::NotSupported)?;
+                if self.find(id).is_some() || self.load(id).is_ok() {
+                    return Err(Error::AlreadyExists);
+                }
+                let mut buf = [0; PERSISTED_HEADER_SIZE + MAX_KEY_SIZE];
+                buf[0..4].copy_from_slice(&attributes.key_type.to_le_bytes());
+                buf[4..8].copy_from_slice(&attributes.bits.to_le_bytes());
+                buf[8..12].copy_from_slice(&attributes.usage.to_le_bytes());
+                buf[PERSISTED_HEADER_SIZE..PERSISTED_HEADER_SIZE + material.len()]
+                    .copy_from_slice(material);
+                let result = storage.save(id, &buf[..PERSISTED_HEADER_SIZE + material.len()]);
+                buf.iter_mut().for_each(|b| *b = 0);
+                result?;
+                id
+            }
+            _ => return Err(Error::InvalidArgument),
+        };
+        attributes.id = id;
+        let index = match self.free_slot() {
+            Ok(index) => index,
+            // a persistent key can still be loaded when it's used
+            Err(_) if attributes.lifetime == LIFETIME_PERSISTENT => return Ok(id),
+            Err(err) => return Err(err),
+        };
+        self.fill_slot(index, attributes, material);
+        Ok(id)
+    }
+
+    /// Copies `material` straight into the slot `index`, so the key isn't moved through the stack
+    fn fill_slot(&mut self, index: usize, attributes: KeyAttributes, material: &[u8]) {
+        let key = self.keys[index].insert(Key {
+            id: attributes.id,
+            attributes,
+            material: [0; MAX_KEY_SIZE],
+            len: material.len(),
+        });
+        key.material[..material.len()].copy_from_slice(material);
+    }
+
+    /// Generates a random key, public keys can't be generated
+    pub fn generate_key(&mut self, attributes: &KeyAttributes) -> Result<KeyId, Error> {
+        let size = key_size(attributes, None)?;
+        let mut material = [0; MAX_KEY_SIZE];
+        if attributes.key_type == KEY_TYPE_P256_KEY_PAIR {
+            // a random 256 bit value is almost always a valid scalar
+            let mut tries = 0;
+            loop {
+                self.backend.random(&mut material[..32])?;
+                let private_key = material[..32].try_into().unwrap();
+                if self.backend.p256_public_key(private_key).is_ok() {
+                    break;
+                }
+                tries += 1;
+                if tries == 8 {
+                    return Err(Error::InsufficientEntropy);
+                }
+            }
+        } else {
+            self.backend.random(&mut material[..size])?;
+        }
+        let result = self.insert(*attributes, &material[..size]);
+        material.iter_mut().for_each(|b| *b = 0);
+        result
+    }
+
+    /// Imports a key. ECC key pairs are imported as the 32 byte private scalar, and public keys as an uncompressed
+    /// SEC1 point.
+    pub fn import_key(&mut self, attributes: &KeyAttributes, data: &[u8]) -> Result<KeyId, Error> {
+        key_size(attributes, Some(data))?;
+        let valid = match attributes.key_type {
+            KEY_TYPE_P256_KEY_PAIR => {
+                let private_key = data.try_into().map_err(|_| Error::InvalidArgument)?;
+                self.backend.p256_public_key(private_key).is_ok()
+            }
+            KEY_TYPE_P256_PUBLIC_KEY => software::p256_valid_public_key(data),
+            _ => true,
+        };
+        if !valid {
+            return Err(Error::InvalidArgument);
+        }
+        self.insert(*attributes, data)
+    }
+
+    /// Destroys the key `id`, removing it from storage if it is persistent
+    pub fn destroy_key(&mut self, id: KeyId) -> Result<(), Error> {
+        let index = self.find(id);
+        if let Some(index) = index {
+            self.evict(index);
+        }
+        match self.storage {
+            Some(storage) if (1..=MAX_PERSISTENT_KEY_ID).contains(&id) => {
+                match storage.remove(id) {
+                    Err(Error::Storage(storage::Error::DoesNotExist)) => Err(Error::InvalidHandle),
+                    result => result,
+                }
+            }
+            _ if index.is_some() => Ok(()),
+            _ => Err(Error::InvalidHandle),
+        }
+    }
+
+    /// Writes the uncompressed SEC1 public key of an ECC key to `out`, returning its length
+    pub fn export_public_key(&mut self, id: KeyId, out: &mut [u8]) -> Result<usize, Error> {
+        let (key, backend) = self.key(id, 0)?;
+        let public_key = match key.attributes.key_type {
+            KEY_TYPE_P256_KEY_PAIR => backend.p256_public_key(key.private_key()?)?,
+            KEY_TYPE_P256_PUBLIC_KEY => {
+                let mut public_key = [0; P256_PUBLIC_KEY_SIZE];
+                public_key.copy_from_slice(key.material());
+                public_key
+            }
+            _ => return Err(Error::InvalidArgument),
+        };
+        copy_out(&public_key, out)
+    }
+
+    /// Writes the SHA-256 digest of `input` to `out`, returning its length
+    pub fn hash_compute(&self, input: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+        copy_out(&self.backend.sha256(input), out)
+    }
+
+    /// Writes the HMAC-SHA-256 of `input` to `out`, returning its length
+    pub fn mac_compute(&mut self, id: KeyId, input: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+        let mac = self.mac(id, USAGE_SIGN_MESSAGE, input)?;
+        copy_out(&mac, out)
+    }
+
+    /// Checks `mac` is the HMAC-SHA-256 of `input`
+    pub fn mac_verify(&mut self, id: KeyId, input: &[u8], mac: &[u8]) -> Result<(), Error> {
+        if ct_eq(&self.mac(id, USAGE_VERIFY_MESSAGE, input)?, mac) {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+
+    fn mac(&mut self, id: KeyId, usage: u32, input: &[u8]) -> Result<[u8; 32], Error> {
+        let (key, backend) = self.key(id, usage)?;
+        if key.attributes.key_type != KEY_TYPE_HMAC {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(backend.hmac_sha256(key.material(), input))
+    }
+
+    fn aes_key(
+        &mut self,
+        id: KeyId,
+        usage: u32,
+        nonce: &[u8],
+    ) -> Result<(&Key, &B, [u8; 12]), Error> {
+        let (key, backend) = self.key(id, usage)?;
+        if key.attributes.key_type != KEY_TYPE_AES {
+            return Err(Error::InvalidArgument);
+        }
+        let nonce = nonce.try_into().map_err(|_| Error::InvalidArgument)?;
+        Ok((key, backend, nonce))
+    }
+
+    /// Encrypts `plaintext` with AES-GCM, writing the ciphertext followed by the tag to `out`, and returns the length
+    /// written
+    pub fn aead_encrypt(
+        &mut self,
+        id: KeyId,
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, Error> {
+        let (key, backend, nonce) = self.aes_key(id, USAGE_ENCRYPT, nonce)?;
+        let len = plaintext.len();
+        if len > MAX_AEAD_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+        if out.len() < len + GCM_TAG_SIZE {
+            return Err(Error::BufferTooSmall);
+        }
+        let mut buf = [0; MAX_AEAD_SIZE];
+        buf[..len].copy_from_slice(plaintext);
+        let tag = backend.aes_gcm_encrypt(key.material(), &nonce, aad, &mut buf[..len])?;
+        out[..len].copy_from_slice(&buf[..len]);
+        out[len..len + GCM_TAG_SIZE].copy_from_slice(&tag);
+        Ok(len + GCM_TAG_SIZE)
+    }
+
+    /// Decrypts `ciphertext`, which ends with the tag, with AES-GCM, and returns the length of the plaintext written
+    /// to `out`
+    pub fn aead_decrypt(
+        &mut self,
+        id: KeyId,
+        nonce: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, Error> {
+        let (key, backend, nonce) = self.aes_key(id, USAGE_DECRYPT, nonce)?;
+        let len = ciphertext
+            .len()
+            .checked_sub(GCM_TAG_SIZE)
+            .ok_or(Error::InvalidArgument)?;
+        if len > MAX_AEAD_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+        if out.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+        let mut buf = [0; MAX_AEAD_SIZE];
+        buf[..len].copy_from_slice(&ciphertext[..len]);
+        let mut tag = [0; GCM_TAG_SIZE];
+        tag.copy_from_slice(&ciphertext[len..]);
+        backend.aes_gcm_decrypt(key.material(), &nonce, aad, &mut buf[..len], &tag)?;
+        out[..len].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    /// Signs a SHA-256 `hash` with ECDSA, writing the raw r || s signature to `out`, and returns its length
+    pub fn sign_hash(&mut self, id: KeyId, hash: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+        let (key, backend) = self.key(id, USAGE_SIGN_HASH)?;
+        let hash = hash.try_into().map_err(|_| Error::InvalidArgument)?;
+        let signature = backend.ecdsa_p256_sign(key.private_key()?, hash)?;
+        copy_out(&signature, out)
+    }
+
+    /// Verifies a raw r || s ECDSA `signature` over a SHA-256 `hash`
+    pub fn verify_hash(&mut self, id: KeyId, hash: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let (key, backend) = self.key(id, USAGE_VERIFY_HASH)?;
+        let hash = hash.try_into().map_err(|_| Error::InvalidArgument)?;
+        let public_key = match key.attributes.key_type {
+            KEY_TYPE_P256_KEY_PAIR => backend.p256_public_key(key.private_key()?)?,
+            KEY_TYPE_P256_PUBLIC_KEY => {
+                let mut public_key = [0; P256_PUBLIC_KEY_SIZE];
+                public_key.copy_from_slice(key.material());
+                public_key
+            }
+            _ => return Err(Error::InvalidArgument),
+        };
+        if backend.ecdsa_p256_verify(&public_key, hash, signature) {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+
+    /// Runs ECDH with `peer_public_key`, an uncompressed SEC1 point, and writes the shared secret to `out`
+    pub fn raw_key_agreement(
+        &mut self,
+        id: KeyId,
+        peer_public_key: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, Error> {
+        let (key, backend) = self.key(id, USAGE_DERIVE)?;
+        let secret = backend.ecdh_p256(key.private_key()?, peer_public_key)?;
+        copy_out(&secret, out)
+    }
+}
+
+fn copy_out(data: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    out.get_mut(..data.len())
+        .ok_or(Error::BufferTooSmall)?
+        .copy_from_slice(data);
+    Ok(data.len())
+}
+
+/// Service shares a [`Crypto`] between the NSC veneers, a request made while another is being handled fails with
+/// [`Error::BadState`].
+pub type Service<'a, B, const N: usize> = nsc::Shared<Crypto<'a, B, N>>;
+
+/// A request from the non-secure world, passed to the veneers by pointer.
+/// Fields that aren't used by an operation are ignored, and `output` can't overlap any of the inputs.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Request {
+    /// The key used, set to the new key's id by `generate_key` and `import_key`
+    pub key: KeyId,
+    /// Attributes for `generate_key` and `import_key`
+    pub attributes: KeyAttributes,
+    /// The key data for `import_key`, the message, the hash for `sign_hash` and `verify_hash`, or the peer's public
+    /// key for `raw_key_agreement`
+    pub input: *const u8,
+    pub input_length: u32,
+    /// The additional data for AEAD, the MAC for `mac_verify`, or the signature for `verify_hash`
+    pub extra: *const u8,
+    pub extra_length: u32,
+    pub nonce: *const u8,
+    pub nonce_length: u32,
+    pub output: *mut u8,
+    pub output_size: u32,
+    /// Set to the number of bytes written to `output`
+    pub output_length: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Operation {
+    GenerateKey,
+    ImportKey,
+    DestroyKey,
+    ExportPublicKey,
+    HashCompute,
+    MacCompute,
+    MacVerify,
+    AeadEncrypt,
+    AeadDecrypt,
+    SignHash,
+    VerifyHash,
+    RawKeyAgreement,
+}
+
+/// Handles a request from the non-secure world, returning a PSA status code. This is called by the veneers
+/// generated by [`crypto_veneers`](crate::crypto_veneers).
+// the request, and the buffers it points to, are checked against the non-secure world's permissions before use
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn handle<B: Backend, const N: usize>(
+    service: &Service<'_, B, N>,
+    operation: Operation,
+    request: *mut Request,
+) -> i32 {
+    let result = match unsafe { nsc::read(request) } {
+        Some(req) => service
+            .with(|crypto| handle_request(crypto, operation, request, &req))
+            .unwrap_or(Err(Error::BadState)),
+        None => Err(Error::InvalidArgument),
+    };
+    match result {
+        Ok(()) => status::SUCCESS,
+        Err(err) => err.status(),
+    }
+}
+
+fn overlaps(a: *const u8, a_len: u32, b: *const u8, b_len: u32) -> bool {
+    let (a, b) = (a as usize, b as usize);
+    a_len != 0 && b_len != 0 && a < b + b_len as usize && b < a + a_len as usize
+}
+
+fn handle_request<B: Backend, const N: usize>(
+    crypto: &mut Crypto<'_, B, N>,
+    operation: Operation,
+    request: *mut Request,
+    req: &Request,
+) -> Result<(), Error> {
+    let output = req.output as *const u8;
+    if overlaps(output, req.output_size, req.input, req.input_length)
+        || overlaps(output, req.output_size, req.extra, req.extra_length)
+        || overlaps(output, req.output_size, req.nonce, req.nonce_length)
+    {
+        return Err(Error::InvalidArgument);
+    }
+    let input = unsafe { nsc::slice(req.input, req.input_length as usize) };
+    let extra = unsafe { nsc::slice(req.extra, req.extra_length as usize) };
+    let nonce = unsafe { nsc::slice(req.nonce, req.nonce_length as usize) };
+    let output = unsafe { nsc::slice_mut(req.output, req.output_size as usize) };
+    let (input, extra, nonce, output) = match (input, extra, nonce, output) {
+        (Some(input), Some(extra), Some(nonce), Some(output)) => (input, extra, nonce, output),
+        _ => return Err(Error::InvalidArgument),
+    };
+    let written = match operation {
+        Operation::GenerateKey | Operation::ImportKey => {
+            let id = if operation == Operation::GenerateKey {
+                crypto.generate_key(&req.attributes)?
+            } else {
+                crypto.import_key(&req.attributes, input)?
+            };
+            let key = unsafe { core::ptr::addr_of_mut!((*request).key) };
+            return unsafe { nsc::write(key, id) }.ok_or(Error::InvalidArgument);
+        }
+        Operation::DestroyKey => return crypto.destroy_key(req.key),
+        Operation::MacVerify => return crypto.mac_verify(req.key, input, extra),
+        Operation::VerifyHash => return crypto.verify_hash(req.key, input, extra),
+        Operation::ExportPublicKey => crypto.export_public_key(req.key, output)?,
+        Operation::HashCompute => crypto.hash_compute(input, output)?,
+        Operation::MacCompute => crypto.mac_compute(req.key, input, output)?,
+        Operation::AeadEncrypt => crypto.aead_encrypt(req.key, nonce, extra, input, output)?,
+        Operation::AeadDecrypt => crypto.aead_decrypt(req.key, nonce, extra, input, output)?,
+        Operation::SignHash => crypto.sign_hash(req.key, input, output)?,
+        Operation::RawKeyAgreement => crypto.raw_key_agreement(req.key, input, output)?,
+    };
+    let length = unsafe { core::ptr::addr_of_mut!((*request).output_length) };
+    unsafe { nsc::write(length, written as u32) }.ok_or(Error::InvalidArgument)
+}
+
+/// Generates the crypto service's NSC veneers for `$service`, a `static` [`crypto::Service`](crate::crypto::Service).
+/// The crate using this needs `#![feature(cmse_nonsecure_entry)]`.
+///
+/// Each veneer takes a pointer to a [`crypto::Request`](crate::crypto::Request) in non-secure memory, and returns a
+/// PSA status code. They are named after the operation, e.g. `frumsceaft_crypto_sign_hash`.
+/// ```ignore
+/// static CRYPTO: frumsceaft::crypto::Service<frumsceaft::crypto::Software, 8> = frumsceaft::crypto::Service::new();
+/// frumsceaft::crypto_veneers!(CRYPTO);
+/// ```
+#[macro_export]
+macro_rules! crypto_veneers {
+    ($service:path) => {
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_generate_key, GenerateKey);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_import_key, ImportKey);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_destroy_key, DestroyKey);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_export_public_key, ExportPublicKey);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_hash_compute, HashCompute);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_mac_compute, MacCompute);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_mac_verify, MacVerify);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_aead_encrypt, AeadEncrypt);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_aead_decrypt, AeadDecrypt);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_sign_hash, SignHash);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_verify_hash, VerifyHash);
+        $crate::crypto_veneers!(@veneer $service, frumsceaft_crypto_raw_key_agreement, RawKeyAgreement);
+    };
+    (@veneer $service:path, $name:ident, $operation:ident) => {
+        #[no_mangle]
+        #[cmse_nonsecure_entry]
+        pub extern "C" fn $name(request: *mut $crate::crypto::Request) -> i32 {
+            $crate::crypto::handle(&$service, $crate::crypto::Operation::$operation, request)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Ram, PAGE_SIZE};
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn software() -> Software {
+        Software {
+            entropy: |buf| {
+                buf.iter_mut()
+                    .enumerate()
+                    .for_each(|(i, b)| *b = i as u8 + 1);
+                Ok(())
+            },
+        }
+    }
+
+    /// Keeps persistent keys in a map, so the persisted layout can be looked at
+    #[derive(Default)]
+    struct MapStorage(Mutex<BTreeMap<KeyId, Vec<u8>>>);
+
+    impl KeyStorage for MapStorage {
+        fn save(&self, id: KeyId, data: &[u8]) -> Result<(), Error> {
+            self.0.lock().unwrap().insert(id, data.to_vec());
+            Ok(())
+        }
+
+        fn load(&self, id: KeyId, buf: &mut [u8]) -> Result<usize, Error> {
+            let keys = self.0.lock().unwrap();
+            let data = keys
+                .get(&id)
+                .ok_or(Error::Storage(storage::Error::DoesNotExist))?;
+            buf[..data.len()].copy_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn remove(&self, id: KeyId) -> Result<(), Error> {
+            match self.0.lock().unwrap().remove(&id) {
+                Some(_) => Ok(()),
+                None => Err(Error::Storage(storage::Error::DoesNotExist)),
+            }
+        }
+    }
+
+    fn attributes(key_type: u32, usage: u32) -> KeyAttributes {
+        KeyAttributes {
+            key_type,
+            bits: 0,
+            usage,
+            lifetime: LIFETIME_VOLATILE,
+            id: 0,
+        }
+    }
+
+    /// Test cases 2, 4 and 14 from the GCM specification
+    #[test]
+    fn aes_gcm_known_answers() {
+        // key, nonce, aad, plaintext, ciphertext, tag
+        let cases = [
+            (
+                "00000000000000000000000000000000",
+                "000000000000000000000000",
+                "",
+                "00000000000000000000000000000000",
+                "0388dace60b6a392f328c2b971b2fe78",
+                "ab6e47d42cec13bdf53a67b21257bddf",
+            ),
+            (
+                "feffe9928665731c6d6a8f9467308308",
+                "cafebabefacedbaddecaf888",
+                "feedfacedeadbeeffeedfacedeadbeefabaddad2",
+                concat!(
+                    "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72",
+                    "1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+                ),
+                concat!(
+                    "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e",
+                    "21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091",
+                ),
+                "5bc94fbc3221a5db94fae95ae7121a47",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "000000000000000000000000",
+                "",
+                "00000000000000000000000000000000",
+                "cea7403d4d606b6e074ec5d3baf39d18",
+                "d0d1c8a799996bf0265b98b5d48ab919",
+            ),
+        ];
+        for (key, nonce, aad, plaintext, ciphertext, tag) in cases {
+            let (key, nonce, aad) = (hex(key), hex(nonce), hex(aad));
+            let mut crypto = Crypto::<_, 2>::new(software(), None);
+            let id = crypto
+                .import_key(
+                    &attributes(KEY_TYPE_AES, USAGE_ENCRYPT | USAGE_DECRYPT),
+                    &key,
+                )
+                .unwrap();
+            let mut expected = hex(ciphertext);
+            expected.extend(hex(tag));
+            let mut out = vec![0; expected.len()];
+            assert_eq!(
+                crypto.aead_encrypt(id, &nonce, &aad, &hex(plaintext), &mut out),
+                Ok(expected.len())
+            );
+            assert_eq!(out, expected);
+
+            let mut decrypted = vec![0; expected.len() - GCM_TAG_SIZE];
+            assert_eq!(
+                crypto.aead_decrypt(id, &nonce, &aad, &expected, &mut decrypted),
+                Ok(decrypted.len())
+            );
+            assert_eq!(decrypted, hex(plaintext));
+            // a changed tag is rejected, and nothing is written out
+            *expected.last_mut().unwrap() ^= 1;
+            let mut untouched = vec![0xA5; decrypted.len()];
+            assert_eq!(
+                crypto.aead_decrypt(id, &nonce, &aad, &expected, &mut untouched),
+                Err(Error::InvalidSignature)
+            );
+            assert!(untouched.iter().all(|b| *b == 0xA5));
+        }
+    }
+
+    /// The P-256 SHA-256 "sample" case from RFC 6979 A.2.5, signing is deterministic so the signature is known
+    #[test]
+    fn ecdsa_sign_verify() {
+        let private_key = hex("c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721");
+        let mut public_key = hex("04");
+        public_key.extend(hex(
+            "60fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb6",
+        ));
+        public_key.extend(hex(
+            "7903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299",
+        ));
+        let mut signature = hex("efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716");
+        signature.extend(hex(
+            "f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8",
+        ));
+
+        let mut crypto = Crypto::<_, 4>::new(software(), None);
+        let key_pair = crypto
+            .import_key(
+                &attributes(KEY_TYPE_P256_KEY_PAIR, USAGE_SIGN_HASH | USAGE_VERIFY_HASH),
+                &private_key,
+            )
+            .unwrap();
+        let mut exported = [0; P256_PUBLIC_KEY_SIZE];
+        assert_eq!(
+            crypto.export_public_key(key_pair, &mut exported),
+            Ok(P256_PUBLIC_KEY_SIZE)
+        );
+        assert_eq!(exported[..], public_key[..]);
+
+        let mut hash = [0; SHA256_SIZE];
+        crypto.hash_compute(b"sample", &mut hash).unwrap();
+        let mut signed = [0; P256_SIGNATURE_SIZE];
+        assert_eq!(
+            crypto.sign_hash(key_pair, &hash, &mut signed),
+            Ok(P256_SIGNATURE_SIZE)
+        );
+        assert_eq!(signed[..], signature[..]);
+
+        let public = crypto
+            .import_key(
+                &attributes(KEY_TYPE_P256_PUBLIC_KEY, USAGE_VERIFY_HASH),
+                &public_key,
+            )
+            .unwrap();
+        for id in [key_pair, public] {
+            assert_eq!(crypto.verify_hash(id, &hash, &signed), Ok(()));
+            let mut other = hash;
+            other[0] ^= 1;
+            assert_eq!(
+                crypto.verify_hash(id, &other, &signed),
+                Err(Error::InvalidSignature)
+            );
+        }
+        // public keys can't sign
+        assert_eq!(
+            crypto.sign_hash(public, &hash, &mut signed),
+            Err(Error::NotPermitted)
+        );
+    }
+
+    /// Persistent keys are kept in protected storage under UIDs the non-secure world can't reach, with their type,
+    /// size, and usage in front of the material
+    #[test]
+    fn key_storage_layout() {
+        let service = storage::Service::new();
+        let flash = Ram::<4>::new(4);
+        assert!(service
+            .init(storage::Storage::new(flash, 0..4 * PAGE_SIZE, None).unwrap())
+            .is_ok());
+        KeyStorage::save(&service, 7, &[1, 2, 3]).unwrap();
+        let uid = storage::SECURE_UID_BASE | 1 << 32 | 7;
+        let mut data = [0; 3];
+        assert_eq!(
+            service.with(|storage| storage.get(uid, 0, &mut data)),
+            Some(Ok(3))
+        );
+        assert_eq!(data, [1, 2, 3]);
+        assert_eq!(KeyStorage::load(&service, 7, &mut data), Ok(3));
+        assert_eq!(KeyStorage::remove(&service, 7), Ok(()));
+        assert_eq!(
+            service.with(|storage| storage.get(uid, 0, &mut data)),
+            Some(Err(storage::Error::DoesNotExist))
+        );
+
+        let keys = MapStorage::default();
+        let mut crypto = Crypto::<_, 2>::new(software(), Some(&keys));
+        let key = [0x42; 16];
+        let attributes = KeyAttributes {
+            lifetime: LIFETIME_PERSISTENT,
+            id: 9,
+            ..attributes(KEY_TYPE_AES, USAGE_ENCRYPT)
+        };
+        assert_eq!(crypto.import_key(&attributes, &key), Ok(9));
+        let mut persisted = Vec::new();
+        persisted.extend(KEY_TYPE_AES.to_le_bytes());
+        persisted.extend(128u32.to_le_bytes());
+        persisted.extend(USAGE_ENCRYPT.to_le_bytes());
+        persisted.extend(key);
+        assert_eq!(keys.0.lock().unwrap()[&9], persisted);
+
+        // the key is loaded back from storage by a fresh service
+        let mut crypto = Crypto::<_, 2>::new(software(), Some(&keys));
+        let mut out = [0; 16 + GCM_TAG_SIZE];
+        assert_eq!(
+            crypto.aead_encrypt(9, &[0; 12], &[], &[0; 16], &mut out),
+            Ok(32)
+        );
+        assert_eq!(crypto.destroy_key(9), Ok(()));
+        assert!(keys.0.lock().unwrap().is_empty());
+        assert_eq!(crypto.destroy_key(9), Err(Error::InvalidHandle));
+    }
+
+    #[test]
+    fn destroy_zeroizes() {
+        let mut key = core::mem::MaybeUninit::new(Key {
+            id: 1,
+            attributes: attributes(KEY_TYPE_HMAC, USAGE_SIGN_MESSAGE),
+            material: [0x5C; MAX_KEY_SIZE],
+            len: 32,
+        });
+        // the memory is still there after the key is dropped, only the material is gone
+        let material = unsafe {
+            key.as_mut_ptr().drop_in_place();
+            core::ptr::addr_of!((*key.as_ptr()).material).read()
+        };
+        assert_eq!(material, [0; MAX_KEY_SIZE]);
+
+        let mut crypto = Crypto::<_, 2>::new(software(), None);
+        let id = crypto
+            .import_key(&attributes(KEY_TYPE_HMAC, USAGE_SIGN_MESSAGE), &[0x5C; 32])
+            .unwrap();
+        assert_eq!(crypto.destroy_key(id), Ok(()));
+        assert!(crypto.keys.iter().all(|k| k.is_none()));
+        let mut mac = [0; SHA256_SIZE];
+        assert_eq!(
+            crypto.mac_compute(id, b"message", &mut mac),
+            Err(Error::InvalidHandle)
+        );
+    }
+
+    #[test]
+    fn handle_rejects_overlapping_output() {
+        let service: Service<'_, Software, 2> = Service::new();
+        assert!(service.init(Crypto::new(software(), None)).is_ok());
+        let mut buf = [0u8; 64];
+        let base = buf.as_mut_ptr();
+        let request = |input: usize, output: usize| Request {
+            key: 0,
+            attributes: attributes(0, 0),
+            input: unsafe { base.add(input) },
+            input_length: 16,
+            extra: core::ptr::null(),
+            extra_length: 0,
+            nonce: core::ptr::null(),
+            nonce_length: 0,
+            output: unsafe { base.add(output) },
+            output_size: 32,
+            output_length: 0,
+        };
+
+        for output in [0, 8, 16] {
+            let mut req = request(16, output);
+            assert_eq!(
+                handle(&service, Operation::HashCompute, &mut req),
+                status::INVALID_ARGUMENT
+            );
+            assert_eq!(req.output_length, 0);
+        }
+        // the output can sit right up against the input
+        let mut req = request(0, 16);
+        assert_eq!(
+            handle(&service, Operation::HashCompute, &mut req),
+            status::SUCCESS
+        );
+        assert_eq!(req.output_length, 32);
+        assert_eq!(buf[16..48], software::sha256(&[0; 16]));
+    }
+}
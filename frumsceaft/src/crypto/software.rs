@@ -0,0 +1,92 @@
+//! Software implementations of the crypto service's primitives, using the RustCrypto crates. These are the defaults
+//! for [`Backend`](super::Backend)'s methods.
+use super::{Error, P256_PUBLIC_KEY_SIZE};
+use aes_gcm::aead::{AeadInPlace, NewAead};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use ecdsa_core::hazmat::{rfc6979_generate_k, SignPrimitive};
+use hmac::{Hmac, Mac, NewMac};
+use p256::elliptic_curve::ecdh::diffie_hellman;
+use p256::elliptic_curve::ops::Reduce;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{NistP256, PublicKey, Scalar, SecretKey};
+use sha2::{Digest, Sha256};
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+pub fn aes_gcm_encrypt(
+    key: &[u8],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    buf: &mut [u8],
+) -> Result<[u8; 16], Error> {
+    let tag = match key.len() {
+        16 => Aes128Gcm::new(key.into()).encrypt_in_place_detached(nonce.into(), aad, buf),
+        32 => Aes256Gcm::new(key.into()).encrypt_in_place_detached(nonce.into(), aad, buf),
+        _ => return Err(Error::InvalidArgument),
+    };
+    Ok(tag.map_err(|_| Error::InvalidArgument)?.into())
+}
+
+/// Decrypts `buf` in place, it is left untouched if the tag doesn't match
+pub fn aes_gcm_decrypt(
+    key: &[u8],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    buf: &mut [u8],
+    tag: &[u8; 16],
+) -> Result<(), Error> {
+    let result = match key.len() {
+        16 => {
+            Aes128Gcm::new(key.into()).decrypt_in_place_detached(nonce.into(), aad, buf, tag.into())
+        }
+        32 => {
+            Aes256Gcm::new(key.into()).decrypt_in_place_detached(nonce.into(), aad, buf, tag.into())
+        }
+        _ => return Err(Error::InvalidArgument),
+    };
+    result.map_err(|_| Error::InvalidSignature)
+}
+
+/// Signs `hash` with a deterministic nonce (RFC 6979), returning the raw r || s signature
+pub fn ecdsa_p256_sign(private_key: &[u8; 32], hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+    let secret = SecretKey::from_be_bytes(private_key).map_err(|_| Error::InvalidArgument)?;
+    let x = secret.to_nonzero_scalar();
+    let z = <Scalar as Reduce<_>>::from_be_bytes_reduced((*hash).into());
+    let k = rfc6979_generate_k::<NistP256, Sha256>(&x, &z, &[]);
+    let (signature, _) = x
+        .try_sign_prehashed(**k, z)
+        .map_err(|_| Error::InvalidArgument)?;
+    let mut raw = [0; 64];
+    raw.copy_from_slice(signature.as_ref());
+    Ok(raw)
+}
+
+pub fn ecdh_p256(private_key: &[u8; 32], peer_public_key: &[u8]) -> Result<[u8; 32], Error> {
+    let secret = SecretKey::from_be_bytes(private_key).map_err(|_| Error::InvalidArgument)?;
+    let peer = PublicKey::from_sec1_bytes(peer_public_key).map_err(|_| Error::InvalidArgument)?;
+    let shared = diffie_hellman(secret.to_nonzero_scalar(), peer.as_affine());
+    Ok((*shared.as_bytes()).into())
+}
+
+/// Returns the uncompressed SEC1 encoding of the public key for `private_key`
+pub fn p256_public_key(private_key: &[u8; 32]) -> Result<[u8; P256_PUBLIC_KEY_SIZE], Error> {
+    let secret = SecretKey::from_be_bytes(private_key).map_err(|_| Error::InvalidArgument)?;
+    let point = secret.public_key().to_encoded_point(false);
+    let mut public_key = [0; P256_PUBLIC_KEY_SIZE];
+    public_key.copy_from_slice(point.as_bytes());
+    Ok(public_key)
+}
+
+/// Returns true if `public_key` is a SEC1 encoded point on the curve
+pub fn p256_valid_public_key(public_key: &[u8]) -> bool {
+    PublicKey::from_sec1_bytes(public_key).is_ok()
+}
@@ -0,0 +1,22 @@
+//! ECDSA P-256 signature verification, shared by the chip crypto modules and image verification
+use ecdsa_core::hazmat::VerifyPrimitive;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::elliptic_curve::ops::Reduce;
+use p256::{FieldBytes, PublicKey, Scalar, U256};
+
+/// Verifies a raw (r || s) `signature` over a SHA-256 `digest`, `public_key` is a SEC1 encoded point
+pub fn verify_prehash(public_key: &[u8], digest: &[u8; 32], signature: &[u8]) -> bool {
+    let key = match VerifyingKey::from_sec1_bytes(public_key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let signature = match Signature::try_from(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let z = <Scalar as Reduce<U256>>::from_be_bytes_reduced(FieldBytes::from(*digest));
+    PublicKey::from(&key)
+        .as_affine()
+        .verify_prehashed(z, &signature)
+        .is_ok()
+}
@@ -0,0 +1,25 @@
+//! Flash access for the secure services that keep state in secure flash.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// The access falls outside the flash
+    OutOfBounds,
+    /// A write isn't aligned to, or a multiple of, [`Flash::WRITE_SIZE`], or an erase isn't aligned to
+    /// [`Flash::ERASE_SIZE`]
+    Unaligned,
+    /// The flash controller reported an error
+    Controller,
+}
+
+/// Flash memory that can be read, written, and erased by the secure world. Addresses are absolute.
+pub trait Flash {
+    /// Size of the smallest erasable unit, in bytes
+    const ERASE_SIZE: u32;
+    /// Writes must be aligned to, and a multiple of, this many bytes. This is a power of two no larger than 16.
+    const WRITE_SIZE: u32;
+    fn read(&self, address: u32, buf: &mut [u8]) -> Result<(), Error>;
+    /// Programs `data` at `address`, which must have been erased
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Error>;
+    /// Erases the page starting at `address`
+    fn erase(&mut self, address: u32) -> Result<(), Error>;
+}
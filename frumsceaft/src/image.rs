@@ -0,0 +1,360 @@
+//! Signed firmware images, in the format used by MCUboot and produced by its `imgtool`.
+//!
+//! An image starts with a 32 byte [`Header`], padded to `header_size` so the vector table that follows is aligned.
+//! After the image come the TLVs (type-length-value entries): an optional protected area, which is covered by the
+//! hash, then the unprotected area holding the SHA-256 of the header, image and protected TLVs, and an ECDSA P-256
+//! signature over that hash. Images are signed with e.g.
+//! `imgtool sign --key key.pem --header-size 0x200 --pad-header --align 4 --version 1.0.0 --slot-size <size>`.
+//! Images can also be encrypted, see [`encrypted`].
+use crate::flash::{self, Flash};
+use core::ops::Range;
+use sha2::{Digest, Sha256};
+
+pub mod encrypted;
+
+pub const IMAGE_MAGIC: u32 = 0x96f3_b83d;
+/// Size of the header fields, `header_size` can be larger to align the vector table
+pub const HEADER_SIZE: u32 = 32;
+const TLV_INFO_MAGIC: u16 = 0x6907;
+const TLV_PROTECTED_INFO_MAGIC: u16 = 0x6908;
+const TLV_INFO_SIZE: u32 = 4;
+
+// TLV types
+pub const TLV_SHA256: u16 = 0x10;
+/// ECDSA P-256 signature, DER encoded by `imgtool` though a raw r || s signature is also accepted
+pub const TLV_ECDSA_SIG: u16 = 0x22;
+
+/// Size of the buffer images are read through
+const CHUNK: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    Flash(flash::Error),
+    /// The slot doesn't start with an image header
+    NoImage,
+    /// The header or TLVs are inconsistent, or the image doesn't fit in its slot
+    Malformed,
+    /// The image has no SHA-256 TLV, or the hash doesn't match
+    HashMismatch,
+    /// The image has no signature, or the signature doesn't verify
+    InvalidSignature,
+    /// The image is encrypted and its content key couldn't be unwrapped
+    Decryption,
+}
+
+impl From<flash::Error> for Error {
+    fn from(err: flash::Error) -> Self {
+        Error::Flash(err)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub revision: u16,
+    pub build: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct Header {
+    pub load_address: u32,
+    /// Offset of the image from the start of the header
+    pub header_size: u16,
+    /// Size of the protected TLV area, including its info header
+    pub protected_tlv_size: u16,
+    pub image_size: u32,
+    pub flags: u32,
+    pub version: Version,
+}
+
+impl Header {
+    pub fn parse(bytes: &[u8; HEADER_SIZE as usize]) -> Result<Self, Error> {
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if u32_at(0) != IMAGE_MAGIC {
+            return Err(Error::NoImage);
+        }
+        let header = Header {
+            load_address: u32_at(4),
+            header_size: u16_at(8),
+            protected_tlv_size: u16_at(10),
+            image_size: u32_at(12),
+            flags: u32_at(16),
+            version: Version {
+                major: bytes[20],
+                minor: bytes[21],
+                revision: u16_at(22),
+                build: u32_at(24),
+            },
+        };
+        if (header.header_size as u32) < HEADER_SIZE {
+            return Err(Error::Malformed);
+        }
+        Ok(header)
+    }
+
+    /// Reads the header at the start of `slot`
+    pub fn read<F: Flash>(flash: &F, slot: &Range<u32>) -> Result<Self, Error> {
+        let mut bytes = [0; HEADER_SIZE as usize];
+        flash.read(slot.start, &mut bytes)?;
+        Self::parse(&bytes)
+    }
+
+    /// Offset of the TLVs from the start of the header
+    pub fn tlv_offset(&self) -> u32 {
+        (self.header_size as u32).saturating_add(self.image_size)
+    }
+}
+
+/// Reads a TLV area's info header, returning the area's size
+fn tlv_area<F: Flash>(flash: &F, address: u32, magic: u16) -> Result<u32, Error> {
+    let mut info = [0; TLV_INFO_SIZE as usize];
+    flash.read(address, &mut info)?;
+    if u16::from_le_bytes([info[0], info[1]]) != magic {
+        return Err(Error::Malformed);
+    }
+    let size = u16::from_le_bytes([info[2], info[3]]) as u32;
+    if size < TLV_INFO_SIZE {
+        return Err(Error::Malformed);
+    }
+    Ok(size)
+}
+
+/// Returns the size of the image in `slot`, from the start of the header to the end of the TLVs
+pub fn total_size<F: Flash>(flash: &F, slot: &Range<u32>, header: &Header) -> Result<u32, Error> {
+    let tlvs = slot
+        .start
+        .checked_add(header.tlv_offset())
+        .ok_or(Error::Malformed)?;
+    let protected = header.protected_tlv_size as u32;
+    if protected != 0 && tlv_area(flash, tlvs, TLV_PROTECTED_INFO_MAGIC)? != protected {
+        return Err(Error::Malformed);
+    }
+    if tlvs
+        .checked_add(protected + TLV_INFO_SIZE)
+        .map_or(true, |end| end > slot.end)
+    {
+        return Err(Error::Malformed);
+    }
+    let unprotected = tlv_area(flash, tlvs + protected, TLV_INFO_MAGIC)?;
+    let size = header.tlv_offset() + protected + unprotected;
+    if size > slot.end - slot.start {
+        return Err(Error::Malformed);
+    }
+    Ok(size)
+}
+
+/// Finds the first TLV of type `kind`, in either area, and copies its value into `buf`. Returns the value's length,
+/// or `None` if there is no such TLV.
+pub fn find_tlv<F: Flash>(
+    flash: &F,
+    slot: &Range<u32>,
+    header: &Header,
+    kind: u16,
+    buf: &mut [u8],
+) -> Result<Option<usize>, Error> {
+    let end = slot.start + total_size(flash, slot, header)?;
+    let mut address = slot.start + header.tlv_offset();
+    let mut area_end = address;
+    while address < end {
+        if address == area_end {
+            // the start of the protected or unprotected area
+            let mut info = [0; TLV_INFO_SIZE as usize];
+            flash.read(address, &mut info)?;
+            area_end = address + u16::from_le_bytes([info[2], info[3]]) as u32;
+            address += TLV_INFO_SIZE;
+            continue;
+        }
+        let mut tlv = [0; 4];
+        flash.read(address, &mut tlv)?;
+        let tlv_kind = u16::from_le_bytes([tlv[0], tlv[1]]);
+        let len = u16::from_le_bytes([tlv[2], tlv[3]]) as usize;
+        let value = address + 4;
+        if value + len as u32 > area_end {
+            return Err(Error::Malformed);
+        }
+        if tlv_kind == kind {
+            let buf = buf.get_mut(..len).ok_or(Error::Malformed)?;
+            flash.read(value, buf)?;
+            return Ok(Some(len));
+        }
+        address = value + len as u32;
+    }
+    Ok(None)
+}
+
+/// Computes the SHA-256 of the header, image, and protected TLVs, which is what the image is signed over
+pub fn hash<F: Flash>(flash: &F, slot: &Range<u32>, header: &Header) -> Result<[u8; 32], Error> {
+    hash_with(flash, slot, header, |_, _| {})
+}
+
+/// Computes the image's hash, passing each chunk through `map` with its offset in the slot before hashing it
+fn hash_with<F: Flash>(
+    flash: &F,
+    slot: &Range<u32>,
+    header: &Header,
+    mut map: impl FnMut(u32, &mut [u8]),
+) -> Result<[u8; 32], Error> {
+    let len = header.tlv_offset() + header.protected_tlv_size as u32;
+    let mut hasher = Sha256::new();
+    let mut buf = [0; CHUNK];
+    let mut offset = 0;
+    while offset < len {
+        let n = (len - offset).min(CHUNK as u32) as usize;
+        flash.read(slot.start + offset, &mut buf[..n])?;
+        map(offset, &mut buf[..n]);
+        hasher.update(&buf[..n]);
+        offset += n as u32;
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Verifies the image in `slot` against `public_key`, a SEC1 encoded P-256 point, and returns its header.
+///
+/// The image's hash is checked against its SHA-256 TLV, and its signature TLV against the hash.
+pub fn verify<F: Flash>(flash: &F, slot: &Range<u32>, public_key: &[u8]) -> Result<Header, Error> {
+    let header = Header::read(flash, slot)?;
+    total_size(flash, slot, &header)?;
+    let digest = hash(flash, slot, &header)?;
+    verify_digest(flash, slot, &header, &digest, public_key)?;
+    Ok(header)
+}
+
+/// Checks `digest` against the image's SHA-256 TLV, and its signature TLV against `digest`
+fn verify_digest<F: Flash>(
+    flash: &F,
+    slot: &Range<u32>,
+    header: &Header,
+    digest: &[u8; 32],
+    public_key: &[u8],
+) -> Result<(), Error> {
+    let mut expected = [0; 32];
+    match find_tlv(flash, slot, header, TLV_SHA256, &mut expected) {
+        Ok(Some(32)) => {}
+        Ok(_) | Err(Error::Malformed) => return Err(Error::HashMismatch),
+        Err(err) => return Err(err),
+    }
+    if digest
+        .iter()
+        .zip(&expected)
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        != 0
+    {
+        return Err(Error::HashMismatch);
+    }
+    let mut signature = [0; 72];
+    let len = match find_tlv(flash, slot, header, TLV_ECDSA_SIG, &mut signature) {
+        Ok(Some(len)) => len,
+        Ok(None) | Err(Error::Malformed) => return Err(Error::InvalidSignature),
+        Err(err) => return Err(err),
+    };
+    let signature = raw_signature(&signature[..len]).ok_or(Error::InvalidSignature)?;
+    if !crate::ecdsa::verify_prehash(public_key, digest, &signature) {
+        return Err(Error::InvalidSignature);
+    }
+    Ok(())
+}
+
+/// Returns the raw r || s form of a signature, converting it from DER if needed
+fn raw_signature(signature: &[u8]) -> Option<[u8; 64]> {
+    let mut raw = [0; 64];
+    if signature.len() == 64 {
+        raw.copy_from_slice(signature);
+        return Some(raw);
+    }
+    // SEQUENCE { INTEGER r, INTEGER s }
+    let (sequence, rest) = der_element(signature, 0x30)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let (r, rest) = der_element(sequence, 0x02)?;
+    let (s, rest) = der_element(rest, 0x02)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    der_integer(r, &mut raw[..32])?;
+    der_integer(s, &mut raw[32..])?;
+    Some(raw)
+}
+
+/// Splits off a DER element with a short form length, returning its contents and the rest of `data`
+fn der_element(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match data {
+        [t, len, rest @ ..] if *t == tag && *len < 0x80 && rest.len() >= *len as usize => {
+            Some(rest.split_at(*len as usize))
+        }
+        _ => None,
+    }
+}
+
+/// Copies a positive DER integer into `out`, right aligned
+fn der_integer(mut int: &[u8], out: &mut [u8]) -> Option<()> {
+    while let [0, rest @ ..] = int {
+        int = rest;
+    }
+    let start = out.len().checked_sub(int.len())?;
+    out[start..].copy_from_slice(int);
+    Some(())
+}
+
+/// Signed images for host tests
+#[cfg(test)]
+pub(crate) mod test_image {
+    use super::*;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[5; 32]).unwrap()
+    }
+
+    /// The SEC1 encoded public key the images are signed for
+    pub(crate) fn public_key() -> Vec<u8> {
+        VerifyingKey::from(&signing_key())
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// Builds an image with `len` bytes of code and version `major`.0.7, with a protected TLV area if `protected`
+    /// is set. The signature is DER encoded like `imgtool`'s, or a raw one if `raw_signature` is set.
+    pub(crate) fn build(major: u8, len: usize, protected: bool, raw_signature: bool) -> Vec<u8> {
+        let header_size = 64u16;
+        let mut image = vec![0; header_size as usize];
+        let protected_tlvs: &[u8] = if protected {
+            &[0x08, 0x69, 12, 0, 0x50, 0, 4, 0, 1, 2, 3, 4]
+        } else {
+            &[]
+        };
+        image[0..4].copy_from_slice(&IMAGE_MAGIC.to_le_bytes());
+        image[8..10].copy_from_slice(&header_size.to_le_bytes());
+        image[10..12].copy_from_slice(&(protected_tlvs.len() as u16).to_le_bytes());
+        image[12..16].copy_from_slice(&(len as u32).to_le_bytes());
+        image[20] = major;
+        image[22] = 7;
+        image.extend((0..len).map(|i| (i as u8).wrapping_mul(major).wrapping_add(3)));
+        image.extend(protected_tlvs);
+
+        let hash = Sha256::digest(&image);
+        let signature: Signature = signing_key().sign(&image);
+        let signature = if raw_signature {
+            signature.as_ref().to_vec()
+        } else {
+            signature.to_der().as_bytes().to_vec()
+        };
+        let mut tlvs = vec![];
+        tlvs.extend(TLV_SHA256.to_le_bytes());
+        tlvs.extend(32u16.to_le_bytes());
+        tlvs.extend(hash);
+        tlvs.extend(TLV_ECDSA_SIG.to_le_bytes());
+        tlvs.extend((signature.len() as u16).to_le_bytes());
+        tlvs.extend(signature);
+        image.extend(TLV_INFO_MAGIC.to_le_bytes());
+        image.extend((tlvs.len() as u16 + TLV_INFO_SIZE as u16).to_le_bytes());
+        image.extend(tlvs);
+        image
+    }
+}
@@ -0,0 +1,361 @@
+//! Encrypted images, in the format produced by `imgtool sign --encrypt`.
+//!
+//! The image's payload (but not its header or TLVs) is encrypted with AES-CTR under a random content key, starting
+//! from a zero counter block. The content key is wrapped for the device in a TLV, either with ECIES-P256 or with
+//! AES key wrap (RFC 3394), and the hash and signature are over the plaintext. Images are decrypted by the update
+//! service as they are installed, so the primary slot holds the plaintext image.
+use super::{find_tlv, hash_with, verify_digest, Error, Header};
+use crate::flash::Flash;
+use crate::storage;
+use aes::{Aes128, Aes256, BlockDecrypt, BlockEncrypt, NewBlockCipher};
+use core::ops::Range;
+use hmac::{Hmac, Mac, NewMac};
+use p256::elliptic_curve::ecdh::diffie_hellman;
+use p256::{PublicKey, SecretKey};
+use sha2::Sha256;
+
+/// The payload is encrypted with AES-128-CTR
+pub const FLAG_ENCRYPTED_AES128: u32 = 0x04;
+/// The payload is encrypted with AES-256-CTR
+pub const FLAG_ENCRYPTED_AES256: u32 = 0x08;
+
+/// Content key wrapped with AES key wrap
+pub const TLV_ENC_KW: u16 = 0x31;
+/// Content key wrapped with ECIES-P256: the ephemeral public key, an HMAC-SHA-256 tag, then the encrypted key
+pub const TLV_ENC_EC256: u16 = 0x32;
+
+/// Largest wrapped key, an ECIES-P256 wrapped AES-256 key
+pub const MAX_WRAPPED_KEY_SIZE: usize = 65 + 32 + 32;
+const ECIES_INFO: &[u8] = b"MCUBoot_ECIES_v1";
+const KW_IV: [u8; 8] = [0xA6; 8];
+
+/// Unwraps the content keys of encrypted images with a key that stays in the secure world
+pub trait KeyUnwrap {
+    /// Type of the TLV the content key is wrapped in
+    fn tlv(&self) -> u16;
+    /// Unwraps the content key from `wrapped`, the TLV's value, into `key`, which is 16 or 32 bytes
+    fn unwrap(&self, wrapped: &[u8], key: &mut [u8]) -> Result<(), Error>;
+}
+
+/// Unwraps ECIES-P256 wrapped keys with a P-256 private key
+pub struct Ecies {
+    private_key: [u8; 32],
+}
+
+impl Ecies {
+    pub fn new(private_key: [u8; 32]) -> Self {
+        Ecies { private_key }
+    }
+
+    /// Loads the private key from protected storage, it should be stored under a [`storage::SECURE_UID_BASE`] UID
+    /// so the non-secure world can't read it
+    pub fn from_storage<F: Flash>(
+        storage: &storage::Service<'_, F>,
+        uid: storage::Uid,
+    ) -> Result<Self, storage::Error> {
+        let mut private_key = [0; 32];
+        let len = storage
+            .with(|storage| storage.get(uid, 0, &mut private_key))
+            .ok_or(storage::Error::BadState)??;
+        if len != private_key.len() {
+            return Err(storage::Error::DataCorrupt);
+        }
+        Ok(Ecies { private_key })
+    }
+}
+
+impl KeyUnwrap for Ecies {
+    fn tlv(&self) -> u16 {
+        TLV_ENC_EC256
+    }
+
+    fn unwrap(&self, wrapped: &[u8], key: &mut [u8]) -> Result<(), Error> {
+        if wrapped.len() != 65 + 32 + key.len() {
+            return Err(Error::Decryption);
+        }
+        let (ephemeral, rest) = wrapped.split_at(65);
+        let (tag, encrypted_key) = rest.split_at(32);
+        let secret = SecretKey::from_be_bytes(&self.private_key).map_err(|_| Error::Decryption)?;
+        let ephemeral = PublicKey::from_sec1_bytes(ephemeral).map_err(|_| Error::Decryption)?;
+        let shared = diffie_hellman(secret.to_nonzero_scalar(), ephemeral.as_affine());
+        // the first part of the derived key encrypts the content key, the rest authenticates it
+        let mut derived = [0; 64];
+        let derived = &mut derived[..key.len() + 32];
+        hkdf_sha256(shared.as_bytes(), ECIES_INFO, derived);
+        let mut mac = Hmac::<Sha256>::new_from_slice(&derived[key.len()..]).unwrap();
+        mac.update(encrypted_key);
+        mac.verify(tag).map_err(|_| Error::Decryption)?;
+        key.copy_from_slice(encrypted_key);
+        apply_keystream(&derived[..key.len()], 0, key);
+        derived.iter_mut().for_each(|b| *b = 0);
+        Ok(())
+    }
+}
+
+impl Drop for Ecies {
+    fn drop(&mut self) {
+        self.private_key.iter_mut().for_each(|b| *b = 0);
+    }
+}
+
+/// Unwraps AES key wrapped keys with a 128 bit key encryption key
+pub struct AesKeyWrap {
+    kek: [u8; 16],
+}
+
+impl AesKeyWrap {
+    pub fn new(kek: [u8; 16]) -> Self {
+        AesKeyWrap { kek }
+    }
+
+    /// Loads the key encryption key from protected storage, it should be stored under a
+    /// [`storage::SECURE_UID_BASE`] UID so the non-secure world can't read it
+    pub fn from_storage<F: Flash>(
+        storage: &storage::Service<'_, F>,
+        uid: storage::Uid,
+    ) -> Result<Self, storage::Error> {
+        let mut kek = [0; 16];
+        let len = storage
+            .with(|storage| storage.get(uid, 0, &mut kek))
+            .ok_or(storage::Error::BadState)??;
+        if len != kek.len() {
+            return Err(storage::Error::DataCorrupt);
+        }
+        Ok(AesKeyWrap { kek })
+    }
+}
+
+impl KeyUnwrap for AesKeyWrap {
+    fn tlv(&self) -> u16 {
+        TLV_ENC_KW
+    }
+
+    fn unwrap(&self, wrapped: &[u8], key: &mut [u8]) -> Result<(), Error> {
+        let cipher = Aes128::new(&self.kek.into());
+        aes_kw_unwrap(|block| cipher.decrypt_block(block.into()), wrapped, key)
+    }
+}
+
+impl Drop for AesKeyWrap {
+    fn drop(&mut self) {
+        self.kek.iter_mut().for_each(|b| *b = 0);
+    }
+}
+
+/// Unwraps a key with AES key wrap (RFC 3394), `decrypt` runs the AES inverse cipher on a block with the key
+/// encryption key. `wrapped` is 8 bytes longer than `key`.
+pub fn aes_kw_unwrap(
+    mut decrypt: impl FnMut(&mut [u8; 16]),
+    wrapped: &[u8],
+    key: &mut [u8],
+) -> Result<(), Error> {
+    if wrapped.len() != key.len() + 8 || key.len() % 8 != 0 || key.is_empty() {
+        return Err(Error::Decryption);
+    }
+    let n = key.len() / 8;
+    let mut a = [0; 8];
+    a.copy_from_slice(&wrapped[..8]);
+    key.copy_from_slice(&wrapped[8..]);
+    for j in (0..6).rev() {
+        for i in (1..=n).rev() {
+            let t = (n * j + i) as u64;
+            let mut block = [0; 16];
+            for (b, (a, t)) in block.iter_mut().zip(a.iter().zip(t.to_be_bytes())) {
+                *b = a ^ t;
+            }
+            block[8..].copy_from_slice(&key[(i - 1) * 8..i * 8]);
+            decrypt(&mut block);
+            a.copy_from_slice(&block[..8]);
+            key[(i - 1) * 8..i * 8].copy_from_slice(&block[8..]);
+        }
+    }
+    if a != KW_IV {
+        key.iter_mut().for_each(|b| *b = 0);
+        return Err(Error::Decryption);
+    }
+    Ok(())
+}
+
+fn hkdf_sha256(ikm: &[u8], info: &[u8], out: &mut [u8]) {
+    // extract, with an all zero salt
+    let mut mac = Hmac::<Sha256>::new_from_slice(&[0; 32]).unwrap();
+    mac.update(ikm);
+    let prk = mac.finalize().into_bytes();
+    // expand
+    let mut previous = [0; 32];
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&prk).unwrap();
+        if i != 0 {
+            mac.update(&previous);
+        }
+        mac.update(info);
+        mac.update(&[i as u8 + 1]);
+        previous.copy_from_slice(&mac.finalize().into_bytes());
+        chunk.copy_from_slice(&previous[..chunk.len()]);
+    }
+}
+
+/// Applies the AES-CTR keystream for `key`, starting `offset` bytes into the stream from a zero counter block
+fn apply_keystream(key: &[u8], offset: u32, data: &mut [u8]) {
+    if key.len() == 32 {
+        let cipher = Aes256::new(key.into());
+        keystream(|block| cipher.encrypt_block(block.into()), offset, data)
+    } else {
+        let cipher = Aes128::new(key.into());
+        keystream(|block| cipher.encrypt_block(block.into()), offset, data)
+    }
+}
+
+fn keystream(encrypt: impl Fn(&mut [u8; 16]), mut position: u32, mut data: &mut [u8]) {
+    while !data.is_empty() {
+        let mut block = ((position / 16) as u128).to_be_bytes();
+        encrypt(&mut block);
+        let skip = (position % 16) as usize;
+        let n = (16 - skip).min(data.len());
+        for (d, k) in data[..n].iter_mut().zip(&block[skip..]) {
+            *d ^= k;
+        }
+        position += n as u32;
+        data = &mut data[n..];
+    }
+}
+
+/// The key an image's payload is encrypted with
+pub struct ContentKey {
+    key: [u8; 32],
+    len: usize,
+}
+
+impl ContentKey {
+    /// Decrypts `data`, which starts `offset` bytes into the image's payload
+    pub fn decrypt(&self, offset: u32, data: &mut [u8]) {
+        apply_keystream(&self.key[..self.len], offset, data)
+    }
+
+    /// Decrypts the part of `data`, which starts `offset` bytes into the slot, that falls in the image's payload
+    pub fn decrypt_payload(&self, header: &Header, offset: u32, data: &mut [u8]) {
+        let payload = header.header_size as u32..header.tlv_offset();
+        let start = offset.max(payload.start);
+        let end = (offset + data.len() as u32).min(payload.end);
+        if start < end {
+            let data = &mut data[(start - offset) as usize..(end - offset) as usize];
+            self.decrypt(start - payload.start, data);
+        }
+    }
+}
+
+impl Drop for ContentKey {
+    fn drop(&mut self) {
+        self.key.iter_mut().for_each(|b| *b = 0);
+    }
+}
+
+/// Returns the size of the image's content key, or `None` if it isn't encrypted
+pub fn key_size(header: &Header) -> Result<Option<usize>, Error> {
+    match header.flags & (FLAG_ENCRYPTED_AES128 | FLAG_ENCRYPTED_AES256) {
+        0 => Ok(None),
+        FLAG_ENCRYPTED_AES128 => Ok(Some(16)),
+        FLAG_ENCRYPTED_AES256 => Ok(Some(32)),
+        _ => Err(Error::Malformed),
+    }
+}
+
+/// Unwraps the content key of an image with the header `header` from `wrapped`, the value of its key TLV
+pub fn unwrap_content_key(
+    header: &Header,
+    wrapped: &[u8],
+    unwrap: &dyn KeyUnwrap,
+) -> Result<ContentKey, Error> {
+    let len = key_size(header)?.ok_or(Error::Decryption)?;
+    let mut key = ContentKey { key: [0; 32], len };
+    unwrap.unwrap(wrapped, &mut key.key[..len])?;
+    Ok(key)
+}
+
+/// Reads the wrapped content key of the image in `slot` into `buf`, returning its length
+pub fn wrapped_key<F: Flash>(
+    flash: &F,
+    slot: &Range<u32>,
+    header: &Header,
+    unwrap: &dyn KeyUnwrap,
+    buf: &mut [u8; MAX_WRAPPED_KEY_SIZE],
+) -> Result<usize, Error> {
+    match find_tlv(flash, slot, header, unwrap.tlv(), buf) {
+        Ok(Some(len)) => Ok(len),
+        Ok(None) | Err(Error::Malformed) => Err(Error::Decryption),
+        Err(err) => Err(err),
+    }
+}
+
+/// Verifies the image in `slot` like [`verify`](super::verify), decrypting it first if it is encrypted. Returns the
+/// image's header, and its content key if it is encrypted, which needs `unwrap`.
+pub fn verify<F: Flash>(
+    flash: &F,
+    slot: &Range<u32>,
+    public_key: &[u8],
+    unwrap: Option<&dyn KeyUnwrap>,
+) -> Result<(Header, Option<ContentKey>), Error> {
+    let header = Header::read(flash, slot)?;
+    super::total_size(flash, slot, &header)?;
+    let key = match key_size(&header)? {
+        Some(_) => {
+            let unwrap = unwrap.ok_or(Error::Decryption)?;
+            let mut wrapped = [0; MAX_WRAPPED_KEY_SIZE];
+            let len = wrapped_key(flash, slot, &header, unwrap, &mut wrapped)?;
+            Some(unwrap_content_key(&header, &wrapped[..len], unwrap)?)
+        }
+        None => None,
+    };
+    let digest = hash_with(flash, slot, &header, |offset, data| {
+        if let Some(key) = &key {
+            key.decrypt_payload(&header, offset, data);
+        }
+    })?;
+    verify_digest(flash, slot, &header, &digest, public_key)?;
+    Ok((header, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The encrypted zero counter blocks for an all zero key, from the GCM specification's test cases 1, 2, 13
+    /// and 14: H is block 0, the tag of the empty message is block 1, and the first ciphertext block is block 2
+    #[test]
+    fn aes_ctr_known_answers() {
+        let cases: [(usize, [[u8; 16]; 3]); 2] = [
+            (
+                16,
+                [
+                    0x66e94bd4ef8a2c3b884cfa59ca342b2eu128.to_be_bytes(),
+                    0x58e2fccefa7e3061367f1d57a4e7455au128.to_be_bytes(),
+                    0x0388dace60b6a392f328c2b971b2fe78u128.to_be_bytes(),
+                ],
+            ),
+            (
+                32,
+                [
+                    0xdc95c078a2408989ad48a21492842087u128.to_be_bytes(),
+                    0x530f8afbc74536b9a963b4f1c4cb738bu128.to_be_bytes(),
+                    0xcea7403d4d606b6e074ec5d3baf39d18u128.to_be_bytes(),
+                ],
+            ),
+        ];
+        for (key_size, blocks) in cases {
+            let keystream = blocks.concat();
+            let key = ContentKey {
+                key: [0; 32],
+                len: key_size,
+            };
+            let mut data = [0; 48];
+            key.decrypt(0, &mut data);
+            assert_eq!(data[..], keystream[..]);
+            // decrypting part way through the payload picks the keystream up at the same point
+            let mut data = [0xFF; 21];
+            key.decrypt(13, &mut data);
+            for (i, byte) in data.iter().enumerate() {
+                assert_eq!(*byte, keystream[13 + i] ^ 0xFF);
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Interrupts owned by the secure world.
+//!
+//! `boot` sets AIRCR.PRIS, which maps every non-secure priority into the lower half of the priority range
+//! (0x80-0xFF). Secure interrupts registered here are limited to the upper half (0x00-0x7F), so they always
+//! pre-empt the non-secure world.
+//!
+//! Handlers are dispatched from cortex-m-rt's `DefaultHandler`, which catches every interrupt that
+//! doesn't have a handler of its own:
+//! ```no_run
+//! use cortex_m_rt::exception;
+//!
+//! #[exception]
+//! fn DefaultHandler(irqn: i16) {
+//!     frumsceaft::interrupt::dispatch(irqn);
+//! }
+//! ```
+use core::sync::atomic::{AtomicUsize, Ordering};
+use cortex_m::interrupt::InterruptNumber;
+use cortex_m::peripheral::NVIC;
+
+/// Number of interrupts that can be registered, which covers every supported chip
+pub const MAX_INTERRUPTS: usize = 160;
+/// The lowest priority (highest value) a secure interrupt can use
+pub const LOWEST_SECURE_PRIORITY: u8 = 0x7F;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_HANDLER: AtomicUsize = AtomicUsize::new(0);
+static HANDLERS: [AtomicUsize; MAX_INTERRUPTS] = [NO_HANDLER; MAX_INTERRUPTS];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// The interrupt number is above [`MAX_INTERRUPTS`]
+    OutOfRange(u16),
+    /// The priority falls in the lower half, which is shared with non-secure interrupts
+    InvalidPriority(u8),
+    /// The interrupt is targeted at the non-secure world, usually because its peripheral was passed
+    TargetedNonSecure(u16),
+    /// The interrupt belongs to a passed peripheral, but is still targeted at the secure world
+    TargetedSecure(u16),
+}
+
+#[derive(Clone, Copy)]
+struct Irq(u16);
+
+unsafe impl InterruptNumber for Irq {
+    fn number(self) -> u16 {
+        self.0
+    }
+}
+
+/// NVIC_ITNS, bit `n` is set if interrupt `n` targets the non-secure world
+const ITNS: *const u32 = 0xE000_E380 as *const u32;
+
+fn targets_non_secure(irq: u16) -> bool {
+    let irq = irq as usize;
+    let itns = unsafe { core::ptr::read_volatile(ITNS.add(irq / 32)) };
+    itns & (1 << (irq % 32)) != 0
+}
+
+/// Registers `handler` for the secure interrupt `irq`, sets its priority, and enables it.
+///
+/// `priority` must be between 0 and [`LOWEST_SECURE_PRIORITY`], and the interrupt must still be targeted at
+/// the secure world.
+pub fn register(irq: u16, priority: u8, handler: fn()) -> Result<(), Error> {
+    if irq as usize >= MAX_INTERRUPTS {
+        return Err(Error::OutOfRange(irq));
+    }
+    if priority > LOWEST_SECURE_PRIORITY {
+        return Err(Error::InvalidPriority(priority));
+    }
+    if targets_non_secure(irq) {
+        return Err(Error::TargetedNonSecure(irq));
+    }
+    HANDLERS[irq as usize].store(handler as usize, Ordering::Release);
+    let mut peripherals = unsafe { cortex_m::Peripherals::steal() };
+    unsafe {
+        peripherals.NVIC.set_priority(Irq(irq), priority);
+        NVIC::unpend(Irq(irq));
+        NVIC::unmask(Irq(irq));
+    }
+    Ok(())
+}
+
+/// Disables the secure interrupt `irq`, and removes its handler
+pub fn unregister(irq: u16) {
+    if irq as usize >= MAX_INTERRUPTS {
+        return;
+    }
+    NVIC::mask(Irq(irq));
+    NVIC::unpend(Irq(irq));
+    HANDLERS[irq as usize].store(0, Ordering::Release);
+}
+
+/// Checks that every registered interrupt is still targeted at the secure world, this fails if a peripheral
+/// that shares one of them was passed to the non-secure world. `boot` calls this after passing peripherals.
+pub fn validate() -> Result<(), Error> {
+    for (irq, handler) in HANDLERS.iter().enumerate() {
+        if handler.load(Ordering::Acquire) != 0 && targets_non_secure(irq as u16) {
+            return Err(Error::TargetedNonSecure(irq as u16));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that `irq`, an interrupt of a passed peripheral, is targeted at the non-secure world.
+/// `boot` calls this for every interrupt of each passed peripheral.
+pub fn validate_passed(irq: u16) -> Result<(), Error> {
+    if !targets_non_secure(irq) {
+        return Err(Error::TargetedSecure(irq));
+    }
+    Ok(())
+}
+
+/// Calls the handler registered for `irqn`, this should be called from `DefaultHandler`.
+/// Interrupts without a handler are logged and masked, so they don't fire again.
+pub fn dispatch(irqn: i16) {
+    if irqn < 0 || irqn as usize >= MAX_INTERRUPTS {
+        defmt::error!("unhandled exception: {}", irqn);
+        return;
+    }
+    let handler = HANDLERS[irqn as usize].load(Ordering::Acquire);
+    if handler == 0 {
+        defmt::error!("unhandled secure interrupt: {}", irqn);
+        NVIC::mask(Irq(irqn as u16));
+        return;
+    }
+    let handler: fn() = unsafe { core::mem::transmute(handler) };
+    handler();
+}
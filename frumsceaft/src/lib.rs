@@ -1,6 +1,7 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(abi_c_cmse_nonsecure_call)]
 #![doc = include_str!("../../README.md")]
+#[cfg(armv8m)]
 use cortex_m::cmse::{AccessType, TestTarget};
 
 use core::ops::Range;
@@ -15,7 +16,32 @@ pub trait IDAU {
     fn set_memory_region_params(&self, region: Range<u32>, params: RegionParams);
     fn set_nsc_region(&self, region: Range<u32>);
     fn pass_peripheral_non_secure(&self, perph: &Self::Peripheral);
+    /// Calls `f` with each interrupt line of `perph`, which `pass_peripheral_non_secure` targets at the
+    /// non-secure world
+    fn peripheral_interrupts(&self, perph: &Self::Peripheral, f: &mut dyn FnMut(usize));
     fn prepare_boot(&self);
+    /// Reads back the security configuration. Backends that can't read back their memory and peripheral
+    /// permissions only report the core's registers.
+    fn report(&self) -> audit::SecurityMap {
+        audit::SecurityMap::read_core()
+    }
+}
+
+/// Extends an [`IDAU`] whose peripheral security can still be changed after `boot`, so a peripheral can be lent to
+/// the non-secure world and later reclaimed.
+///
+/// Only peripherals that were left unlocked can be reassigned. Secure code should clear any sensitive state
+/// (e.g. keys loaded into a crypto block) before lending a peripheral.
+///
+/// Only the nRF53 and nRF91 SPUs implement this, the other backends configure peripheral security once in `boot`.
+pub trait ReassignPeripheral: IDAU {
+    type Error;
+    /// Makes `perph` non-secure, and targets its interrupts at the non-secure world
+    fn lend_peripheral(&self, perph: &Self::Peripheral) -> Result<(), Self::Error>;
+    /// Makes `perph` secure again. Its interrupts are disabled, cleared, and targeted back at the secure world,
+    /// and the peripheral is disabled with its interrupts, events and connections reset. The backend documents
+    /// which registers keep their non-secure values.
+    fn reclaim_peripheral(&self, perph: &Self::Peripheral) -> Result<(), Self::Error>;
 }
 
 /// RegionParams defines permissions for a flash or RAM region.
@@ -31,43 +57,113 @@ pub struct RegionParams {
 /// MemoryLayout specifies boundaries for the non-secure and secure regions.
 ///
 /// Each of these regions should be non-overlapping. It is undefined behavior if there is an overlap between regions.
+/// Regions are absolute addresses, so RAM regions start at the chip's RAM base address (e.g. `0x2000_0000`).
 pub struct MemoryLayout {
     pub secure_flash_region: Range<u32>,
     pub non_secure_flash_region: Range<u32>,
     pub secure_ram_region: Range<u32>,
     pub non_secure_ram_region: Range<u32>,
     pub nsc_flash_region: Option<Range<u32>>,
+    /// Address of the non-secure vector table, if it doesn't sit at the start of `non_secure_flash_region`
+    /// (e.g. because the region starts with an [`image`] header)
+    pub non_secure_vector_table: Option<u32>,
+}
+
+/// Converts a peripheral into the descriptor an [`IDAU`] uses to pass it to the non-secure world.
+///
+/// Each backend implements this for its descriptors, and for the secure aliases of its PAC peripherals (e.g.
+/// `UARTE0_S` on the nRF, `SEC_USART1` on the STM32), consuming them. Secure code accesses peripherals through those
+/// aliases, so passing them leaves it without a handle to the peripheral.
+pub trait IntoNonSecure<P> {
+    fn into_non_secure(self) -> P;
+}
+
+/// The peripherals handed to [`boot`]. This is implemented for slices and arrays of descriptors, and for tuples
+/// of up to 12 values that implement [`IntoNonSecure`], such as the secure PAC peripherals from `Peripherals::take()`.
+pub trait NonSecurePeripherals<P> {
+    fn for_each(self, f: impl FnMut(&P));
+}
+
+impl<P> NonSecurePeripherals<P> for &[P] {
+    fn for_each(self, f: impl FnMut(&P)) {
+        self.iter().for_each(f)
+    }
+}
+
+impl<P, const N: usize> NonSecurePeripherals<P> for &[P; N] {
+    fn for_each(self, f: impl FnMut(&P)) {
+        self.iter().for_each(f)
+    }
+}
+
+impl<P> NonSecurePeripherals<P> for () {
+    fn for_each(self, _: impl FnMut(&P)) {}
 }
 
+macro_rules! impl_non_secure_tuple {
+    ($($t:ident $v:ident),+) => {
+        impl<P, $($t: IntoNonSecure<P>),+> NonSecurePeripherals<P> for ($($t,)+) {
+            fn for_each(self, mut f: impl FnMut(&P)) {
+                let ($($v,)+) = self;
+                $(f(&$v.into_non_secure());)+
+            }
+        }
+    };
+}
+
+impl_non_secure_tuple! { A a }
+impl_non_secure_tuple! { A a, B b }
+impl_non_secure_tuple! { A a, B b, C c }
+impl_non_secure_tuple! { A a, B b, C c, D d }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e, F f }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e, F f, G g }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e, F f, G g, H h }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e, F f, G g, H h, I i }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e, F f, G g, H h, I i, J j }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k }
+impl_non_secure_tuple! { A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l }
+
 /// Uses the IDAU to set permissions on each flash region, and to mark the passed peripherals as non-secure. Once done, `boot`
 /// jumps to the `reset` handler of the non-secure firmware.
 ///
+/// On ARMv8-M Mainline targets with an FPU (`thumbv8m.main-none-eabihf`), `boot` grants the non-secure world access to
+/// it through `NSACR`, after setting `FPCCR` so secure floating-point state is cleared before non-secure code runs.
+/// ARMv8-M Baseline parts (`thumbv8m.base-none-eabi`, e.g. the Cortex-M23 in the SAM L11) have no FPU or `NSACR`, so
+/// that step is skipped there, they still provide `MSP_NS`, `VTOR_NS`, and the `TT` instructions.
+///
+/// Peripherals are passed either as a tuple of secure PAC peripherals (e.g. `UARTE0_S`), which `boot` takes ownership
+/// of so secure code can't keep using them, or as a slice of descriptors (e.g. `P0_NS::perph()`) for code that
+/// doesn't own them.
+///
 /// # Example
 /// ```no_run
-/// use frumsceaft::nrf53::PerphExt;
-///
+/// let p = nrf5340_app_pac::Peripherals::take().unwrap();
 /// let spu = unsafe { &*nrf5340_app_pac::SPU_S::PTR };
 /// frumscaeft::boot(
-///     spu,
+///     &frumsceaft::nrf53::SPU::new(spu),
 ///     boot_oxide::MemoryLayout {
 ///         secure_flash_region: 0..NON_SECURE_START,
 ///         non_secure_flash_region: NON_SECURE_START..ROM_SIZE,
-///         secure_ram_region: 0..NON_SECURE_SRAM_START,
-///         non_secure_ram_region: NON_SECURE_SRAM_START..RAM_SIZE,
+///         secure_ram_region: RAM_START..NON_SECURE_SRAM_START,
+///         non_secure_ram_region: NON_SECURE_SRAM_START..RAM_END,
 ///         nsc_flash_region: None,
+///         non_secure_vector_table: None,
 ///     },
-///     &[
-///         nrf5340_app_pac::P0_NS::perph(),
-///         nrf5340_app_pac::MUTEX_NS::perph(),
-///         nrf5340_app_pac::UARTE0_NS::perph(),
-///         nrf5340_app_pac::TIMER0_NS::perph(),
-///     ],
+///     (p.P0_S, p.MUTEX_S, p.UARTE0_S, p.TIMER0_S),
 /// )
 /// ```
-pub fn boot<I: IDAU>(idau: &I, layout: MemoryLayout, peripherals: &[I::Peripheral]) -> ! {
-    let non_secure_start = layout.non_secure_flash_region.start;
+#[cfg(armv8m)]
+pub fn boot<I: IDAU, P: NonSecurePeripherals<I::Peripheral>>(
+    idau: &I,
+    layout: MemoryLayout,
+    peripherals: P,
+) -> ! {
+    let non_secure_start = layout
+        .non_secure_vector_table
+        .unwrap_or(layout.non_secure_flash_region.start);
     idau.set_flash_region_params(
-        layout.secure_flash_region,
+        layout.secure_flash_region.clone(),
         RegionParams {
             write: true,
             read: true,
@@ -78,7 +174,7 @@ pub fn boot<I: IDAU>(idau: &I, layout: MemoryLayout, peripherals: &[I::Periphera
     );
 
     idau.set_flash_region_params(
-        layout.non_secure_flash_region,
+        layout.non_secure_flash_region.clone(),
         RegionParams {
             write: true,
             read: true,
@@ -89,7 +185,7 @@ pub fn boot<I: IDAU>(idau: &I, layout: MemoryLayout, peripherals: &[I::Periphera
     );
 
     idau.set_memory_region_params(
-        layout.non_secure_ram_region,
+        layout.non_secure_ram_region.clone(),
         RegionParams {
             write: true,
             read: true,
@@ -100,7 +196,7 @@ pub fn boot<I: IDAU>(idau: &I, layout: MemoryLayout, peripherals: &[I::Periphera
     );
 
     idau.set_memory_region_params(
-        layout.secure_ram_region,
+        layout.secure_ram_region.clone(),
         RegionParams {
             write: true,
             read: true,
@@ -109,16 +205,29 @@ pub fn boot<I: IDAU>(idau: &I, layout: MemoryLayout, peripherals: &[I::Periphera
             secure: true,
         },
     );
-    if let Some(nsc_flash_region) = layout.nsc_flash_region {
+    if let Some(nsc_flash_region) = layout.nsc_flash_region.clone() {
         idau.set_nsc_region(nsc_flash_region);
     }
 
-    for p in peripherals {
+    peripherals.for_each(|p| {
         idau.pass_peripheral_non_secure(p);
+        idau.peripheral_interrupts(p, &mut |irq| {
+            if let Err(err) = interrupt::validate_passed(irq as u16) {
+                defmt::panic!("passed peripheral's interrupt left secure: {:?}", err);
+            }
+        });
+    });
+    if let Err(err) = interrupt::validate() {
+        defmt::panic!("secure interrupt passed to the non-secure world: {:?}", err);
     }
 
     idau.prepare_boot();
 
+    let mismatches = audit::verify_layout(&layout);
+    if mismatches != 0 {
+        defmt::error!("{} ranges don't match the memory layout", mismatches);
+    }
+
     unsafe {
         let ns_vector_table = non_secure_start as *const u32;
         // get scb (system control block) peripheral
@@ -132,6 +241,21 @@ pub fn boot<I: IDAU>(idau: &I, layout: MemoryLayout, peripherals: &[I::Periphera
         // & send non-banked exceptions to non-secure
         scb.aircr.write(0x5FA56000);
 
+        // let the non-secure world use CP10 and CP11 (the FPU), Baseline has neither
+        #[cfg(has_fpu)]
+        {
+            // FPCCR: TS (26) treats the FP registers as secure so they're stacked and cleared before non-secure
+            // exception handlers run, CLRONRET (28) clears the caller-saved ones on exception return, and CLRONRETS (27)
+            // stops the non-secure world from turning that off again
+            let fpccr = 0xE000EF34 as *mut u32;
+            core::ptr::write_volatile(
+                fpccr,
+                core::ptr::read_volatile(fpccr) | 1 << 26 | 1 << 27 | 1 << 28,
+            );
+            let nsacr = 0xE000ED8C as *mut u32;
+            core::ptr::write_volatile(nsacr, core::ptr::read_volatile(nsacr) | 0b11 << 10);
+        }
+
         // ensure that flash region has the appropriate permissions
         let region_access = TestTarget::check(
             non_secure_start as *mut u32,
@@ -153,8 +277,42 @@ pub fn boot<I: IDAU>(idau: &I, layout: MemoryLayout, peripherals: &[I::Periphera
     }
 }
 
+#[cfg(feature = "ecdsa")]
+pub mod ecdsa;
+
+pub mod audit;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+pub mod flash;
+#[cfg(feature = "image")]
+pub mod image;
+pub mod interrupt;
+pub mod nsc;
+#[cfg(feature = "recovery")]
+pub mod recovery;
+pub mod storage;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "update")]
+pub mod update;
+
+#[cfg(any(feature = "nrf53", feature = "nrf91"))]
+mod nrf_spu;
+
 #[cfg(feature = "nrf53")]
 pub mod nrf53;
 
-#[cfg(feature = "stm32l562")]
-pub mod stm32l562;
+#[cfg(feature = "nrf91")]
+pub mod nrf91;
+
+#[cfg(feature = "lpc55")]
+pub mod lpc55;
+
+#[cfg(feature = "saml11")]
+pub mod saml11;
+
+#[cfg(any(feature = "stm32l552", feature = "stm32l562"))]
+pub mod stm32l5;
+
+#[cfg(any(feature = "stm32u575", feature = "stm32u585"))]
+pub mod stm32u5;
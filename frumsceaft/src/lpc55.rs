@@ -0,0 +1,720 @@
+use crate::RegionParams;
+use core::cell::Cell;
+use core::ops::Range;
+use core::ptr::{read_volatile, write_volatile};
+use cortex_m::peripheral::sau::{SauRegion, SauRegionAttribute};
+use lpc55_pac::Interrupt;
+
+/// The LPC55's IDAU marks every address with bit 28 set as secure, the AHB secure controller has to
+/// be accessed through that alias.
+const SECURE_ALIAS: u32 = 0x10000000;
+
+// AHB secure controller register offsets, pulled from UM11126 chapter 49
+const SEC_CTRL_APB_BRIDGE_SLAVE_RULE: u32 = 0xF0;
+const SEC_CTRL_APB_BRIDGE0_MEM_CTRL0: u32 = 0x100;
+const SEC_CTRL_APB_BRIDGE1_MEM_CTRL0: u32 = 0x110;
+const SEC_CTRL_AHB_PORT8_SLAVE0_RULE: u32 = 0x120;
+const SEC_CTRL_AHB_PORT9_SLAVE0_RULE: u32 = 0x130;
+const SEC_CTRL_AHB_PORT10_SLAVE0_RULE: u32 = 0x140;
+const SEC_GPIO_MASK0: u32 = 0xF80;
+const SEC_MASK_LOCK: u32 = 0xFBC;
+const MASTER_SEC_LEVEL: u32 = 0xFD0;
+const MASTER_SEC_ANTI_POL_REG: u32 = 0xFD4;
+const MISC_CTRL_DP_REG: u32 = 0xFF8;
+const MISC_CTRL_REG: u32 = 0xFFC;
+
+/// Every peripheral rule register, in the order [`crate::audit::SecurityMap`] numbers their rules
+const PERIPHERAL_RULES: [u32; 13] = [
+    SEC_CTRL_APB_BRIDGE0_MEM_CTRL0,
+    SEC_CTRL_APB_BRIDGE0_MEM_CTRL0 + 4,
+    SEC_CTRL_APB_BRIDGE0_MEM_CTRL0 + 8,
+    SEC_CTRL_APB_BRIDGE1_MEM_CTRL0,
+    SEC_CTRL_APB_BRIDGE1_MEM_CTRL0 + 4,
+    SEC_CTRL_APB_BRIDGE1_MEM_CTRL0 + 8,
+    SEC_CTRL_APB_BRIDGE1_MEM_CTRL0 + 12,
+    SEC_CTRL_AHB_PORT8_SLAVE0_RULE,
+    SEC_CTRL_AHB_PORT8_SLAVE0_RULE + 4,
+    SEC_CTRL_AHB_PORT9_SLAVE0_RULE,
+    SEC_CTRL_AHB_PORT9_SLAVE0_RULE + 4,
+    SEC_CTRL_AHB_PORT10_SLAVE0_RULE,
+    SEC_CTRL_AHB_PORT10_SLAVE0_RULE + 4,
+];
+
+/// MISC_CTRL value that enables secure checking and violation aborts, keeps the IDAU enabled,
+/// and locks the rule tables. Every field is a two bit pair, `0b01` enables and `0b10` disables.
+const MISC_CTRL_LOCKED: u32 = 0xAAA5;
+
+/// A memory that the AHB secure controller splits into equally sized rules, each rule is a four bit
+/// field holding a [`SecurityLevel`], eight to a register.
+struct MemoryRules {
+    base: u32,
+    size: u32,
+    granule: u32,
+    rules: u32,
+}
+
+const FLASH: MemoryRules = MemoryRules {
+    base: 0x00000000,
+    size: 0xA0000,
+    granule: 0x8000,
+    rules: 0x10,
+};
+
+const RAM: [MemoryRules; 6] = [
+    // RAMX
+    MemoryRules {
+        base: 0x04000000,
+        size: 0x8000,
+        granule: 0x1000,
+        rules: 0x40,
+    },
+    // SRAM0
+    MemoryRules {
+        base: 0x20000000,
+        size: 0x10000,
+        granule: 0x1000,
+        rules: 0x60,
+    },
+    // SRAM1
+    MemoryRules {
+        base: 0x20010000,
+        size: 0x10000,
+        granule: 0x1000,
+        rules: 0x80,
+    },
+    // SRAM2
+    MemoryRules {
+        base: 0x20020000,
+        size: 0x10000,
+        granule: 0x1000,
+        rules: 0xA0,
+    },
+    // SRAM3
+    MemoryRules {
+        base: 0x20030000,
+        size: 0x10000,
+        granule: 0x1000,
+        rules: 0xC0,
+    },
+    // SRAM4
+    MemoryRules {
+        base: 0x20040000,
+        size: 0x4000,
+        granule: 0x1000,
+        rules: 0xE0,
+    },
+];
+
+impl MemoryRules {
+    /// Sets the rule of every granule of this memory that falls inside `region`
+    fn apply(&self, region: &Range<u32>, level: SecurityLevel) {
+        let start = region.start.max(self.base);
+        let end = region.end.min(self.base + self.size);
+        if start >= end {
+            return;
+        }
+        if (start - self.base) % self.granule != 0 || (end - self.base) % self.granule != 0 {
+            defmt::panic!(
+                "region {:x}..{:x} isn't aligned to the AHB secure controller's {:x} byte rules",
+                region.start,
+                region.end,
+                self.granule
+            );
+        }
+        for rule in (start - self.base) / self.granule..(end - self.base) / self.granule {
+            set_rule(self.rules + (rule / 8) * 4, rule % 8, level);
+        }
+    }
+
+    /// Adds the attribution of each rule of this memory to `map`
+    fn report(&self, map: &mut crate::audit::SecurityMap, locked: bool) {
+        for rule in 0..self.size / self.granule {
+            let level = (reg_read(self.rules + (rule / 8) * 4) >> ((rule % 8) * 4)) & 0b11;
+            let start = self.base + rule * self.granule;
+            let secure = level >= SecurityLevel::SecureUser as u32;
+            map.push_range(start..start + self.granule, secure, locked);
+        }
+    }
+}
+
+fn reg(offset: u32) -> *mut u32 {
+    (lpc55_pac::AHB_SECURE_CTRL::PTR as u32 | SECURE_ALIAS | offset) as *mut u32
+}
+
+fn reg_read(offset: u32) -> u32 {
+    unsafe { read_volatile(reg(offset)) }
+}
+
+fn reg_write(offset: u32, value: u32) {
+    unsafe { write_volatile(reg(offset), value) }
+}
+
+/// Writes the four bit rule `index` of the rule register at `offset`
+fn set_rule(offset: u32, index: u32, level: SecurityLevel) {
+    let shift = index * 4;
+    unsafe {
+        let value = read_volatile(reg(offset));
+        write_volatile(
+            reg(offset),
+            (value & !(0xF << shift)) | (level as u32) << shift,
+        );
+    }
+}
+
+/// The minimum security level a master needs to access a rule, and the level a master's transactions carry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum SecurityLevel {
+    NonSecureUser = 0,
+    NonSecurePrivileged = 1,
+    SecureUser = 2,
+    SecurePrivileged = 3,
+}
+
+/// AHBSecureCtrl is the LPC55S69's IDAU, it is built from the AHB secure controller's memory and
+/// peripheral rule tables, along with the SAU.
+///
+/// Every peripheral that isn't passed to `boot` is made secure before booting the non-secure world, and the
+/// rule tables are locked once the non-secure world has been set up.
+pub struct AHBSecureCtrl<'a> {
+    /// GPIO pins whose state is masked from the non-secure world, see `SEC_GPIO_MASK`
+    pub secure_pins: &'a [GpioPin],
+    /// The security level of each bus master's transactions, masters that aren't listed keep the reset
+    /// value of [`SecurityLevel::NonSecureUser`].
+    ///
+    /// A master used by secure code (e.g. a DMA controller moving secure data) must be raised here,
+    /// otherwise it can only reach non-secure memory and peripherals.
+    pub master_security: &'a [(Master, SecurityLevel)],
+    peripherals_secured: Cell<bool>,
+    /// The USB controllers passed so far, one bit each for the FS device, FS host, HS device and HS host
+    usb_passed: Cell<u8>,
+}
+
+impl<'a> AHBSecureCtrl<'a> {
+    pub const fn new() -> Self {
+        AHBSecureCtrl {
+            secure_pins: &[],
+            master_security: &[],
+            peripherals_secured: Cell::new(false),
+            usb_passed: Cell::new(0),
+        }
+    }
+
+    /// Marks every peripheral rule as secure, this only happens once, before the first peripheral is
+    /// passed to the non-secure world. The bridge and port rules stay open, so the per-peripheral rules decide.
+    fn secure_peripherals(&self) {
+        if self.peripherals_secured.replace(true) {
+            return;
+        }
+        // four bits per rule, each set to SecureUser
+        let secure = 0x22222222;
+        for offset in PERIPHERAL_RULES {
+            reg_write(offset, secure);
+        }
+        reg_write(SEC_CTRL_APB_BRIDGE_SLAVE_RULE, 0);
+    }
+
+    /// Checks that every secure pin exists, both ports have 32 pins, and that each USB controller's device and host
+    /// have both been passed or both been kept, as they share their interrupts
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(pin) = self.secure_pins.iter().find(|pin| !pin.is_valid()) {
+            return Err(Error::InvalidPin(*pin));
+        }
+        let usb = self.usb_passed.get();
+        if usb & 0b11 == 0b01 || usb & 0b11 == 0b10 {
+            return Err(Error::UsbSplit(Usb::FullSpeed));
+        }
+        if usb & 0b1100 == 0b0100 || usb & 0b1100 == 0b1000 {
+            return Err(Error::UsbSplit(Usb::HighSpeed));
+        }
+        Ok(())
+    }
+
+    fn set_master_security(&self) {
+        let mut level = 0;
+        for (master, security) in self.master_security {
+            let shift = master.shift();
+            level = (level & !(0b11 << shift)) | (*security as u32) << shift;
+        }
+        // lock the levels, 0b01 in MASTER_SEC_LEVEL_LOCK
+        level = (level & !(0b11 << 30)) | 0b01 << 30;
+        reg_write(MASTER_SEC_LEVEL, level);
+        // the anti-pole register must always hold the inverse of the levels
+        reg_write(MASTER_SEC_ANTI_POL_REG, !level);
+    }
+}
+
+impl Default for AHBSecureCtrl<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl crate::IDAU for AHBSecureCtrl<'_> {
+    type Peripheral = Peripheral;
+
+    fn set_flash_region_params(&self, region: Range<u32>, params: RegionParams) {
+        // the rules work on the non-secure alias, so strip the secure alias bit
+        let rules = (region.start & !SECURE_ALIAS)..(region.end & !SECURE_ALIAS);
+        FLASH.apply(&rules, level(&params));
+        if !params.secure {
+            let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+            let mut sau = p.SAU;
+            sau.set_region(
+                0,
+                SauRegion {
+                    base_address: region.start,
+                    limit_address: region.end,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    fn set_memory_region_params(&self, region: Range<u32>, params: RegionParams) {
+        let rules = (region.start & !SECURE_ALIAS)..(region.end & !SECURE_ALIAS);
+        for memory in &RAM {
+            memory.apply(&rules, level(&params));
+        }
+        if !params.secure {
+            let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+            let mut sau = p.SAU;
+            sau.set_region(
+                1,
+                SauRegion {
+                    base_address: region.start,
+                    limit_address: region.end,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    fn set_nsc_region(&self, region: Range<u32>) {
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+        sau.set_region(
+            2,
+            SauRegion {
+                base_address: region.start,
+                limit_address: region.end,
+                attribute: SauRegionAttribute::NonSecureCallable,
+            },
+        )
+        .unwrap();
+    }
+
+    fn pass_peripheral_non_secure(&self, perph: &Self::Peripheral) {
+        self.secure_peripherals();
+        let (offset, index) = match perph.rule() {
+            Ok(rule) => rule,
+            Err(err) => defmt::panic!("invalid peripheral: {:?}", err),
+        };
+        set_rule(offset, index, SecurityLevel::NonSecureUser);
+        let usb = match perph {
+            Peripheral::UsbFsDevice => 0b0001,
+            Peripheral::UsbFsHost => 0b0010,
+            Peripheral::UsbHsDevice => 0b0100,
+            Peripheral::UsbHsHost => 0b1000,
+            _ => 0,
+        };
+        self.usb_passed.set(self.usb_passed.get() | usb);
+        perph.for_each_interrupt(enable_int);
+    }
+
+    fn peripheral_interrupts(&self, perph: &Self::Peripheral, f: &mut dyn FnMut(usize)) {
+        perph.for_each_interrupt(f);
+    }
+
+    fn prepare_boot(&self) {
+        if let Err(err) = self.validate() {
+            defmt::panic!("invalid AHB secure controller configuration: {:?}", err);
+        }
+        self.secure_peripherals();
+        self.set_master_security();
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+
+        // a cleared bit in SEC_GPIO_MASK hides the pin's state from the non-secure world
+        let mut masks = [0xFFFFFFFFu32; 2];
+        for pin in self.secure_pins {
+            match pin {
+                GpioPin::P0(p) => masks[0] &= !(1 << p),
+                GpioPin::P1(p) => masks[1] &= !(1 << p),
+            }
+        }
+        reg_write(SEC_GPIO_MASK0, masks[0]);
+        reg_write(SEC_GPIO_MASK0 + 4, masks[1]);
+        // lock the GPIO masks, 0b01 in each lock field
+        reg_write(SEC_MASK_LOCK, 0x5555);
+
+        // set all peripheral memory blocks as non-secure, the peripheral rules decide what is accessible
+        sau.set_region(
+            3,
+            SauRegion {
+                base_address: 0x40000000,
+                limit_address: 0x4FFFFFFF,
+                attribute: SauRegionAttribute::NonSecure,
+            },
+        )
+        .unwrap();
+        sau.enable();
+
+        // the duplicate register has to be written first
+        reg_write(MISC_CTRL_DP_REG, MISC_CTRL_LOCKED);
+        reg_write(MISC_CTRL_REG, MISC_CTRL_LOCKED);
+    }
+
+    fn report(&self) -> crate::audit::SecurityMap {
+        let mut map = crate::audit::SecurityMap::read_core();
+        // MISC_CTRL_REG.WRITE_LOCK, 0b01 locks the rule tables
+        let locked = reg_read(MISC_CTRL_REG) & 0b11 == 0b01;
+        FLASH.report(&mut map, locked);
+        for memory in &RAM {
+            memory.report(&mut map, locked);
+        }
+        // rules below SecureUser are open to the non-secure world
+        for (i, offset) in PERIPHERAL_RULES.iter().enumerate() {
+            let rules = reg_read(*offset);
+            for rule in 0..8 {
+                if (rules >> (rule * 4)) & 0b11 < SecurityLevel::SecureUser as u32 {
+                    map.set_non_secure_peripheral(i * 8 + rule);
+                }
+            }
+        }
+        map
+    }
+}
+
+/// Secure regions are open to secure code regardless of privilege, non-secure regions are open to everyone
+fn level(params: &RegionParams) -> SecurityLevel {
+    if params.secure {
+        SecurityLevel::SecureUser
+    } else {
+        SecurityLevel::NonSecureUser
+    }
+}
+
+/// A GPIO pin on either port of the LPC55S69.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum GpioPin {
+    P0(u8),
+    P1(u8),
+}
+
+impl GpioPin {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            GpioPin::P0(pin) | GpioPin::P1(pin) => *pin < 32,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// The pin is beyond the end of its port
+    InvalidPin(GpioPin),
+    /// There are only Flexcomm interfaces 0-8
+    InvalidFlexcomm(usize),
+    /// Only one of the USB controller's device and host was passed, they share the USB interrupts so both have to
+    /// be passed to the non-secure world or neither
+    UsbSplit(Usb),
+}
+
+/// One of the LPC55S69's two USB controllers, each has a device and a host that share interrupts
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Usb {
+    FullSpeed,
+    HighSpeed,
+}
+
+/// An AHB bus master, whose security level is set through `MASTER_SEC_LEVEL`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Master {
+    Cpu1Code,
+    Cpu1System,
+    UsbFsDevice,
+    Dma0,
+    Sdio,
+    PowerQuad,
+    HashAes,
+    UsbFsHost,
+    Dma1,
+}
+
+impl Master {
+    /// The position of the master's level in `MASTER_SEC_LEVEL`, the USB HS controllers don't have one
+    fn shift(&self) -> u32 {
+        match self {
+            Master::Cpu1Code => 4,
+            Master::Cpu1System => 6,
+            Master::UsbFsDevice => 8,
+            Master::Dma0 => 10,
+            Master::Sdio => 16,
+            Master::PowerQuad => 18,
+            Master::HashAes => 20,
+            Master::UsbFsHost => 22,
+            Master::Dma1 => 24,
+        }
+    }
+}
+
+/// A peripheral that can be passed to the non-secure world.
+///
+/// Passing a DMA or USB peripheral only hands over its registers, the security of the memory accesses it
+/// makes is set through `AHBSecureCtrl::master_security`.
+pub enum Peripheral {
+    Iocon,
+    Gint0,
+    Gint1,
+    Pint,
+    InputMux,
+    Ctimer0,
+    Ctimer1,
+    Ctimer2,
+    Ctimer3,
+    Ctimer4,
+    Wwdt,
+    Mrt,
+    Utick,
+    AnaCtrl,
+    Rtc,
+    OsEvent,
+    UsbHsPhy,
+    Rng,
+    Puf,
+    Plu,
+    Dma0,
+    UsbFsDevice,
+    Sct,
+    /// A Flexcomm interface (0-8), Flexcomm 8 is the high speed SPI
+    Flexcomm(usize),
+    Mailbox,
+    Gpio0,
+    Gpio1,
+    UsbHsDevice,
+    Crc,
+    Sdio,
+    DbgMailbox,
+    Adc,
+    UsbFsHost,
+    UsbHsHost,
+    HashAes,
+    Casper,
+    PowerQuad,
+    Dma1,
+}
+
+impl Peripheral {
+    /// Returns the rule register offset, and the index of the peripheral's rule within it
+    fn rule(&self) -> Result<(u32, u32), Error> {
+        use Peripheral::*;
+        const BRIDGE0: u32 = SEC_CTRL_APB_BRIDGE0_MEM_CTRL0;
+        const BRIDGE1: u32 = SEC_CTRL_APB_BRIDGE1_MEM_CTRL0;
+        const PORT8: u32 = SEC_CTRL_AHB_PORT8_SLAVE0_RULE;
+        const PORT9: u32 = SEC_CTRL_AHB_PORT9_SLAVE0_RULE;
+        const PORT10: u32 = SEC_CTRL_AHB_PORT10_SLAVE0_RULE;
+        Ok(match self {
+            Iocon => (BRIDGE0, 1),
+            Gint0 => (BRIDGE0, 2),
+            Gint1 => (BRIDGE0, 3),
+            Pint => (BRIDGE0, 4),
+            InputMux => (BRIDGE0, 6),
+            Ctimer0 => (BRIDGE0 + 4, 0),
+            Ctimer1 => (BRIDGE0 + 4, 1),
+            Wwdt => (BRIDGE0 + 4, 4),
+            Mrt => (BRIDGE0 + 4, 5),
+            Utick => (BRIDGE0 + 4, 6),
+            AnaCtrl => (BRIDGE0 + 8, 3),
+            Ctimer2 => (BRIDGE1 + 4, 0),
+            Ctimer3 => (BRIDGE1 + 4, 1),
+            Ctimer4 => (BRIDGE1 + 4, 2),
+            Rtc => (BRIDGE1 + 4, 4),
+            OsEvent => (BRIDGE1 + 4, 5),
+            UsbHsPhy => (BRIDGE1 + 12, 0),
+            Rng => (BRIDGE1 + 12, 2),
+            Puf => (BRIDGE1 + 12, 3),
+            Plu => (BRIDGE1 + 12, 5),
+            Dma0 => (PORT8, 2),
+            UsbFsDevice => (PORT8, 3),
+            Sct => (PORT8, 4),
+            Flexcomm(0) => (PORT8, 5),
+            Flexcomm(1) => (PORT8, 6),
+            Flexcomm(2) => (PORT8 + 4, 0),
+            Flexcomm(3) => (PORT8 + 4, 1),
+            Flexcomm(4) => (PORT8 + 4, 2),
+            Mailbox => (PORT8 + 4, 3),
+            Gpio0 => (PORT8 + 4, 4),
+            UsbHsDevice => (PORT9, 0),
+            Crc => (PORT9, 1),
+            Flexcomm(5) => (PORT9, 2),
+            Flexcomm(6) => (PORT9, 3),
+            Flexcomm(7) => (PORT9 + 4, 0),
+            Sdio => (PORT9 + 4, 3),
+            DbgMailbox => (PORT9 + 4, 4),
+            Flexcomm(8) => (PORT9 + 4, 6),
+            Adc => (PORT10, 0),
+            UsbFsHost => (PORT10, 2),
+            UsbHsHost => (PORT10, 3),
+            HashAes => (PORT10, 4),
+            Casper => (PORT10, 5),
+            PowerQuad => (PORT10, 6),
+            Dma1 => (PORT10, 7),
+            Gpio1 => (PORT10 + 4, 0),
+            Flexcomm(n) => return Err(Error::InvalidFlexcomm(*n)),
+        })
+    }
+
+    /// Calls `f` with each of the peripheral's interrupt lines
+    fn for_each_interrupt(&self, mut f: impl FnMut(usize)) {
+        match self {
+            Peripheral::Gint0 => f(Interrupt::GINT0 as usize),
+            Peripheral::Gint1 => f(Interrupt::GINT1 as usize),
+            Peripheral::Pint => {
+                for id in [
+                    Interrupt::PIN_INT0,
+                    Interrupt::PIN_INT1,
+                    Interrupt::PIN_INT2,
+                    Interrupt::PIN_INT3,
+                    Interrupt::PIN_INT4,
+                    Interrupt::PIN_INT5,
+                    Interrupt::PIN_INT6,
+                    Interrupt::PIN_INT7,
+                ] {
+                    f(id as usize);
+                }
+            }
+            Peripheral::Ctimer0 => f(Interrupt::CTIMER0 as usize),
+            Peripheral::Ctimer1 => f(Interrupt::CTIMER1 as usize),
+            Peripheral::Ctimer2 => f(Interrupt::CTIMER2 as usize),
+            Peripheral::Ctimer3 => f(Interrupt::CTIMER3 as usize),
+            Peripheral::Ctimer4 => f(Interrupt::CTIMER4 as usize),
+            Peripheral::Wwdt => f(Interrupt::WDT_BOD as usize),
+            Peripheral::Mrt => f(Interrupt::MRT0 as usize),
+            Peripheral::Utick => f(Interrupt::UTICK0 as usize),
+            Peripheral::Rtc => f(Interrupt::RTC as usize),
+            Peripheral::OsEvent => f(Interrupt::OS_EVENT as usize),
+            Peripheral::UsbHsPhy => f(Interrupt::USB1_PHY as usize),
+            Peripheral::Puf => f(Interrupt::PUF as usize),
+            Peripheral::Plu => f(Interrupt::PLU as usize),
+            Peripheral::Dma0 => f(Interrupt::DMA0 as usize),
+            Peripheral::UsbFsDevice | Peripheral::UsbFsHost => {
+                f(Interrupt::USB0 as usize);
+                f(Interrupt::USB0_NEEDCLK as usize);
+            }
+            Peripheral::UsbHsDevice | Peripheral::UsbHsHost => {
+                f(Interrupt::USB1 as usize);
+                f(Interrupt::USB1_NEEDCLK as usize);
+            }
+            Peripheral::Sct => f(Interrupt::SCT0 as usize),
+            Peripheral::Flexcomm(n) => {
+                let id = match n {
+                    0 => Interrupt::FLEXCOMM0,
+                    1 => Interrupt::FLEXCOMM1,
+                    2 => Interrupt::FLEXCOMM2,
+                    3 => Interrupt::FLEXCOMM3,
+                    4 => Interrupt::FLEXCOMM4,
+                    5 => Interrupt::FLEXCOMM5,
+                    6 => Interrupt::FLEXCOMM6,
+                    7 => Interrupt::FLEXCOMM7,
+                    8 => Interrupt::FLEXCOMM8,
+                    _ => return,
+                };
+                f(id as usize);
+            }
+            Peripheral::Mailbox => f(Interrupt::MAILBOX as usize),
+            Peripheral::Sdio => f(Interrupt::SDIO as usize),
+            Peripheral::Adc => f(Interrupt::ADC0 as usize),
+            Peripheral::HashAes => f(Interrupt::HASH_AES as usize),
+            Peripheral::Casper => f(Interrupt::CASPER as usize),
+            Peripheral::PowerQuad => f(Interrupt::PQ as usize),
+            Peripheral::Dma1 => f(Interrupt::DMA1 as usize),
+            Peripheral::Iocon
+            | Peripheral::InputMux
+            | Peripheral::AnaCtrl
+            | Peripheral::Rng
+            | Peripheral::Gpio0
+            | Peripheral::Gpio1
+            | Peripheral::Crc
+            | Peripheral::DbgMailbox => {}
+        }
+    }
+}
+
+fn enable_int(id: usize) {
+    let peripherals = unsafe { cortex_m::Peripherals::steal() };
+    unsafe {
+        peripherals.NVIC.icer[id / 32].write(1 << (id % 32));
+    }
+    unsafe {
+        peripherals.NVIC.itns[id / 32].modify(|w| w | 1 << (id & 0x1F));
+    }
+}
+
+impl crate::IntoNonSecure<Peripheral> for Peripheral {
+    fn into_non_secure(self) -> Peripheral {
+        self
+    }
+}
+
+/// Maps PAC peripherals to their descriptors, passing a PAC peripheral to `boot` consumes it
+macro_rules! impl_into_non_secure {
+    ($($(#[$attr:meta])* $pac:ident => $variant:expr),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            impl crate::IntoNonSecure<Peripheral> for lpc55_pac::$pac {
+                fn into_non_secure(self) -> Peripheral {
+                    #[allow(unused_imports)]
+                    use Peripheral::*;
+                    $variant
+                }
+            }
+        )*
+    };
+}
+
+impl_into_non_secure! {
+    IOCON => Iocon,
+    GINT0 => Gint0,
+    GINT1 => Gint1,
+    PINT => Pint,
+    INPUTMUX => InputMux,
+    CTIMER0 => Ctimer0,
+    CTIMER1 => Ctimer1,
+    CTIMER2 => Ctimer2,
+    CTIMER3 => Ctimer3,
+    CTIMER4 => Ctimer4,
+    WWDT => Wwdt,
+    MRT0 => Mrt,
+    UTICK0 => Utick,
+    ANACTRL => AnaCtrl,
+    RTC => Rtc,
+    USBPHY => UsbHsPhy,
+    RNG => Rng,
+    PUF => Puf,
+    PLU => Plu,
+    DMA0 => Dma0,
+    USB0 => UsbFsDevice,
+    SCT0 => Sct,
+    FLEXCOMM0 => Flexcomm(0),
+    FLEXCOMM1 => Flexcomm(1),
+    FLEXCOMM2 => Flexcomm(2),
+    FLEXCOMM3 => Flexcomm(3),
+    FLEXCOMM4 => Flexcomm(4),
+    FLEXCOMM5 => Flexcomm(5),
+    FLEXCOMM6 => Flexcomm(6),
+    FLEXCOMM7 => Flexcomm(7),
+    FLEXCOMM8 => Flexcomm(8),
+    MAILBOX => Mailbox,
+    USB1 => UsbHsDevice,
+    CRC_ENGINE => Crc,
+    SDIF => Sdio,
+    ADC0 => Adc,
+    USBFSH => UsbFsHost,
+    USBHSH => UsbHsHost,
+    HASHCRYPT => HashAes,
+    CASPER => Casper,
+    POWERQUAD => PowerQuad,
+    DMA1 => Dma1,
+}
@@ -1,219 +1,464 @@
 use super::IDAU;
+use core::ops::Range;
+
+pub mod crypto;
+pub mod flash;
+#[cfg(feature = "recovery")]
+pub mod recovery;
 
 const REGION_SIZE: u32 = 0x4000;
 const SRAM_REGION_SIZE: u32 = 0x2000;
 // pulled from https://docs.zephyrproject.org/latest/reference/kconfig/CONFIG_NRF_SPU_FLASH_REGION_SIZE.html
+const FLASH_BASE: u32 = 0x00000000;
+const RAM_BASE: u32 = 0x20000000;
+const FLASH_REGIONS: u32 = 64;
+const RAM_REGIONS: u32 = 64;
+
+crate::nrf_spu::impl_spu!(nrf5340_app_pac, "nrf53");
+
+/// Groups of peripherals that share a single SPU ID on the nRF5340 application core
+const SHARED_INSTANCES: &[(u8, &[&str])] = &[
+    (0x04, &["OSCILLATORS_NS", "REGULATORS_NS"]),
+    (0x05, &["CLOCK_NS", "POWER_NS", "RESET_NS"]),
+    (
+        0x08,
+        &["SPIM0_NS", "SPIS0_NS", "TWIM0_NS", "TWIS0_NS", "UARTE0_NS"],
+    ),
+    (
+        0x09,
+        &["SPIM1_NS", "SPIS1_NS", "TWIM1_NS", "TWIS1_NS", "UARTE1_NS"],
+    ),
+    (
+        0x0B,
+        &["SPIM2_NS", "SPIS2_NS", "TWIM2_NS", "TWIS2_NS", "UARTE2_NS"],
+    ),
+    (
+        0x0C,
+        &["SPIM3_NS", "SPIS3_NS", "TWIM3_NS", "TWIS3_NS", "UARTE3_NS"],
+    ),
+    (0x42, &["P0_NS", "P1_NS"]),
+];
+
+/// SPU wraps the nRF5340's System Protection Unit, along with the configuration that can't be
+/// expressed through `MemoryLayout`.
+pub struct SPU<'a> {
+    pub spu: &'a nrf5340_app_pac::spu_s::RegisterBlock,
+    /// GPIO pins that are kept secure, every other pin on P0 and P1 is made non-secure.
+    ///
+    /// Pin security is controlled entirely through this list, so it is an error to also pass
+    /// `P0_NS::perph()` or `P1_NS::perph()` while any pin is kept secure.
+    pub secure_pins: &'a [GpioPin],
+    /// Mask of the DPPI channels that are usable from the non-secure world, bit `n` is channel `n`.
+    /// Every other channel stays secure, so non-secure code can't trigger or subscribe to it.
+    ///
+    /// Non-secure code also needs `DPPIC_NS::perph()` to be passed to configure its channels.
+    pub non_secure_dppi_channels: u32,
+    /// Peripherals that are used by the secure firmware. It is an error to pass any peripheral that
+    /// shares an SPU ID with one of these, as that would hand it to the non-secure world as well.
+    pub secure_peripherals: &'a [NSPeripheral],
+    /// Peripherals whose SPU permission is left unlocked when passed, so they can be lent and reclaimed at
+    /// runtime through [`crate::ReassignPeripheral`]. Every other passed peripheral is locked.
+    pub lendable_peripherals: &'a [NSPeripheral],
+    /// Called from [`handle_access_error`] whenever non-secure code violates the SPU's permissions
+    pub access_error_hook: Option<fn(AccessError)>,
+    /// How flash and RAM ranges that don't line up with SPU region boundaries are handled
+    pub alignment: AlignmentPolicy,
+    /// Additional non-secure-callable regions, on top of `MemoryLayout::nsc_flash_region`.
+    ///
+    /// The SPU has two flash and two RAM NSC slots, each region is assigned to a free slot based on
+    /// its address. A region must end inside of the SPU region it starts in, and the distance from its
+    /// start to the end of that SPU region must be a power of two between 32 and 4096 bytes.
+    /// RAM regions also need to be covered by the secure, executable RAM region.
+    pub nsc_regions: &'a [Range<u32>],
+    /// Configures the network core's security, and whether it is released from reset during boot
+    pub network_core: Option<NetworkCore>,
+    /// KMU key slots whose read and write permissions are removed during boot, leaving them push-only.
+    /// This is permanent, see [`crypto::Kmu::lock`].
+    pub locked_key_slots: &'a [u8],
+}
+
+impl<'a> SPU<'a> {
+    pub fn new(spu: &'a nrf5340_app_pac::spu_s::RegisterBlock) -> Self {
+        SPU {
+            spu,
+            secure_pins: &[],
+            non_secure_dppi_channels: 0,
+            secure_peripherals: &[],
+            lendable_peripherals: &[],
+            access_error_hook: None,
+            alignment: AlignmentPolicy::Strict,
+            nsc_regions: &[],
+            network_core: None,
+            locked_key_slots: &[],
+        }
+    }
+
+    /// Returns the SPU regions each range of `layout` will be mapped to
+    pub fn region_plan(&self, layout: &crate::MemoryLayout) -> Result<RegionPlan, Error> {
+        Ok(RegionPlan {
+            secure_flash: self.flash_regions(&layout.secure_flash_region, true)?,
+            non_secure_flash: self.flash_regions(&layout.non_secure_flash_region, false)?,
+            secure_ram: self.ram_regions(&layout.secure_ram_region, true)?,
+            non_secure_ram: self.ram_regions(&layout.non_secure_ram_region, false)?,
+        })
+    }
+
+    /// Checks that the passed peripherals don't conflict with the rest of the SPU's configuration
+    pub fn validate(&self, peripherals: &[NSPeripheral]) -> Result<(), Error> {
+        self.validate_pins()?;
+        self.validate_ipc()?;
+        for perph in peripherals {
+            self.validate_peripheral(perph)?;
+        }
+        Ok(())
+    }
+
+    fn validate_pins(&self) -> Result<(), Error> {
+        match self.secure_pins.iter().find(|pin| !pin.is_valid()) {
+            Some(pin) => Err(Error::InvalidPin(*pin)),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_ipc(&self) -> Result<(), Error> {
+        match self.network_core.and_then(|core| core.ipc) {
+            Some(ipc) if ipc.send >= IPC_CHANNELS || ipc.receive >= IPC_CHANNELS => {
+                Err(Error::InvalidIpcChannel(ipc))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The SPU region indexes that each range of a `MemoryLayout` maps to
+#[derive(Debug, PartialEq, Eq)]
+pub struct RegionPlan {
+    pub secure_flash: Range<usize>,
+    pub non_secure_flash: Range<usize>,
+    pub secure_ram: Range<usize>,
+    pub non_secure_ram: Range<usize>,
+}
+
+impl RegionPlan {
+    /// Prints each range, along with the addresses the SPU will actually protect
+    pub fn print(&self) {
+        let flash = [
+            ("secure flash", &self.secure_flash),
+            ("non-secure flash", &self.non_secure_flash),
+        ];
+        for (name, regions) in flash {
+            defmt::println!(
+                "{}: regions {}..{} ({:x}..{:x})",
+                name,
+                regions.start,
+                regions.end,
+                FLASH_BASE + regions.start as u32 * REGION_SIZE,
+                FLASH_BASE + regions.end as u32 * REGION_SIZE
+            );
+        }
+        let ram = [
+            ("secure RAM", &self.secure_ram),
+            ("non-secure RAM", &self.non_secure_ram),
+        ];
+        for (name, regions) in ram {
+            defmt::println!(
+                "{}: regions {}..{} ({:x}..{:x})",
+                name,
+                regions.start,
+                regions.end,
+                RAM_BASE + regions.start as u32 * SRAM_REGION_SIZE,
+                RAM_BASE + regions.end as u32 * SRAM_REGION_SIZE
+            );
+        }
+    }
+}
+
+/// A GPIO pin on either port of the nRF5340.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum GpioPin {
+    P0(u8),
+    P1(u8),
+}
+
+impl GpioPin {
+    /// Returns false if the pin doesn't exist, P0 has 32 pins and P1 has 16
+    pub fn is_valid(&self) -> bool {
+        match self {
+            GpioPin::P0(pin) => *pin < 32,
+            GpioPin::P1(pin) => *pin < 16,
+        }
+    }
+}
 
-pub struct NSPeripheral(u8);
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// A GPIO port was passed to the non-secure world while one of its pins is kept secure
+    SecurePinOnPassedPort(GpioPin),
+    /// The pin is beyond the end of its port
+    InvalidPin(GpioPin),
+    /// The IPC peripheral only has 16 channels
+    InvalidIpcChannel(IpcChannel),
+    /// A peripheral was passed to the non-secure world that shares an SPU ID with one used by secure code
+    SharedPeripheralId {
+        passed: NSPeripheral,
+        secure: NSPeripheral,
+    },
+    /// The range doesn't start and end on an SPU region boundary
+    UnalignedRegion { start: u32, end: u32 },
+    /// The range falls outside of the flash or RAM covered by the SPU
+    RegionOutOfBounds { start: u32, end: u32 },
+    /// An NSC region must be a power of two between 32 and 4096 bytes
+    InvalidNscSize(u32),
+    /// Every FLASHNSC or RAMNSC slot is already in use
+    NoFreeNscSlot,
+    /// The peripheral isn't in `SPU::lendable_peripherals`
+    NotLendable(NSPeripheral),
+    /// The peripheral's SPU permission is locked, so it can't be reassigned until reset
+    PeripheralLocked(NSPeripheral),
+}
 
-impl IDAU for nrf5340_app_pac::spu_s::RegisterBlock {
+impl IDAU for SPU<'_> {
     type Peripheral = NSPeripheral;
 
     fn set_flash_region_params(&self, region: core::ops::Range<u32>, params: crate::RegionParams) {
-        for i in (region.start / REGION_SIZE)..(region.end / REGION_SIZE) {
-            self.flashregion[i as usize].perm.write(|w| {
-                if params.write {
-                    w.write().enable();
-                } else {
-                    w.write().disable();
-                }
-                if params.read {
-                    w.read().enable();
-                } else {
-                    w.read().disable();
-                }
-                if params.execute {
-                    w.execute().enable();
-                } else {
-                    w.execute().disable();
-                }
-                if params.lock {
-                    w.lock().locked();
-                } else {
-                    w.lock().unlocked();
-                }
-                if params.secure {
-                    w.secattr().secure();
-                } else {
-                    w.secattr().non_secure();
-                }
-                w
-            })
-        }
+        self.write_flash_regions(region, &params);
     }
 
     fn set_memory_region_params(&self, region: core::ops::Range<u32>, params: crate::RegionParams) {
-        for i in (region.start / SRAM_REGION_SIZE)..(region.end / SRAM_REGION_SIZE) {
-            self.ramregion[i as usize].perm.write(|w| {
-                if params.write {
-                    w.write().enable();
-                } else {
-                    w.write().disable();
-                }
-                if params.read {
-                    w.read().enable();
-                } else {
-                    w.read().disable();
-                }
-                if params.execute {
-                    w.execute().enable();
-                } else {
-                    w.execute().disable();
-                }
-                if params.lock {
-                    w.lock().locked();
-                } else {
-                    w.lock().unlocked();
-                }
-                if params.secure {
-                    w.secattr().secure();
-                } else {
-                    w.secattr().non_secure();
-                }
-                w
-            })
+        let regions = match self.ram_regions(&region, params.secure) {
+            Ok(regions) => regions,
+            Err(err) => defmt::panic!("invalid RAM region: {:?}", err),
+        };
+        for i in regions {
+            self.write_ram_region(i, &params, params.execute);
         }
     }
 
     fn set_nsc_region(&self, region: core::ops::Range<u32>) {
-        let sg_start = region.start;
-        let nsc_size = REGION_SIZE - (sg_start % REGION_SIZE);
-        let size_reg = (31 - nsc_size.leading_zeros()) - 4;
-        let region_reg = (sg_start as u32 / REGION_SIZE) & 0x3F; // x << SPU_FLASHNSC_REGION_REGION_Pos & SPU_FLASHNSC_REGION_REGION_Msk
-        self.flashnsc[0].size.write(|w| {
-            unsafe {
-                w.bits(size_reg);
-            }
-            w
-        });
-        self.flashnsc[0].region.write(|w| {
-            unsafe {
-                w.bits(region_reg);
-            }
-            w
-        });
+        self.write_nsc_region(region);
     }
 
     fn pass_peripheral_non_secure(&self, perph: &Self::Peripheral) {
-        let id = perph.0 as usize;
+        self.pass_peripheral(perph);
+    }
 
-        let peripherals = unsafe { cortex_m::Peripherals::steal() };
-        // disable each interupt
-        unsafe { peripherals.NVIC.icer[id / 32].write(1 << (id % 32)) }
+    fn peripheral_interrupts(&self, perph: &Self::Peripheral, f: &mut dyn FnMut(usize)) {
+        // the SPU ID doubles as the interrupt number
+        f(perph.id as usize);
+    }
 
-        // allow the peripheral to be accessed from non-secure memory
-        self.periphid[id].perm.write(|w| {
-            w.secattr().non_secure().lock().locked();
-            w
-        });
-        // allow the interupt to be registed from non-secure memory
-        unsafe {
-            peripherals.NVIC.itns[id / 32].modify(|w| w | 1 << (id & 0x1F));
+    fn prepare_boot(&self) {
+        if let Err(err) = self.validate_pins().and_then(|_| self.validate_ipc()) {
+            defmt::panic!("invalid SPU configuration: {:?}", err);
+        }
+        if let Some(network_core) = &self.network_core {
+            network_core.configure(self.spu);
         }
+        let kmu = crypto::Kmu::new();
+        for slot in self.locked_key_slots {
+            if let Err(err) = kmu.lock(*slot) {
+                defmt::panic!("failed to lock key slot: {:?}", err);
+            }
+        }
+        let mut secure_pins = [0u32; 2];
+        for pin in self.secure_pins {
+            match pin {
+                GpioPin::P0(p) => secure_pins[0] |= 1 << p,
+                GpioPin::P1(p) => secure_pins[1] |= 1 << p,
+            }
+        }
+        self.prepare_spu(&secure_pins);
     }
 
-    fn prepare_boot(&self) {
-        unsafe {
-            self.gpioport[0].perm.write_with_zero(|w| w);
-            self.gpioport[1].perm.write_with_zero(|w| w);
+    fn report(&self) -> crate::audit::SecurityMap {
+        self.spu_report()
+    }
+}
 
-            let sau = &*cortex_m::peripheral::SAU::PTR;
-            // disable SAU
-            sau.ctrl.modify(|mut ctrl| {
-                ctrl.0 &= !1;
-                ctrl.0 |= 1 << 1;
-                ctrl
-            });
+/// NetworkCore controls how the nRF5340's network core is brought up by the bootloader.
+///
+/// The network core's accesses to the application core's memory and peripherals go through the SPU's
+/// EXTDOMAIN setting. When it is non-secure any RAM shared with the network core (e.g. for IPC) must be in
+/// the non-secure RAM region. The network core's own DCNF protection registers can only be set from the
+/// network core, so they are left to its firmware.
+#[derive(Clone, Copy, Debug)]
+pub struct NetworkCore {
+    /// Marks the network core's accesses to the application domain as secure
+    pub secure: bool,
+    /// Releases the network core from reset (`RESET.NETWORK.FORCEOFF`)
+    pub release: bool,
+    /// Called before releasing the network core, it stays held in reset if this returns false.
+    /// The application core can't read the network core's flash, so verification usually happens
+    /// against an image staged in shared RAM or reported back by the network core's bootloader.
+    pub verify: Option<fn() -> bool>,
+    /// Secure IPC channel used to talk to the network core
+    pub ipc: Option<IpcChannel>,
+}
+
+impl NetworkCore {
+    fn configure(&self, spu: &nrf5340_app_pac::spu_s::RegisterBlock) {
+        spu.extdomain[0].perm.write(|w| {
+            if self.secure {
+                w.secattr().secure();
+            } else {
+                w.secattr().non_secure();
+            }
+            w.lock().locked();
+            w
+        });
+        if let Some(ipc) = &self.ipc {
+            ipc.configure();
+        }
+        if !self.release {
+            return;
         }
+        if let Some(verify) = self.verify {
+            if !verify() {
+                defmt::error!("network core verification failed, holding it in reset");
+                return;
+            }
+        }
+        let reset = unsafe { &*nrf5340_app_pac::RESET_S::PTR };
+        reset.network.forceoff.write(|w| w.forceoff().release());
     }
 }
 
-fn get_perph_id<T>(reg_block: *const T) -> u8 {
-    let base_addr = reg_block as u32;
-    (base_addr >> 12) as u8
+/// Number of channels on the IPC peripheral
+const IPC_CHANNELS: usize = 16;
+
+/// A pair of IPC channels on the secure IPC peripheral, used to signal the network core.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct IpcChannel {
+    /// Channel triggered by [`IpcChannel::send`]
+    pub send: usize,
+    /// Channel the network core signals on
+    pub receive: usize,
 }
 
-pub trait PerphExt {
-    fn perph() -> NSPeripheral;
+impl IpcChannel {
+    fn configure(&self) {
+        let ipc = unsafe { &*nrf5340_app_pac::IPC_S::PTR };
+        ipc.send_cnf[self.send].write(|w| unsafe { w.bits(1 << self.send) });
+        ipc.receive_cnf[self.receive].write(|w| unsafe { w.bits(1 << self.receive) });
+    }
+
+    /// Signals the network core
+    pub fn send(&self) {
+        let ipc = unsafe { &*nrf5340_app_pac::IPC_S::PTR };
+        ipc.tasks_send[self.send].write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Returns true, and clears the event, if the network core has signalled us
+    pub fn received(&self) -> bool {
+        let ipc = unsafe { &*nrf5340_app_pac::IPC_S::PTR };
+        if ipc.events_receive[self.receive].read().bits() != 0 {
+            ipc.events_receive[self.receive].write(|w| unsafe { w.bits(0) });
+            true
+        } else {
+            false
+        }
+    }
 }
 
+/// Implements `PerphExt` for the non-secure alias of a peripheral, and `IntoNonSecure` for its secure alias. Secure
+/// code uses the `_S` alias, so passing it to `boot` consumes the handle secure code would otherwise keep using.
+/// Peripherals without a secure alias are passed themselves.
 macro_rules! impl_perph {
-    ($s:ty) => {
-        impl PerphExt for $s {
+    ($ns:ident) => {
+        impl_perph! { @perph $ns }
+        impl crate::IntoNonSecure<NSPeripheral> for nrf5340_app_pac::$ns {
+            fn into_non_secure(self) -> NSPeripheral {
+                Self::perph()
+            }
+        }
+    };
+    ($ns:ident, $s:ident) => {
+        impl_perph! { @perph $ns }
+        impl crate::IntoNonSecure<NSPeripheral> for nrf5340_app_pac::$s {
+            fn into_non_secure(self) -> NSPeripheral {
+                nrf5340_app_pac::$ns::perph()
+            }
+        }
+    };
+    (@perph $ns:ident) => {
+        impl PerphExt for nrf5340_app_pac::$ns {
             fn perph() -> NSPeripheral {
-                NSPeripheral(get_perph_id(<$s>::PTR))
+                NSPeripheral {
+                    id: get_perph_id(nrf5340_app_pac::$ns::PTR),
+                    name: stringify!($ns),
+                }
             }
         }
     };
 }
 
-impl_perph! { nrf5340_app_pac::CLOCK_NS }
-impl_perph! { nrf5340_app_pac::COMP_NS }
-impl_perph! { nrf5340_app_pac::CTRLAP_NS }
-impl_perph! { nrf5340_app_pac::DCNF_NS }
-impl_perph! { nrf5340_app_pac::DPPIC_NS }
-impl_perph! { nrf5340_app_pac::DWT }
-impl_perph! { nrf5340_app_pac::EGU0_NS }
-impl_perph! { nrf5340_app_pac::EGU1_NS }
-impl_perph! { nrf5340_app_pac::EGU2_NS }
-impl_perph! { nrf5340_app_pac::EGU3_NS }
-impl_perph! { nrf5340_app_pac::EGU4_NS }
-impl_perph! { nrf5340_app_pac::EGU5_NS }
-impl_perph! { nrf5340_app_pac::FPU_NS }
-impl_perph! { nrf5340_app_pac::GPIOTE1_NS }
-impl_perph! { nrf5340_app_pac::I2S0_NS }
-impl_perph! { nrf5340_app_pac::IPC_NS }
-impl_perph! { nrf5340_app_pac::KMU_NS }
-impl_perph! { nrf5340_app_pac::LPCOMP_NS }
-impl_perph! { nrf5340_app_pac::MPU }
-impl_perph! { nrf5340_app_pac::MUTEX_NS }
-impl_perph! { nrf5340_app_pac::NFCT_NS }
-impl_perph! { nrf5340_app_pac::NVIC }
-impl_perph! { nrf5340_app_pac::NVMC_NS }
-impl_perph! { nrf5340_app_pac::OSCILLATORS_NS }
-impl_perph! { nrf5340_app_pac::P0_NS }
-impl_perph! { nrf5340_app_pac::P1_NS }
-impl_perph! { nrf5340_app_pac::PDM0_NS }
-impl_perph! { nrf5340_app_pac::POWER_NS }
-impl_perph! { nrf5340_app_pac::PWM0_NS }
-impl_perph! { nrf5340_app_pac::PWM1_NS }
-impl_perph! { nrf5340_app_pac::PWM2_NS }
-impl_perph! { nrf5340_app_pac::PWM3_NS }
-impl_perph! { nrf5340_app_pac::QDEC0_NS }
-impl_perph! { nrf5340_app_pac::QDEC1_NS }
-impl_perph! { nrf5340_app_pac::QSPI_NS }
-impl_perph! { nrf5340_app_pac::REGULATORS_NS }
-impl_perph! { nrf5340_app_pac::RESET_NS }
-impl_perph! { nrf5340_app_pac::RTC0_NS }
-impl_perph! { nrf5340_app_pac::RTC1_NS }
-impl_perph! { nrf5340_app_pac::SAADC_NS }
-impl_perph! { nrf5340_app_pac::SPIM0_NS }
-impl_perph! { nrf5340_app_pac::SPIM1_NS }
-impl_perph! { nrf5340_app_pac::SPIM2_NS }
-impl_perph! { nrf5340_app_pac::SPIM3_NS }
-impl_perph! { nrf5340_app_pac::SPIM4_NS }
-impl_perph! { nrf5340_app_pac::SPIS0_NS }
-impl_perph! { nrf5340_app_pac::SPIS1_NS }
-impl_perph! { nrf5340_app_pac::SPIS2_NS }
-impl_perph! { nrf5340_app_pac::SPIS3_NS }
-impl_perph! { nrf5340_app_pac::TIMER0_NS }
-impl_perph! { nrf5340_app_pac::TIMER1_NS }
-impl_perph! { nrf5340_app_pac::TIMER2_NS }
-impl_perph! { nrf5340_app_pac::TPIU }
-impl_perph! { nrf5340_app_pac::TWIM0_NS }
-impl_perph! { nrf5340_app_pac::TWIM1_NS }
-impl_perph! { nrf5340_app_pac::TWIM2_NS }
-impl_perph! { nrf5340_app_pac::TWIM3_NS }
-impl_perph! { nrf5340_app_pac::TWIS0_NS }
-impl_perph! { nrf5340_app_pac::TWIS1_NS }
-impl_perph! { nrf5340_app_pac::TWIS2_NS }
-impl_perph! { nrf5340_app_pac::TWIS3_NS }
-impl_perph! { nrf5340_app_pac::UARTE0_NS }
-impl_perph! { nrf5340_app_pac::UARTE1_NS }
-impl_perph! { nrf5340_app_pac::UARTE2_NS }
-impl_perph! { nrf5340_app_pac::UARTE3_NS }
-impl_perph! { nrf5340_app_pac::USBD_NS }
-impl_perph! { nrf5340_app_pac::USBREGULATOR_NS }
-impl_perph! { nrf5340_app_pac::VMC_NS }
-impl_perph! { nrf5340_app_pac::WDT0_NS }
-impl_perph! { nrf5340_app_pac::WDT1_NS }
+impl_perph! { CLOCK_NS, CLOCK_S }
+impl_perph! { COMP_NS, COMP_S }
+impl_perph! { CTRLAP_NS, CTRLAP_S }
+impl_perph! { DCNF_NS, DCNF_S }
+impl_perph! { DPPIC_NS, DPPIC_S }
+impl_perph! { DWT }
+impl_perph! { EGU0_NS, EGU0_S }
+impl_perph! { EGU1_NS, EGU1_S }
+impl_perph! { EGU2_NS, EGU2_S }
+impl_perph! { EGU3_NS, EGU3_S }
+impl_perph! { EGU4_NS, EGU4_S }
+impl_perph! { EGU5_NS, EGU5_S }
+impl_perph! { FPU_NS, FPU_S }
+impl_perph! { GPIOTE1_NS }
+impl_perph! { I2S0_NS, I2S0_S }
+impl_perph! { IPC_NS, IPC_S }
+impl_perph! { KMU_NS, KMU_S }
+impl_perph! { LPCOMP_NS, LPCOMP_S }
+impl_perph! { MPU }
+impl_perph! { MUTEX_NS, MUTEX_S }
+impl_perph! { NFCT_NS, NFCT_S }
+impl_perph! { NVIC }
+impl_perph! { NVMC_NS, NVMC_S }
+impl_perph! { OSCILLATORS_NS, OSCILLATORS_S }
+impl_perph! { P0_NS, P0_S }
+impl_perph! { P1_NS, P1_S }
+impl_perph! { PDM0_NS, PDM0_S }
+impl_perph! { POWER_NS, POWER_S }
+impl_perph! { PWM0_NS, PWM0_S }
+impl_perph! { PWM1_NS, PWM1_S }
+impl_perph! { PWM2_NS, PWM2_S }
+impl_perph! { PWM3_NS, PWM3_S }
+impl_perph! { QDEC0_NS, QDEC0_S }
+impl_perph! { QDEC1_NS, QDEC1_S }
+impl_perph! { QSPI_NS, QSPI_S }
+impl_perph! { REGULATORS_NS, REGULATORS_S }
+impl_perph! { RESET_NS, RESET_S }
+impl_perph! { RTC0_NS, RTC0_S }
+impl_perph! { RTC1_NS, RTC1_S }
+impl_perph! { SAADC_NS, SAADC_S }
+impl_perph! { SPIM0_NS, SPIM0_S }
+impl_perph! { SPIM1_NS, SPIM1_S }
+impl_perph! { SPIM2_NS, SPIM2_S }
+impl_perph! { SPIM3_NS, SPIM3_S }
+impl_perph! { SPIM4_NS, SPIM4_S }
+impl_perph! { SPIS0_NS, SPIS0_S }
+impl_perph! { SPIS1_NS, SPIS1_S }
+impl_perph! { SPIS2_NS, SPIS2_S }
+impl_perph! { SPIS3_NS, SPIS3_S }
+impl_perph! { TIMER0_NS, TIMER0_S }
+impl_perph! { TIMER1_NS, TIMER1_S }
+impl_perph! { TIMER2_NS, TIMER2_S }
+impl_perph! { TPIU }
+impl_perph! { TWIM0_NS, TWIM0_S }
+impl_perph! { TWIM1_NS, TWIM1_S }
+impl_perph! { TWIM2_NS, TWIM2_S }
+impl_perph! { TWIM3_NS, TWIM3_S }
+impl_perph! { TWIS0_NS, TWIS0_S }
+impl_perph! { TWIS1_NS, TWIS1_S }
+impl_perph! { TWIS2_NS, TWIS2_S }
+impl_perph! { TWIS3_NS, TWIS3_S }
+impl_perph! { UARTE0_NS, UARTE0_S }
+impl_perph! { UARTE1_NS, UARTE1_S }
+impl_perph! { UARTE2_NS, UARTE2_S }
+impl_perph! { UARTE3_NS, UARTE3_S }
+impl_perph! { USBD_NS, USBD_S }
+impl_perph! { USBREGULATOR_NS, USBREGULATOR_S }
+impl_perph! { VMC_NS, VMC_S }
+impl_perph! { WDT0_NS, WDT0_S }
+impl_perph! { WDT1_NS, WDT1_S }
@@ -0,0 +1,531 @@
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Base address of the CryptoCell-312's own register map, which sits 0x1000 after `CRYPTOCELL_S`
+const CC_BASE: u32 = 0x50845000;
+
+// CryptoCell-312 register offsets
+const AES_KEY_0: u32 = 0x400;
+const AES_BUSY: u32 = 0x470;
+const AES_CONTROL: u32 = 0x4C0;
+const HASH_H0: u32 = 0x640;
+const HASH_CONTROL: u32 = 0x7C0;
+const HASH_PAD_EN: u32 = 0x7C4;
+const AES_CLK_ENABLE: u32 = 0x810;
+const HASH_CLK_ENABLE: u32 = 0x818;
+const DMA_CLK_ENABLE: u32 = 0x820;
+const CRYPTO_CTL: u32 = 0x900;
+const HASH_BUSY: u32 = 0x91C;
+const DIN_BUFFER: u32 = 0xC00;
+const DIN_CPU_DATA_SIZE: u32 = 0xC48;
+const DOUT_BUFFER: u32 = 0xD00;
+
+// CRYPTO_CTL modes
+const CRYPTO_CTL_AES: u32 = 0x1;
+const CRYPTO_CTL_HASH: u32 = 0x7;
+// HASH_CONTROL modes
+const HASH_CONTROL_SHA256: u32 = 0x2;
+
+/// Address of the CryptoCell's AES key registers, this is the destination KMU key slots should be
+/// provisioned with so they can be pushed into the AES engine.
+pub const CC_AES_KEY_DEST: u32 = CC_BASE + AES_KEY_0;
+
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+fn reg_read(offset: u32) -> u32 {
+    unsafe { read_volatile((CC_BASE + offset) as *const u32) }
+}
+
+fn reg_write(offset: u32, value: u32) {
+    unsafe { write_volatile((CC_BASE + offset) as *mut u32, value) }
+}
+
+/// The key used for an AES operation
+#[derive(Clone, Copy)]
+pub enum AesKey<'a> {
+    /// A 128 or 256 bit key held in software
+    Software(&'a [u8]),
+    /// A 128 bit key that has already been pushed into the CryptoCell from a KMU slot, see [`Kmu::push`].
+    /// The key material never passes through the CPU.
+    Kmu,
+}
+
+/// Number of live [`CryptoCell`] handles, the hardware stays powered while this isn't zero
+static USERS: AtomicUsize = AtomicUsize::new(0);
+
+/// CryptoCell drives the nRF5340's CryptoCell-312. It is powered on when the first handle is created and
+/// powered off when the last one is dropped, so a handle can be created wherever one is needed.
+///
+/// Hashing and AES run on the CryptoCell. Its PKA isn't driven, as it's only documented through Nordic's closed
+/// `nrf_cc3xx` library, so ECDSA verification only uses the CryptoCell for the digest and checks the signature in
+/// software with the `p256` crate (with the `ecdsa` feature enabled). ECDH and ECDSA signing run in software too.
+pub struct CryptoCell {
+    _private: (),
+}
+
+impl CryptoCell {
+    pub fn new() -> Self {
+        cortex_m::interrupt::free(|_| {
+            if USERS.fetch_add(1, Ordering::Relaxed) == 0 {
+                let cc = unsafe { &*nrf5340_app_pac::CRYPTOCELL_S::PTR };
+                cc.enable.write(|w| w.enable().enabled());
+                reg_write(AES_CLK_ENABLE, 1);
+                reg_write(HASH_CLK_ENABLE, 1);
+                reg_write(DMA_CLK_ENABLE, 1);
+            }
+        });
+        CryptoCell { _private: () }
+    }
+
+    /// Computes the SHA-256 digest of `data`
+    pub fn sha256(&self, data: &[u8]) -> [u8; 32] {
+        reg_write(CRYPTO_CTL, CRYPTO_CTL_HASH);
+        reg_write(HASH_CONTROL, HASH_CONTROL_SHA256);
+        // padding is done in software, so that every block fed to the engine is complete
+        reg_write(HASH_PAD_EN, 0);
+        for (i, h) in SHA256_IV.iter().enumerate() {
+            reg_write(HASH_H0 + 4 * i as u32, *h);
+        }
+        let mut chunks = data.chunks_exact(64);
+        for block in &mut chunks {
+            self.hash_block(block);
+        }
+        // final block(s): remaining data, 0x80, zeros, and the length in bits
+        let remainder = chunks.remainder();
+        let mut last = [0u8; 128];
+        last[..remainder.len()].copy_from_slice(remainder);
+        last[remainder.len()] = 0x80;
+        let len = if remainder.len() < 56 { 64 } else { 128 };
+        last[len - 8..len].copy_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+        for block in last[..len].chunks_exact(64) {
+            self.hash_block(block);
+        }
+        while reg_read(HASH_BUSY) != 0 {}
+        let mut digest = [0u8; 32];
+        for (i, word) in digest.chunks_exact_mut(4).enumerate() {
+            word.copy_from_slice(&reg_read(HASH_H0 + 4 * i as u32).to_be_bytes());
+        }
+        digest
+    }
+
+    fn hash_block(&self, block: &[u8]) {
+        while reg_read(HASH_BUSY) != 0 {}
+        reg_write(DIN_CPU_DATA_SIZE, block.len() as u32);
+        for word in block.chunks_exact(4) {
+            reg_write(
+                DIN_BUFFER,
+                u32::from_be_bytes([word[0], word[1], word[2], word[3]]),
+            );
+        }
+    }
+
+    /// Encrypts a single block in place using AES-ECB
+    pub fn aes_encrypt_block(&self, key: AesKey<'_>, block: &mut [u8; 16]) {
+        self.aes_block(key, block, false)
+    }
+
+    /// Decrypts a single block in place using AES-ECB
+    pub fn aes_decrypt_block(&self, key: AesKey<'_>, block: &mut [u8; 16]) {
+        self.aes_block(key, block, true)
+    }
+
+    fn aes_block(&self, key: AesKey<'_>, block: &mut [u8; 16], decrypt: bool) {
+        reg_write(CRYPTO_CTL, CRYPTO_CTL_AES);
+        let key_size = match key {
+            AesKey::Software(key) => {
+                for (i, word) in key.chunks_exact(4).enumerate() {
+                    reg_write(
+                        AES_KEY_0 + 4 * i as u32,
+                        u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+                    );
+                }
+                if key.len() == 32 {
+                    2
+                } else {
+                    0
+                }
+            }
+            AesKey::Kmu => 0,
+        };
+        // ECB, with DEC_KEY0 selecting decryption and NK_KEY0 the key size
+        reg_write(AES_CONTROL, key_size << 12 | decrypt as u32);
+        reg_write(DIN_CPU_DATA_SIZE, 16);
+        for word in block.chunks_exact(4) {
+            reg_write(
+                DIN_BUFFER,
+                u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+            );
+        }
+        while reg_read(AES_BUSY) != 0 {}
+        for word in block.chunks_exact_mut(4) {
+            word.copy_from_slice(&reg_read(DOUT_BUFFER).to_le_bytes());
+        }
+    }
+
+    /// Encrypts or decrypts `data` in place using AES-CTR, `counter` is the initial counter block
+    /// and is left holding the next counter value.
+    pub fn aes_ctr(&self, key: AesKey<'_>, counter: &mut [u8; 16], data: &mut [u8]) {
+        for chunk in data.chunks_mut(16) {
+            let mut keystream = *counter;
+            self.aes_encrypt_block(key, &mut keystream);
+            for (d, k) in chunk.iter_mut().zip(keystream.iter()) {
+                *d ^= k;
+            }
+            // the counter is a 128-bit big endian integer
+            for byte in counter.iter_mut().rev() {
+                *byte = byte.wrapping_add(1);
+                if *byte != 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Verifies an ECDSA P-256 `signature` (raw r || s) over `message`.
+    /// `public_key` is a SEC1 encoded point. Only the digest is computed on the CryptoCell, see [`CryptoCell`].
+    #[cfg(feature = "ecdsa")]
+    pub fn verify_p256(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        let digest = self.sha256(message);
+        crate::ecdsa::verify_prehash(public_key, &digest, signature)
+    }
+}
+
+impl Default for CryptoCell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for CryptoCell {
+    fn drop(&mut self) {
+        cortex_m::interrupt::free(|_| {
+            if USERS.fetch_sub(1, Ordering::Relaxed) == 1 {
+                reg_write(AES_CLK_ENABLE, 0);
+                reg_write(HASH_CLK_ENABLE, 0);
+                reg_write(DMA_CLK_ENABLE, 0);
+                let cc = unsafe { &*nrf5340_app_pac::CRYPTOCELL_S::PTR };
+                cc.enable.write(|w| w.enable().disabled());
+            }
+        });
+    }
+}
+
+/// KmuCipher encrypts protected storage objects with AES-CTR on the CryptoCell, using a key pushed from a KMU slot.
+/// The slot should hold a device-unique key (e.g. generated from the RNG when the device is provisioned), and be
+/// locked so the key never passes through the CPU.
+pub struct KmuCipher {
+    pub slot: u8,
+}
+
+impl crate::storage::Cipher for KmuCipher {
+    fn apply_keystream(
+        &self,
+        nonce: &[u8; 16],
+        offset: u32,
+        data: &mut [u8],
+    ) -> Result<(), crate::storage::Error> {
+        let cc = CryptoCell::new();
+        Kmu::new()
+            .push(self.slot)
+            .map_err(|_| crate::storage::Error::Cipher)?;
+        let mut counter = (u128::from_be_bytes(*nonce) + (offset / 16) as u128).to_be_bytes();
+        cc.aes_ctr(AesKey::Kmu, &mut counter, data);
+        Ok(())
+    }
+}
+
+/// KmuKeyWrap unwraps the content keys of encrypted images with AES key wrap on the CryptoCell, using a key
+/// encryption key pushed from a KMU slot, so the KEK never passes through the CPU. The slot holds a 128 bit key.
+#[cfg(feature = "image")]
+pub struct KmuKeyWrap {
+    pub slot: u8,
+}
+
+#[cfg(feature = "image")]
+impl crate::image::encrypted::KeyUnwrap for KmuKeyWrap {
+    fn tlv(&self) -> u16 {
+        crate::image::encrypted::TLV_ENC_KW
+    }
+
+    fn unwrap(&self, wrapped: &[u8], key: &mut [u8]) -> Result<(), crate::image::Error> {
+        let cc = CryptoCell::new();
+        Kmu::new()
+            .push(self.slot)
+            .map_err(|_| crate::image::Error::Decryption)?;
+        crate::image::encrypted::aes_kw_unwrap(
+            |block| cc.aes_decrypt_block(AesKey::Kmu, block),
+            wrapped,
+            key,
+        )
+    }
+}
+
+/// CryptoCellBackend runs the crypto service's SHA-256 and AES-GCM on the CryptoCell, GHASH and the P-256 operations
+/// run in software. Random numbers come from `entropy`.
+#[cfg(feature = "crypto")]
+pub struct CryptoCellBackend {
+    pub entropy: fn(&mut [u8]) -> Result<(), crate::crypto::Error>,
+}
+
+#[cfg(feature = "crypto")]
+impl CryptoCellBackend {
+    /// Returns the GCM tag over `aad` and `ciphertext`, `j0` is the first counter block
+    fn gcm_tag(
+        cc: &CryptoCell,
+        key: &[u8],
+        j0: &[u8; 16],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> [u8; 16] {
+        use ghash::universal_hash::{NewUniversalHash, UniversalHash};
+        // the hash key is the encrypted zero block
+        let mut h = [0u8; 16];
+        cc.aes_encrypt_block(AesKey::Software(key), &mut h);
+        let mut ghash = ghash::GHash::new(&h.into());
+        ghash.update_padded(aad);
+        ghash.update_padded(ciphertext);
+        let mut lengths = [0u8; 16];
+        lengths[..8].copy_from_slice(&(aad.len() as u64 * 8).to_be_bytes());
+        lengths[8..].copy_from_slice(&(ciphertext.len() as u64 * 8).to_be_bytes());
+        ghash.update(&lengths.into());
+        let mut tag: [u8; 16] = ghash.finalize().into_bytes().into();
+        let mut mask = *j0;
+        cc.aes_encrypt_block(AesKey::Software(key), &mut mask);
+        for (t, m) in tag.iter_mut().zip(mask.iter()) {
+            *t ^= m;
+        }
+        tag
+    }
+
+    /// Returns the first counter block for a 96 bit nonce
+    fn gcm_j0(key: &[u8], nonce: &[u8; 12]) -> Result<[u8; 16], crate::crypto::Error> {
+        if key.len() != 16 && key.len() != 32 {
+            return Err(crate::crypto::Error::InvalidArgument);
+        }
+        let mut j0 = [0u8; 16];
+        j0[..12].copy_from_slice(nonce);
+        j0[15] = 1;
+        Ok(j0)
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl crate::crypto::Backend for CryptoCellBackend {
+    fn random(&self, buf: &mut [u8]) -> Result<(), crate::crypto::Error> {
+        (self.entropy)(buf)
+    }
+
+    fn sha256(&self, data: &[u8]) -> [u8; 32] {
+        CryptoCell::new().sha256(data)
+    }
+
+    fn aes_gcm_encrypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<[u8; 16], crate::crypto::Error> {
+        let j0 = Self::gcm_j0(key, nonce)?;
+        let cc = CryptoCell::new();
+        // the message is encrypted with the counter blocks following j0
+        let mut counter = j0;
+        counter[15] = 2;
+        cc.aes_ctr(AesKey::Software(key), &mut counter, buf);
+        Ok(Self::gcm_tag(&cc, key, &j0, aad, buf))
+    }
+
+    fn aes_gcm_decrypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8; 16],
+    ) -> Result<(), crate::crypto::Error> {
+        let j0 = Self::gcm_j0(key, nonce)?;
+        let cc = CryptoCell::new();
+        if !crate::crypto::ct_eq(&Self::gcm_tag(&cc, key, &j0, aad, buf), tag) {
+            return Err(crate::crypto::Error::InvalidSignature);
+        }
+        let mut counter = j0;
+        counter[15] = 2;
+        cc.aes_ctr(AesKey::Software(key), &mut counter, buf);
+        Ok(())
+    }
+}
+
+/// Number of key slots in the KMU, slot IDs run from 1 to 128
+pub const KEY_SLOTS: u8 = 128;
+
+// UICR key slot permission bits
+const PERM_WRITE: u32 = 1 << 0;
+const PERM_READ: u32 = 1 << 1;
+const PERM_PUSH: u32 = 1 << 2;
+const PERM_STATE: u32 = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum KmuError {
+    /// Key slots are numbered from 1 to 128
+    InvalidSlot(u8),
+    /// The slot has already been provisioned, UICR bits can only be cleared
+    SlotInUse(u8),
+    /// The KMU refused to push the key, usually because the slot has been revoked or lacks push permission
+    PushFailed(u8),
+}
+
+/// Permissions given to a key slot when it is provisioned
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct KeyPermissions {
+    /// Allows the key to be read back by the CPU, this should be false for anything but public data
+    pub read: bool,
+    /// Allows the key to be pushed to its destination
+    pub push: bool,
+}
+
+/// Kmu provisions keys into the nRF5340's UICR key slots and pushes them into the CryptoCell,
+/// without the key material being readable by software.
+pub struct Kmu {
+    _private: (),
+}
+
+impl Kmu {
+    pub fn new() -> Self {
+        Kmu { _private: () }
+    }
+
+    fn check_slot(slot: u8) -> Result<usize, KmuError> {
+        if slot == 0 || slot > KEY_SLOTS {
+            return Err(KmuError::InvalidSlot(slot));
+        }
+        Ok(slot as usize - 1)
+    }
+
+    /// Selects `slot` through SELECTKEYSLOT for the duration of `f`, which is called with the slot's UICR index.
+    /// A key slot's UICR registers can only be accessed, and its key pushed, while it is selected.
+    fn with_slot<R>(
+        &self,
+        slot: u8,
+        f: impl FnOnce(usize) -> Result<R, KmuError>,
+    ) -> Result<R, KmuError> {
+        let index = Self::check_slot(slot)?;
+        let kmu = unsafe { &*nrf5340_app_pac::KMU_S::PTR };
+        kmu.selectkeyslot.write(|w| unsafe { w.bits(slot as u32) });
+        let result = f(index);
+        kmu.selectkeyslot.write(|w| unsafe { w.bits(0) });
+        result
+    }
+
+    /// Writes a 128 bit key into `slot`, which will be pushed to `dest` (e.g. [`CC_AES_KEY_DEST`]).
+    /// A longer key has to be provisioned by the caller as 128 bit halves in consecutive slots, with `dest`
+    /// advanced by 16 bytes for the second half.
+    pub fn provision(
+        &self,
+        slot: u8,
+        key: &[u8; 16],
+        dest: u32,
+        perm: KeyPermissions,
+    ) -> Result<(), KmuError> {
+        self.with_slot(slot, |index| {
+            let uicr = unsafe { &*nrf5340_app_pac::UICR_S::PTR };
+            let config = &uicr.keyslot.config[index];
+            if config.dest.read().bits() != 0xFFFFFFFF {
+                return Err(KmuError::SlotInUse(slot));
+            }
+            let mut bits = PERM_STATE | PERM_WRITE;
+            if perm.read {
+                bits |= PERM_READ;
+            }
+            if perm.push {
+                bits |= PERM_PUSH;
+            }
+            with_uicr_write(|| {
+                for (i, word) in key.chunks_exact(4).enumerate() {
+                    let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                    uicr.keyslot.key[index].value[i].write(|w| unsafe { w.bits(value) });
+                    wait_nvmc();
+                }
+                config.dest.write(|w| unsafe { w.bits(dest) });
+                wait_nvmc();
+                // write permission is removed last, so the slot can't be changed after provisioning
+                config.perm.write(|w| unsafe { w.bits(bits & !PERM_WRITE) });
+                wait_nvmc();
+            });
+            Ok(())
+        })
+    }
+
+    /// Pushes the key in `slot` to its destination
+    pub fn push(&self, slot: u8) -> Result<(), KmuError> {
+        self.with_slot(slot, |_| {
+            let kmu = unsafe { &*nrf5340_app_pac::KMU_S::PTR };
+            kmu.tasks_push_keyslot.write(|w| unsafe { w.bits(1) });
+            let pushed = loop {
+                if kmu.events_keyslot_pushed.read().bits() != 0 {
+                    break true;
+                }
+                if kmu.events_keyslot_error.read().bits() != 0
+                    || kmu.events_keyslot_revoked.read().bits() != 0
+                {
+                    break false;
+                }
+            };
+            kmu.events_keyslot_pushed.write(|w| unsafe { w.bits(0) });
+            kmu.events_keyslot_error.write(|w| unsafe { w.bits(0) });
+            kmu.events_keyslot_revoked.write(|w| unsafe { w.bits(0) });
+            if pushed {
+                Ok(())
+            } else {
+                Err(KmuError::PushFailed(slot))
+            }
+        })
+    }
+
+    /// Removes read access to `slot`, so the key can only be pushed. This is permanent.
+    pub fn lock(&self, slot: u8) -> Result<(), KmuError> {
+        self.clear_perm(slot, PERM_READ | PERM_WRITE)
+    }
+
+    /// Revokes `slot`, after which it can no longer be pushed or read. This is permanent.
+    pub fn revoke(&self, slot: u8) -> Result<(), KmuError> {
+        self.clear_perm(slot, PERM_STATE | PERM_READ | PERM_WRITE | PERM_PUSH)
+    }
+
+    fn clear_perm(&self, slot: u8, mask: u32) -> Result<(), KmuError> {
+        self.with_slot(slot, |index| {
+            let uicr = unsafe { &*nrf5340_app_pac::UICR_S::PTR };
+            let perm = &uicr.keyslot.config[index].perm;
+            let bits = perm.read().bits();
+            if bits & mask != 0 {
+                with_uicr_write(|| {
+                    perm.write(|w| unsafe { w.bits(bits & !mask) });
+                    wait_nvmc();
+                });
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Default for Kmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Enables NVMC writes for the duration of `f`, which is needed to program the UICR
+fn with_uicr_write(f: impl FnOnce()) {
+    let nvmc = unsafe { &*nrf5340_app_pac::NVMC_S::PTR };
+    nvmc.config.write(|w| w.wen().wen());
+    wait_nvmc();
+    f();
+    nvmc.config.write(|w| w.wen().ren());
+    wait_nvmc();
+}
+
+fn wait_nvmc() {
+    let nvmc = unsafe { &*nrf5340_app_pac::NVMC_S::PTR };
+    while nvmc.ready.read().ready().is_busy() {}
+}
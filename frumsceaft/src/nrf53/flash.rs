@@ -0,0 +1,91 @@
+use crate::flash::{Error, Flash};
+use core::ptr::{read_volatile, write_volatile};
+
+const FLASH_SIZE: u32 = 0x100000;
+const PAGE_SIZE: u32 = 0x1000;
+
+/// Nvmc programs the nRF5340's application core flash through the secure NVMC.
+///
+/// The flash being written must be secure and writable in the SPU, which is the case for the secure flash region
+/// set up by `boot`.
+pub struct Nvmc {
+    _private: (),
+}
+
+impl Nvmc {
+    pub fn new() -> Self {
+        Nvmc { _private: () }
+    }
+
+    fn with_config(&self, een: bool, f: impl FnOnce()) {
+        let nvmc = unsafe { &*nrf5340_app_pac::NVMC_S::PTR };
+        if een {
+            nvmc.config.write(|w| w.wen().een());
+        } else {
+            nvmc.config.write(|w| w.wen().wen());
+        }
+        wait_ready();
+        f();
+        nvmc.config.write(|w| w.wen().ren());
+        wait_ready();
+    }
+}
+
+impl Default for Nvmc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn wait_ready() {
+    let nvmc = unsafe { &*nrf5340_app_pac::NVMC_S::PTR };
+    while nvmc.ready.read().ready().is_busy() {}
+}
+
+fn check_bounds(address: u32, len: usize) -> Result<(), Error> {
+    match address.checked_add(len as u32) {
+        Some(end) if end <= FLASH_SIZE => Ok(()),
+        _ => Err(Error::OutOfBounds),
+    }
+}
+
+impl Flash for Nvmc {
+    const ERASE_SIZE: u32 = PAGE_SIZE;
+    const WRITE_SIZE: u32 = 4;
+
+    fn read(&self, address: u32, buf: &mut [u8]) -> Result<(), Error> {
+        check_bounds(address, buf.len())?;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = unsafe { read_volatile((address + i as u32) as *const u8) };
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        check_bounds(address, data.len())?;
+        if address % Self::WRITE_SIZE != 0 || data.len() as u32 % Self::WRITE_SIZE != 0 {
+            return Err(Error::Unaligned);
+        }
+        self.with_config(false, || {
+            for (i, word) in data.chunks_exact(4).enumerate() {
+                let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                unsafe { write_volatile((address + 4 * i as u32) as *mut u32, value) };
+                wait_ready();
+            }
+        });
+        Ok(())
+    }
+
+    fn erase(&mut self, address: u32) -> Result<(), Error> {
+        check_bounds(address, PAGE_SIZE as usize)?;
+        if address % PAGE_SIZE != 0 {
+            return Err(Error::Unaligned);
+        }
+        // writing 0xFFFFFFFF to the start of a page erases it while erasing is enabled
+        self.with_config(true, || {
+            unsafe { write_volatile(address as *mut u32, 0xFFFFFFFF) };
+            wait_ready();
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! The secure UARTE and recovery pin for [`recovery`](crate::recovery) on the nRF5340.
+use super::GpioPin;
+use crate::recovery::Serial;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{compiler_fence, Ordering};
+
+// UARTE register offsets
+const TASKS_STARTRX: u32 = 0x000;
+const TASKS_STARTTX: u32 = 0x008;
+const EVENTS_ENDRX: u32 = 0x110;
+const EVENTS_ENDTX: u32 = 0x120;
+const ENABLE: u32 = 0x500;
+const PSEL_TXD: u32 = 0x50C;
+const PSEL_RXD: u32 = 0x514;
+const BAUDRATE: u32 = 0x524;
+const RXD_PTR: u32 = 0x534;
+const RXD_MAXCNT: u32 = 0x538;
+const TXD_PTR: u32 = 0x544;
+const TXD_MAXCNT: u32 = 0x548;
+const CONFIG: u32 = 0x56C;
+const ENABLE_UARTE: u32 = 8;
+
+// the secure GPIO ports, and their register offsets
+const P0_S: u32 = 0x5084_2500;
+const P1_S: u32 = 0x5084_2800;
+const GPIO_OUTSET: u32 = 0x008;
+const GPIO_IN: u32 = 0x010;
+const GPIO_DIRSET: u32 = 0x018;
+const GPIO_PIN_CNF: u32 = 0x200;
+// PIN_CNF values
+const PIN_CNF_INPUT_PULLUP: u32 = 3 << 2;
+const PIN_CNF_DISCONNECTED: u32 = 1 << 1;
+
+// BAUDRATE values
+pub const BAUD_115200: u32 = 0x01D6_0000;
+pub const BAUD_1M: u32 = 0x1000_0000;
+
+/// Size of the buffer data is sent through, EasyDMA can only read from RAM
+const TX_CHUNK: usize = 64;
+
+/// Returns the secure GPIO port's base address and the pin's number in it
+fn port(pin: GpioPin) -> (u32, u8) {
+    if !pin.is_valid() {
+        defmt::panic!("invalid pin {:?}", pin);
+    }
+    match pin {
+        GpioPin::P0(pin) => (P0_S, pin),
+        GpioPin::P1(pin) => (P1_S, pin),
+    }
+}
+
+/// The PSEL value that connects a peripheral to `pin`
+fn psel(pin: GpioPin) -> u32 {
+    if !pin.is_valid() {
+        defmt::panic!("invalid pin {:?}", pin);
+    }
+    match pin {
+        GpioPin::P0(pin) => pin as u32,
+        GpioPin::P1(pin) => 1 << 5 | pin as u32,
+    }
+}
+
+fn reg_read(address: u32) -> u32 {
+    unsafe { read_volatile(address as *const u32) }
+}
+
+fn reg_write(address: u32, value: u32) {
+    unsafe { write_volatile(address as *mut u32, value) }
+}
+
+/// Returns true if `pin` is held low, reading it with the pull-up enabled. This is meant to be called straight
+/// after reset, while every pin is still secure, to decide whether to enter recovery. The pin is left disconnected.
+pub fn pin_held(pin: GpioPin) -> bool {
+    let (gpio, pin) = port(pin);
+    let cnf = gpio + GPIO_PIN_CNF + 4 * pin as u32;
+    reg_write(cnf, PIN_CNF_INPUT_PULLUP);
+    // let the pull-up charge the line
+    cortex_m::asm::delay(10_000);
+    let held = reg_read(gpio + GPIO_IN) & 1 << pin == 0;
+    reg_write(cnf, PIN_CNF_DISCONNECTED);
+    held
+}
+
+/// Uarte drives a secure UARTE, 8N1 without flow control, by polling.
+pub struct Uarte {
+    base: u32,
+}
+
+impl Uarte {
+    /// Takes one of the `UARTEn_S` PAC peripherals, which must not be passed to the non-secure world, and sets it
+    /// up on `txd` and `rxd` at `baudrate`. The pins have to be secure, which they are until `boot`.
+    pub fn new<U: core::ops::Deref>(uarte: U, txd: GpioPin, rxd: GpioPin, baudrate: u32) -> Self
+    where
+        U::Target: Sized,
+    {
+        let uarte = Uarte {
+            base: &*uarte as *const U::Target as u32,
+        };
+        // TXD idles high
+        let (gpio, pin) = port(txd);
+        reg_write(gpio + GPIO_OUTSET, 1 << pin);
+        reg_write(gpio + GPIO_DIRSET, 1 << pin);
+        uarte.write_reg(PSEL_TXD, psel(txd));
+        uarte.write_reg(PSEL_RXD, psel(rxd));
+        uarte.write_reg(BAUDRATE, baudrate);
+        uarte.write_reg(CONFIG, 0);
+        uarte.write_reg(ENABLE, ENABLE_UARTE);
+        uarte
+    }
+
+    fn read_reg(&self, offset: u32) -> u32 {
+        reg_read(self.base + offset)
+    }
+
+    fn write_reg(&self, offset: u32, value: u32) {
+        reg_write(self.base + offset, value)
+    }
+}
+
+impl Serial for Uarte {
+    fn read(&mut self) -> u8 {
+        // bytes that arrive between reads wait in the UARTE's RX FIFO
+        let mut byte = 0u8;
+        self.write_reg(RXD_PTR, &mut byte as *mut u8 as u32);
+        self.write_reg(RXD_MAXCNT, 1);
+        self.write_reg(EVENTS_ENDRX, 0);
+        compiler_fence(Ordering::SeqCst);
+        self.write_reg(TASKS_STARTRX, 1);
+        while self.read_reg(EVENTS_ENDRX) == 0 {}
+        compiler_fence(Ordering::SeqCst);
+        unsafe { read_volatile(&byte) }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let mut buf = [0; TX_CHUNK];
+        for chunk in data.chunks(TX_CHUNK) {
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.write_reg(TXD_PTR, buf.as_ptr() as u32);
+            self.write_reg(TXD_MAXCNT, chunk.len() as u32);
+            self.write_reg(EVENTS_ENDTX, 0);
+            compiler_fence(Ordering::SeqCst);
+            self.write_reg(TASKS_STARTTX, 1);
+            while self.read_reg(EVENTS_ENDTX) == 0 {}
+            compiler_fence(Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use super::IDAU;
+use core::ops::Range;
+
+// pulled from https://infocenter.nordicsemi.com/topic/ps_nrf9160/spu.html
+const REGION_SIZE: u32 = 0x8000;
+const SRAM_REGION_SIZE: u32 = 0x2000;
+const FLASH_BASE: u32 = 0x00000000;
+const RAM_BASE: u32 = 0x20000000;
+const FLASH_REGIONS: u32 = 32;
+const RAM_REGIONS: u32 = 32;
+/// The modem can only reach the first 128 KiB of RAM
+const MODEM_RAM_END: u32 = RAM_BASE + 0x20000;
+
+crate::nrf_spu::impl_spu!(nrf9160_pac, "nrf91");
+
+/// Groups of peripherals that share a single SPU ID on the nRF9160
+const SHARED_INSTANCES: &[(u8, &[&str])] = &[
+    (0x05, &["CLOCK_NS", "POWER_NS"]),
+    (
+        0x08,
+        &["SPIM0_NS", "SPIS0_NS", "TWIM0_NS", "TWIS0_NS", "UARTE0_NS"],
+    ),
+    (
+        0x09,
+        &["SPIM1_NS", "SPIS1_NS", "TWIM1_NS", "TWIS1_NS", "UARTE1_NS"],
+    ),
+    (
+        0x0A,
+        &["SPIM2_NS", "SPIS2_NS", "TWIM2_NS", "TWIS2_NS", "UARTE2_NS"],
+    ),
+    (
+        0x0B,
+        &["SPIM3_NS", "SPIS3_NS", "TWIM3_NS", "TWIS3_NS", "UARTE3_NS"],
+    ),
+];
+
+/// SPU wraps the nRF9160's System Protection Unit, along with the configuration that can't be
+/// expressed through a `MemoryLayout`.
+pub struct SPU<'a> {
+    pub spu: &'a nrf9160_pac::spu_s::RegisterBlock,
+    /// Pins on P0 that are kept secure, every other pin is made non-secure.
+    ///
+    /// Pin security is controlled entirely through this list, so it is an error to also pass
+    /// `P0_NS::perph()` while any pin is kept secure.
+    pub secure_pins: &'a [u8],
+    /// Mask of the DPPI channels that are usable from the non-secure world, bit `n` is channel `n`.
+    /// Every other channel stays secure.
+    pub non_secure_dppi_channels: u32,
+    /// Peripherals that are used by the secure firmware. It is an error to pass any peripheral that
+    /// shares an SPU ID with one of these, as that would hand it to the non-secure world as well.
+    pub secure_peripherals: &'a [NSPeripheral],
+    /// Peripherals whose SPU permission is left unlocked when passed, so they can be lent and reclaimed at
+    /// runtime through [`crate::ReassignPeripheral`]. Every other passed peripheral is locked.
+    pub lendable_peripherals: &'a [NSPeripheral],
+    /// Called from [`handle_access_error`] whenever non-secure code violates the SPU's permissions
+    pub access_error_hook: Option<fn(AccessError)>,
+    /// How flash and RAM ranges that don't line up with SPU region boundaries are handled
+    pub alignment: AlignmentPolicy,
+    /// Additional non-secure-callable regions, on top of `MemoryLayout::nsc_flash_region`.
+    /// The same rules as the nRF5340 apply, with two flash and two RAM NSC slots.
+    pub nsc_regions: &'a [Range<u32>],
+    /// RAM shared between the non-secure application and the LTE modem (e.g. the modem library's
+    /// IPC buffers).
+    ///
+    /// The modem can only reach the first 128 KiB of RAM, and only through non-secure regions,
+    /// so this range must be 8 KiB aligned, inside the first 128 KiB, and inside the non-secure RAM
+    /// region. It is made non-executable. The modem library also needs `IPC_NS::perph()` to be passed.
+    pub modem_shared_ram: Option<Range<u32>>,
+}
+
+impl<'a> SPU<'a> {
+    pub fn new(spu: &'a nrf9160_pac::spu_s::RegisterBlock) -> Self {
+        SPU {
+            spu,
+            secure_pins: &[],
+            non_secure_dppi_channels: 0,
+            secure_peripherals: &[],
+            lendable_peripherals: &[],
+            access_error_hook: None,
+            alignment: AlignmentPolicy::Strict,
+            nsc_regions: &[],
+            modem_shared_ram: None,
+        }
+    }
+
+    /// Returns the SPU regions each range of `layout` will be mapped to
+    pub fn region_plan(&self, layout: &crate::MemoryLayout) -> Result<RegionPlan, Error> {
+        let non_secure_ram = self.ram_regions(&layout.non_secure_ram_region, false)?;
+        let modem_shared_ram = self.modem_regions()?;
+        if let Some(modem) = &modem_shared_ram {
+            if modem.start < non_secure_ram.start || modem.end > non_secure_ram.end {
+                return Err(Error::ModemRamNotNonSecure);
+            }
+        }
+        Ok(RegionPlan {
+            secure_flash: self.flash_regions(&layout.secure_flash_region, true)?,
+            non_secure_flash: self.flash_regions(&layout.non_secure_flash_region, false)?,
+            secure_ram: self.ram_regions(&layout.secure_ram_region, true)?,
+            non_secure_ram,
+            modem_shared_ram,
+        })
+    }
+
+    /// Returns the RAM regions shared with the modem, they have to line up with SPU regions
+    /// whatever the alignment policy is, since the modem can't use secure RAM.
+    fn modem_regions(&self) -> Result<Option<Range<usize>>, Error> {
+        let region = match &self.modem_shared_ram {
+            Some(region) => region,
+            None => return Ok(None),
+        };
+        if region.start < RAM_BASE || region.end > MODEM_RAM_END || region.start > region.end {
+            return Err(Error::ModemRamOutOfBounds {
+                start: region.start,
+                end: region.end,
+            });
+        }
+        let start = region.start - RAM_BASE;
+        let end = region.end - RAM_BASE;
+        if start % SRAM_REGION_SIZE != 0 || end % SRAM_REGION_SIZE != 0 {
+            return Err(Error::UnalignedRegion {
+                start: region.start,
+                end: region.end,
+            });
+        }
+        Ok(Some(
+            (start / SRAM_REGION_SIZE) as usize..(end / SRAM_REGION_SIZE) as usize,
+        ))
+    }
+
+    /// Checks that the passed peripherals don't conflict with the rest of the SPU's configuration
+    pub fn validate(&self, peripherals: &[NSPeripheral]) -> Result<(), Error> {
+        self.validate_pins()?;
+        for perph in peripherals {
+            self.validate_peripheral(perph)?;
+        }
+        Ok(())
+    }
+
+    fn validate_pins(&self) -> Result<(), Error> {
+        match self.secure_pins.iter().find(|pin| **pin >= 32) {
+            Some(pin) => Err(Error::InvalidPin(*pin)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The SPU region indexes that each range of a `MemoryLayout` maps to
+#[derive(Debug, PartialEq, Eq)]
+pub struct RegionPlan {
+    pub secure_flash: Range<usize>,
+    pub non_secure_flash: Range<usize>,
+    pub secure_ram: Range<usize>,
+    pub non_secure_ram: Range<usize>,
+    pub modem_shared_ram: Option<Range<usize>>,
+}
+
+impl RegionPlan {
+    /// Prints each range, along with the addresses the SPU will actually protect
+    pub fn print(&self) {
+        let flash = [
+            ("secure flash", &self.secure_flash),
+            ("non-secure flash", &self.non_secure_flash),
+        ];
+        for (name, regions) in flash {
+            defmt::println!(
+                "{}: regions {}..{} ({:x}..{:x})",
+                name,
+                regions.start,
+                regions.end,
+                FLASH_BASE + regions.start as u32 * REGION_SIZE,
+                FLASH_BASE + regions.end as u32 * REGION_SIZE
+            );
+        }
+        let ram = [
+            ("secure RAM", Some(&self.secure_ram)),
+            ("non-secure RAM", Some(&self.non_secure_ram)),
+            ("modem shared RAM", self.modem_shared_ram.as_ref()),
+        ];
+        for (name, regions) in ram {
+            if let Some(regions) = regions {
+                defmt::println!(
+                    "{}: regions {}..{} ({:x}..{:x})",
+                    name,
+                    regions.start,
+                    regions.end,
+                    RAM_BASE + regions.start as u32 * SRAM_REGION_SIZE,
+                    RAM_BASE + regions.end as u32 * SRAM_REGION_SIZE
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// P0 was passed to the non-secure world while one of its pins is kept secure
+    SecurePinOnPassedPort(u8),
+    /// P0 only has 32 pins
+    InvalidPin(u8),
+    /// A peripheral was passed to the non-secure world that shares an SPU ID with one used by secure code
+    SharedPeripheralId {
+        passed: NSPeripheral,
+        secure: NSPeripheral,
+    },
+    /// The range doesn't start and end on an SPU region boundary
+    UnalignedRegion { start: u32, end: u32 },
+    /// The range falls outside of the flash or RAM covered by the SPU
+    RegionOutOfBounds { start: u32, end: u32 },
+    /// An NSC region must be a power of two between 32 and 4096 bytes
+    InvalidNscSize(u32),
+    /// Every FLASHNSC or RAMNSC slot is already in use
+    NoFreeNscSlot,
+    /// The peripheral isn't in `SPU::lendable_peripherals`
+    NotLendable(NSPeripheral),
+    /// The peripheral's SPU permission is locked, so it can't be reassigned until reset
+    PeripheralLocked(NSPeripheral),
+    /// The modem shared RAM falls outside of the first 128 KiB of RAM
+    ModemRamOutOfBounds { start: u32, end: u32 },
+    /// The modem shared RAM overlaps a secure RAM region
+    ModemRamNotNonSecure,
+}
+
+impl IDAU for SPU<'_> {
+    type Peripheral = NSPeripheral;
+
+    fn set_flash_region_params(&self, region: core::ops::Range<u32>, params: crate::RegionParams) {
+        self.write_flash_regions(region, &params);
+    }
+
+    fn set_memory_region_params(&self, region: core::ops::Range<u32>, params: crate::RegionParams) {
+        let regions = match self.ram_regions(&region, params.secure) {
+            Ok(regions) => regions,
+            Err(err) => defmt::panic!("invalid RAM region: {:?}", err),
+        };
+        let modem = match self.modem_regions() {
+            Ok(modem) => modem.unwrap_or(0..0),
+            Err(err) => defmt::panic!("invalid modem shared RAM: {:?}", err),
+        };
+        if params.secure && regions.start < modem.end && modem.start < regions.end {
+            defmt::panic!(
+                "invalid modem shared RAM: {:?}",
+                Error::ModemRamNotNonSecure
+            );
+        }
+        for i in regions {
+            // the modem's buffers are data only
+            let execute = params.execute && !modem.contains(&i);
+            self.write_ram_region(i, &params, execute);
+        }
+    }
+
+    fn set_nsc_region(&self, region: core::ops::Range<u32>) {
+        self.write_nsc_region(region);
+    }
+
+    fn pass_peripheral_non_secure(&self, perph: &Self::Peripheral) {
+        self.pass_peripheral(perph);
+    }
+
+    fn peripheral_interrupts(&self, perph: &Self::Peripheral, f: &mut dyn FnMut(usize)) {
+        // the SPU ID doubles as the interrupt number
+        f(perph.id as usize);
+    }
+
+    fn prepare_boot(&self) {
+        if let Err(err) = self.validate_pins() {
+            defmt::panic!("invalid SPU configuration: {:?}", err);
+        }
+        let secure_pins = self.secure_pins.iter().fold(0u32, |pins, p| pins | 1 << p);
+        self.prepare_spu(&[secure_pins]);
+    }
+
+    fn report(&self) -> crate::audit::SecurityMap {
+        self.spu_report()
+    }
+}
+
+/// Implements `PerphExt` for the non-secure alias of a peripheral, and `IntoNonSecure` for its secure alias. Secure
+/// code uses the `_S` alias, so passing it to `boot` consumes the handle secure code would otherwise keep using.
+/// Peripherals without a secure alias are passed themselves.
+macro_rules! impl_perph {
+    ($ns:ident) => {
+        impl_perph! { @perph $ns }
+        impl crate::IntoNonSecure<NSPeripheral> for nrf9160_pac::$ns {
+            fn into_non_secure(self) -> NSPeripheral {
+                Self::perph()
+            }
+        }
+    };
+    ($ns:ident, $s:ident) => {
+        impl_perph! { @perph $ns }
+        impl crate::IntoNonSecure<NSPeripheral> for nrf9160_pac::$s {
+            fn into_non_secure(self) -> NSPeripheral {
+                nrf9160_pac::$ns::perph()
+            }
+        }
+    };
+    (@perph $ns:ident) => {
+        impl PerphExt for nrf9160_pac::$ns {
+            fn perph() -> NSPeripheral {
+                NSPeripheral {
+                    id: get_perph_id(nrf9160_pac::$ns::PTR),
+                    name: stringify!($ns),
+                }
+            }
+        }
+    };
+}
+
+impl_perph! { CLOCK_NS, CLOCK_S }
+impl_perph! { DPPIC_NS, DPPIC_S }
+impl_perph! { EGU0_NS, EGU0_S }
+impl_perph! { EGU1_NS, EGU1_S }
+impl_perph! { EGU2_NS, EGU2_S }
+impl_perph! { EGU3_NS, EGU3_S }
+impl_perph! { EGU4_NS, EGU4_S }
+impl_perph! { EGU5_NS, EGU5_S }
+impl_perph! { FPU_NS, FPU_S }
+impl_perph! { GPIOTE1_NS }
+impl_perph! { I2S_NS, I2S_S }
+impl_perph! { IPC_NS, IPC_S }
+impl_perph! { KMU_NS, KMU_S }
+impl_perph! { NVMC_NS, NVMC_S }
+impl_perph! { P0_NS, P0_S }
+impl_perph! { PDM_NS, PDM_S }
+impl_perph! { POWER_NS, POWER_S }
+impl_perph! { PWM0_NS, PWM0_S }
+impl_perph! { PWM1_NS, PWM1_S }
+impl_perph! { PWM2_NS, PWM2_S }
+impl_perph! { PWM3_NS, PWM3_S }
+impl_perph! { REGULATORS_NS, REGULATORS_S }
+impl_perph! { RTC0_NS, RTC0_S }
+impl_perph! { RTC1_NS, RTC1_S }
+impl_perph! { SAADC_NS, SAADC_S }
+impl_perph! { SPIM0_NS, SPIM0_S }
+impl_perph! { SPIM1_NS, SPIM1_S }
+impl_perph! { SPIM2_NS, SPIM2_S }
+impl_perph! { SPIM3_NS, SPIM3_S }
+impl_perph! { SPIS0_NS, SPIS0_S }
+impl_perph! { SPIS1_NS, SPIS1_S }
+impl_perph! { SPIS2_NS, SPIS2_S }
+impl_perph! { SPIS3_NS, SPIS3_S }
+impl_perph! { TIMER0_NS, TIMER0_S }
+impl_perph! { TIMER1_NS, TIMER1_S }
+impl_perph! { TIMER2_NS, TIMER2_S }
+impl_perph! { TWIM0_NS, TWIM0_S }
+impl_perph! { TWIM1_NS, TWIM1_S }
+impl_perph! { TWIM2_NS, TWIM2_S }
+impl_perph! { TWIM3_NS, TWIM3_S }
+impl_perph! { TWIS0_NS, TWIS0_S }
+impl_perph! { TWIS1_NS, TWIS1_S }
+impl_perph! { TWIS2_NS, TWIS2_S }
+impl_perph! { TWIS3_NS, TWIS3_S }
+impl_perph! { UARTE0_NS, UARTE0_S }
+impl_perph! { UARTE1_NS, UARTE1_S }
+impl_perph! { UARTE2_NS, UARTE2_S }
+impl_perph! { UARTE3_NS, UARTE3_S }
+impl_perph! { VMC_NS, VMC_S }
+impl_perph! { WDT_NS, WDT_S }
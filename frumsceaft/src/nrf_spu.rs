@@ -0,0 +1,567 @@
+//! The System Protection Unit shared by the nRF5340 and nRF9160. The two SPUs only differ in the size and
+//! number of their regions, and in what each chip configures around them, so [`impl_spu`] generates the rest
+//! of each backend from its PAC.
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// LOCK bit of the FLASHNSC / RAMNSC REGION registers
+pub(crate) const NSC_LOCK: u32 = 1 << 8;
+
+/// Controls what happens to flash and RAM ranges that don't start and end on an SPU region boundary
+/// (16 KiB for flash on the nRF5340, 32 KiB on the nRF9160, and 8 KiB for RAM on both).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum AlignmentPolicy {
+    /// Unaligned ranges are an error
+    Strict,
+    /// Any region that is only partially covered by a range is made secure
+    FavorSecure,
+}
+
+/// The kind of access that violated the SPU's permissions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum AccessError {
+    Ram,
+    Flash,
+    Peripheral,
+}
+
+pub(crate) struct NscPlan {
+    pub ram: bool,
+    pub slot: usize,
+    pub region: u32,
+    pub size: u32,
+}
+
+static ACCESS_ERROR_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn set_access_error_hook(hook: fn(AccessError)) {
+    ACCESS_ERROR_HOOK.store(hook as usize, Ordering::Release);
+}
+
+/// Logs an access error, and passes it to the hook if one was set
+pub(crate) fn report_access_error(err: AccessError) {
+    defmt::error!("non-secure access violation: {:?}", err);
+    let hook = ACCESS_ERROR_HOOK.load(Ordering::Acquire);
+    if hook != 0 {
+        let hook: fn(AccessError) = unsafe { core::mem::transmute(hook) };
+        hook(err);
+    }
+}
+
+/// Converts a FLASHREGION / RAMREGION PERM value into a [`crate::audit::MemoryAttribution`]
+pub(crate) fn attribution(region: Range<u32>, perm: u32) -> crate::audit::MemoryAttribution {
+    crate::audit::MemoryAttribution {
+        start: region.start,
+        end: region.end,
+        secure: perm & (1 << 4) != 0,
+        non_secure_callable: false,
+        read: perm & (1 << 2) != 0,
+        write: perm & (1 << 1) != 0,
+        execute: perm & 1 != 0,
+        locked: perm & (1 << 8) != 0,
+    }
+}
+
+pub(crate) fn get_perph_id<T>(reg_block: *const T) -> u8 {
+    let base_addr = reg_block as u32;
+    (base_addr >> 12) as u8
+}
+
+/// Returns the peripheral with SPU ID `id` to its reset state through the register interface every nRF53 and nRF91
+/// peripheral shares: it's disabled through ENABLE, its interrupts, shortcuts, DPPI subscriptions and publications
+/// are cleared, and so are its events. Registers outside that interface (pin selection, EasyDMA pointers, other
+/// configuration) keep what the non-secure world wrote, so secure code must configure the peripheral before using
+/// it. This goes through the secure alias, so the peripheral must already be secure.
+pub(crate) fn reset_peripheral(id: u8) {
+    const SUBSCRIBE: u32 = 0x080;
+    const EVENTS: u32 = 0x100;
+    const PUBLISH: u32 = 0x180;
+    const SHORTS: u32 = 0x200;
+    const INTENCLR: u32 = 0x308;
+    const ENABLE: u32 = 0x500;
+
+    let base = 0x50000000 | (id as u32) << 12;
+    let register = |offset: u32| (base | offset) as *mut u32;
+    unsafe {
+        core::ptr::write_volatile(register(INTENCLR), 0xFFFFFFFF);
+        core::ptr::write_volatile(register(ENABLE), 0);
+        core::ptr::write_volatile(register(SHORTS), 0);
+        for i in 0..32 {
+            core::ptr::write_volatile(register(SUBSCRIBE + i * 4), 0);
+            core::ptr::write_volatile(register(PUBLISH + i * 4), 0);
+            core::ptr::write_volatile(register(EVENTS + i * 4), 0);
+        }
+    }
+}
+
+/// Writes a FLASHREGION / RAMREGION PERM register, the two have different types in the PACs
+macro_rules! write_region_perm {
+    ($perm:expr, $params:expr, $execute:expr) => {
+        $perm.write(|w| {
+            if $params.write {
+                w.write().enable();
+            } else {
+                w.write().disable();
+            }
+            if $params.read {
+                w.read().enable();
+            } else {
+                w.read().disable();
+            }
+            if $execute {
+                w.execute().enable();
+            } else {
+                w.execute().disable();
+            }
+            if $params.lock {
+                w.lock().locked();
+            } else {
+                w.lock().unlocked();
+            }
+            if $params.secure {
+                w.secattr().secure();
+            } else {
+                w.secattr().non_secure();
+            }
+            w
+        })
+    };
+}
+pub(crate) use write_region_perm;
+
+/// Generates the parts of an SPU backend that only depend on its PAC. The calling module provides `SPU`
+/// (with `spu`, `secure_pins`, `secure_peripherals`, `lendable_peripherals`, `access_error_hook`,
+/// `alignment`, `nsc_regions` and `non_secure_dppi_channels` fields), `Error`, `SHARED_INSTANCES`, and
+/// the region constants.
+macro_rules! impl_spu {
+    ($pac:ident, $module:literal) => {
+        use $crate::nrf_spu::{attribution, get_perph_id, write_region_perm, NscPlan, NSC_LOCK};
+        pub use $crate::nrf_spu::{AccessError, AlignmentPolicy};
+
+        /// A peripheral that can be passed to the non-secure world, identified by its SPU peripheral ID.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+        pub struct NSPeripheral {
+            id: u8,
+            name: &'static str,
+        }
+
+        impl NSPeripheral {
+            /// The peripheral's SPU ID, which is also the ID of its interrupt
+            pub fn id(&self) -> u8 {
+                self.id
+            }
+
+            pub fn name(&self) -> &'static str {
+                self.name
+            }
+
+            /// Returns true if both peripherals are controlled by the same SPU ID, passing one of them
+            /// passes both.
+            pub fn shares_id_with(&self, other: &NSPeripheral) -> bool {
+                self.id == other.id
+            }
+
+            /// Returns every peripheral instance that shares this peripheral's SPU ID, including itself.
+            /// Peripherals with an ID of their own return an empty slice.
+            pub fn shared_instances(&self) -> &'static [&'static str] {
+                SHARED_INSTANCES
+                    .iter()
+                    .find(|(id, _)| *id == self.id)
+                    .map(|(_, names)| *names)
+                    .unwrap_or(&[])
+            }
+        }
+
+        pub trait PerphExt {
+            fn perph() -> NSPeripheral;
+        }
+
+        impl $crate::IntoNonSecure<NSPeripheral> for NSPeripheral {
+            fn into_non_secure(self) -> NSPeripheral {
+                self
+            }
+        }
+
+        impl SPU<'_> {
+            /// Works out which FLASHNSC / RAMNSC slot and SPU region an NSC region should use
+            fn nsc_plan(&self, region: &Range<u32>) -> Result<NscPlan, Error> {
+                let ram = region.start >= RAM_BASE;
+                let (base, region_size, count) = if ram {
+                    (RAM_BASE, SRAM_REGION_SIZE, RAM_REGIONS)
+                } else {
+                    (FLASH_BASE, REGION_SIZE, FLASH_REGIONS)
+                };
+                let offset = region.start - base;
+                if offset >= region_size * count {
+                    return Err(Error::RegionOutOfBounds {
+                        start: region.start,
+                        end: region.end,
+                    });
+                }
+                let size = region_size - (offset % region_size);
+                if !size.is_power_of_two() || !(32..=4096).contains(&size) {
+                    return Err(Error::InvalidNscSize(size));
+                }
+                if region.end > region.start + size {
+                    return Err(Error::UnalignedRegion {
+                        start: region.start,
+                        end: region.end,
+                    });
+                }
+                // a slot with a SIZE of zero is disabled, and so free to use
+                let slot = if ram {
+                    self.spu
+                        .ramnsc
+                        .iter()
+                        .position(|s| s.size.read().bits() == 0)
+                } else {
+                    self.spu
+                        .flashnsc
+                        .iter()
+                        .position(|s| s.size.read().bits() == 0)
+                };
+                Ok(NscPlan {
+                    ram,
+                    slot: slot.ok_or(Error::NoFreeNscSlot)?,
+                    region: offset / region_size,
+                    size,
+                })
+            }
+
+            fn flash_regions(
+                &self,
+                region: &Range<u32>,
+                secure: bool,
+            ) -> Result<Range<usize>, Error> {
+                self.regions(region, secure, FLASH_BASE, REGION_SIZE, FLASH_REGIONS)
+            }
+
+            fn ram_regions(
+                &self,
+                region: &Range<u32>,
+                secure: bool,
+            ) -> Result<Range<usize>, Error> {
+                self.regions(region, secure, RAM_BASE, SRAM_REGION_SIZE, RAM_REGIONS)
+            }
+
+            /// Converts an address range into a range of SPU region indexes
+            fn regions(
+                &self,
+                region: &Range<u32>,
+                secure: bool,
+                base: u32,
+                size: u32,
+                count: u32,
+            ) -> Result<Range<usize>, Error> {
+                if region.start < base
+                    || region.end > base + size * count
+                    || region.start > region.end
+                {
+                    return Err(Error::RegionOutOfBounds {
+                        start: region.start,
+                        end: region.end,
+                    });
+                }
+                let start = region.start - base;
+                let end = region.end - base;
+                let aligned = start % size == 0 && end % size == 0;
+                let (start, end) = match self.alignment {
+                    _ if aligned => (start / size, end / size),
+                    AlignmentPolicy::Strict => {
+                        return Err(Error::UnalignedRegion {
+                            start: region.start,
+                            end: region.end,
+                        })
+                    }
+                    // secure ranges grow to cover any partial regions, while non-secure ranges shrink
+                    AlignmentPolicy::FavorSecure if secure => {
+                        (start / size, (end + size - 1) / size)
+                    }
+                    AlignmentPolicy::FavorSecure => ((start + size - 1) / size, end / size),
+                };
+                Ok(start as usize..end as usize)
+            }
+
+            fn validate_peripheral(&self, perph: &NSPeripheral) -> Result<(), Error> {
+                // pin security is set through `secure_pins`, every GPIO port shares the P0 ID
+                if perph.id == get_perph_id($pac::P0_NS::PTR) {
+                    if let Some(pin) = self.secure_pins.first() {
+                        return Err(Error::SecurePinOnPassedPort(*pin));
+                    }
+                }
+                if let Some(secure) = self
+                    .secure_peripherals
+                    .iter()
+                    .find(|s| s.shares_id_with(perph))
+                {
+                    return Err(Error::SharedPeripheralId {
+                        passed: *perph,
+                        secure: *secure,
+                    });
+                }
+                Ok(())
+            }
+
+            /// Sets the peripheral's SPU permission and interrupt target, its interrupt is disabled and any
+            /// pending request is cleared first, so nothing fires in the wrong world.
+            fn set_peripheral_security(&self, perph: &NSPeripheral, secure: bool, lock: bool) {
+                let id = perph.id as usize;
+                let peripherals = unsafe { cortex_m::Peripherals::steal() };
+                // disable each interupt
+                unsafe {
+                    peripherals.NVIC.icer[id / 32].write(1 << (id % 32));
+                    peripherals.NVIC.icpr[id / 32].write(1 << (id % 32));
+                }
+
+                self.spu.periphid[id].perm.write(|w| {
+                    if secure {
+                        w.secattr().secure();
+                    } else {
+                        w.secattr().non_secure();
+                    }
+                    if lock {
+                        w.lock().locked();
+                    } else {
+                        w.lock().unlocked();
+                    }
+                    w
+                });
+                // target the interupt at the world that now owns the peripheral
+                unsafe {
+                    peripherals.NVIC.itns[id / 32].modify(|w| {
+                        if secure {
+                            w & !(1 << (id & 0x1F))
+                        } else {
+                            w | 1 << (id & 0x1F)
+                        }
+                    });
+                }
+            }
+
+            fn check_reassignable(&self, perph: &NSPeripheral) -> Result<(), Error> {
+                if !self.lendable_peripherals.contains(perph) {
+                    return Err(Error::NotLendable(*perph));
+                }
+                if self.spu.periphid[perph.id as usize]
+                    .perm
+                    .read()
+                    .lock()
+                    .is_locked()
+                {
+                    return Err(Error::PeripheralLocked(*perph));
+                }
+                Ok(())
+            }
+
+            fn write_flash_regions(&self, region: Range<u32>, params: &$crate::RegionParams) {
+                let regions = match self.flash_regions(&region, params.secure) {
+                    Ok(regions) => regions,
+                    Err(err) => defmt::panic!("invalid flash region: {:?}", err),
+                };
+                for i in regions {
+                    write_region_perm!(self.spu.flashregion[i].perm, params, params.execute);
+                }
+            }
+
+            fn write_ram_region(&self, i: usize, params: &$crate::RegionParams, execute: bool) {
+                write_region_perm!(self.spu.ramregion[i].perm, params, execute);
+            }
+
+            fn write_nsc_region(&self, region: Range<u32>) {
+                let nsc = match self.nsc_plan(&region) {
+                    Ok(nsc) => nsc,
+                    Err(err) => defmt::panic!("invalid NSC region: {:?}", err),
+                };
+                // the NSC region sits at the end of an SPU region, SIZE is log2(size) - 4
+                let size_reg = (31 - nsc.size.leading_zeros()) - 4;
+                // LOCK keeps the non-secure world's entry points fixed until the next reset
+                if nsc.ram {
+                    let slot = &self.spu.ramnsc[nsc.slot];
+                    slot.size.write(|w| unsafe { w.bits(size_reg) });
+                    slot.region
+                        .write(|w| unsafe { w.bits(nsc.region | NSC_LOCK) });
+                } else {
+                    let slot = &self.spu.flashnsc[nsc.slot];
+                    slot.size.write(|w| unsafe { w.bits(size_reg) });
+                    slot.region
+                        .write(|w| unsafe { w.bits(nsc.region | NSC_LOCK) });
+                }
+            }
+
+            fn pass_peripheral(&self, perph: &NSPeripheral) {
+                if let Err(err) = self.validate_peripheral(perph) {
+                    defmt::panic!("invalid SPU configuration: {:?}", err);
+                }
+                if !perph.shared_instances().is_empty() {
+                    defmt::warn!(
+                        "passing {} also passes {} to the non-secure world",
+                        perph.name,
+                        perph.shared_instances()
+                    );
+                }
+                self.set_peripheral_security(
+                    perph,
+                    false,
+                    !self.lendable_peripherals.contains(perph),
+                );
+            }
+
+            /// Sets up the NSC regions, access error reporting, DPPI channels and GPIO pins, then disables the
+            /// SAU so the SPU alone decides security. `secure_pins` holds the GPIOPORT.PERM value of each port.
+            fn prepare_spu(&self, secure_pins: &[u32]) {
+                for region in self.nsc_regions {
+                    self.write_nsc_region(region.clone());
+                }
+                if let Some(hook) = self.access_error_hook {
+                    $crate::nrf_spu::set_access_error_hook(hook);
+                }
+                // report access errors through the SPU interrupt, which stays targeted at the secure world
+                self.spu.intenset.write(|w| {
+                    w.ramaccerr().set();
+                    w.flashaccerr().set();
+                    w.periphaccerr().set();
+                    w
+                });
+                let id = get_perph_id($pac::SPU_S::PTR) as usize;
+                let peripherals = unsafe { cortex_m::Peripherals::steal() };
+                unsafe { peripherals.NVIC.iser[id / 32].write(1 << (id % 32)) }
+
+                unsafe {
+                    // a set bit in DPPI.PERM keeps the channel secure
+                    self.spu.dppi[0]
+                        .perm
+                        .write(|w| w.bits(!self.non_secure_dppi_channels));
+                    self.spu.dppi[0].lock.write(|w| w.bits(1));
+
+                    // a set bit in GPIOPORT.PERM keeps the pin secure
+                    for (port, pins) in self.spu.gpioport.iter().zip(secure_pins) {
+                        port.perm.write(|w| w.bits(*pins));
+                    }
+
+                    let sau = &*cortex_m::peripheral::SAU::PTR;
+                    // disable SAU
+                    sau.ctrl.modify(|mut ctrl| {
+                        ctrl.0 &= !1;
+                        ctrl.0 |= 1 << 1;
+                        ctrl
+                    });
+                }
+            }
+
+            fn spu_report(&self) -> $crate::audit::SecurityMap {
+                let mut map = $crate::audit::SecurityMap::read_core();
+                for (i, region) in self.spu.flashregion.iter().enumerate() {
+                    let start = FLASH_BASE + i as u32 * REGION_SIZE;
+                    map.push_memory(attribution(
+                        start..start + REGION_SIZE,
+                        region.perm.read().bits(),
+                    ));
+                }
+                for (i, region) in self.spu.ramregion.iter().enumerate() {
+                    let start = RAM_BASE + i as u32 * SRAM_REGION_SIZE;
+                    map.push_memory(attribution(
+                        start..start + SRAM_REGION_SIZE,
+                        region.perm.read().bits(),
+                    ));
+                }
+                let flash_nsc = self.spu.flashnsc.iter().map(|s| {
+                    (
+                        FLASH_BASE,
+                        REGION_SIZE,
+                        FLASH_REGIONS,
+                        s.region.read().bits(),
+                        s.size.read().bits(),
+                    )
+                });
+                let ram_nsc = self.spu.ramnsc.iter().map(|s| {
+                    (
+                        RAM_BASE,
+                        SRAM_REGION_SIZE,
+                        RAM_REGIONS,
+                        s.region.read().bits(),
+                        s.size.read().bits(),
+                    )
+                });
+                for (base, region_size, count, region, size) in flash_nsc.chain(ram_nsc) {
+                    // a SIZE of zero disables the slot, otherwise it covers the last 2^(SIZE + 4) bytes of the region
+                    if size != 0 {
+                        let end = base + ((region & (count - 1)) + 1) * region_size;
+                        map.push_memory($crate::audit::MemoryAttribution {
+                            start: end - (1 << (size + 4)),
+                            end,
+                            secure: true,
+                            non_secure_callable: true,
+                            read: true,
+                            write: false,
+                            execute: true,
+                            locked: region & NSC_LOCK != 0,
+                        });
+                    }
+                }
+                for (id, perph) in self.spu.periphid.iter().enumerate() {
+                    let perm = perph.perm.read();
+                    if perm.present().is_is_present() && perm.secattr().is_non_secure() {
+                        map.set_non_secure_peripheral(id);
+                    }
+                }
+                map
+            }
+        }
+
+        /// The SPU controls security per ID, so lending or reclaiming a peripheral that shares its ID
+        /// (see [`NSPeripheral::shared_instances`]) lends or reclaims, and on reclaim resets, every instance
+        /// sharing that ID along with it.
+        impl $crate::ReassignPeripheral for SPU<'_> {
+            type Error = Error;
+
+            fn lend_peripheral(&self, perph: &NSPeripheral) -> Result<(), Error> {
+                self.validate_peripheral(perph)?;
+                self.check_reassignable(perph)?;
+                self.set_peripheral_security(perph, false, false);
+                Ok(())
+            }
+
+            fn reclaim_peripheral(&self, perph: &NSPeripheral) -> Result<(), Error> {
+                self.validate_peripheral(perph)?;
+                self.check_reassignable(perph)?;
+                self.set_peripheral_security(perph, true, false);
+                $crate::nrf_spu::reset_peripheral(perph.id);
+                // an event raised before the reset disabled the peripheral's interrupts may have left it pending
+                let id = perph.id as usize;
+                let peripherals = unsafe { cortex_m::Peripherals::steal() };
+                unsafe { peripherals.NVIC.icpr[id / 32].write(1 << (id % 32)) };
+                Ok(())
+            }
+        }
+
+        /// Handles the SPU's access error events, this should be called from the secure `SPU` interrupt handler.
+        ///
+        /// Each pending error is logged, cleared, and passed to `SPU::access_error_hook` if one was set.
+        ///
+        /// # Example
+        /// ```no_run
+        #[doc = concat!("use ", stringify!($pac), "::interrupt;")]
+        ///
+        /// #[interrupt]
+        /// fn SPU() {
+        #[doc = concat!("    frumsceaft::", $module, "::handle_access_error();")]
+        /// }
+        /// ```
+        pub fn handle_access_error() {
+            let spu = unsafe { &*$pac::SPU_S::PTR };
+            if spu.events_ramaccerr.read().bits() != 0 {
+                spu.events_ramaccerr.write(|w| unsafe { w.bits(0) });
+                $crate::nrf_spu::report_access_error(AccessError::Ram);
+            }
+            if spu.events_flashaccerr.read().bits() != 0 {
+                spu.events_flashaccerr.write(|w| unsafe { w.bits(0) });
+                $crate::nrf_spu::report_access_error(AccessError::Flash);
+            }
+            if spu.events_periphaccerr.read().bits() != 0 {
+                spu.events_periphaccerr.write(|w| unsafe { w.bits(0) });
+                $crate::nrf_spu::report_access_error(AccessError::Peripheral);
+            }
+        }
+    };
+}
+pub(crate) use impl_spu;
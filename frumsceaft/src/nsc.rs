@@ -0,0 +1,187 @@
+//! Helpers for NSC veneers. Pointers passed in by the non-secure world have to be checked before they are used,
+//! otherwise the non-secure world could get the secure world to read or write secure memory on its behalf.
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(armv8m)]
+use cortex_m::cmse::{AccessType, TestTarget};
+
+/// Status codes returned by the services' veneers, these match the PSA status codes.
+pub mod status {
+    pub const SUCCESS: i32 = 0;
+    pub const GENERIC_ERROR: i32 = -132;
+    pub const NOT_PERMITTED: i32 = -133;
+    pub const NOT_SUPPORTED: i32 = -134;
+    pub const INVALID_ARGUMENT: i32 = -135;
+    pub const INVALID_HANDLE: i32 = -136;
+    pub const BAD_STATE: i32 = -137;
+    pub const BUFFER_TOO_SMALL: i32 = -138;
+    pub const ALREADY_EXISTS: i32 = -139;
+    pub const DOES_NOT_EXIST: i32 = -140;
+    pub const INSUFFICIENT_MEMORY: i32 = -141;
+    pub const INSUFFICIENT_STORAGE: i32 = -142;
+    pub const STORAGE_FAILURE: i32 = -146;
+    pub const INSUFFICIENT_ENTROPY: i32 = -148;
+    pub const INVALID_SIGNATURE: i32 = -149;
+    pub const DATA_CORRUPT: i32 = -152;
+}
+
+/// Shared holds a service's state for its veneers. Requests are handled one at a time, a request made while another
+/// is being handled (e.g. from a non-secure interrupt) is turned away.
+pub struct Shared<T> {
+    value: UnsafeCell<Option<T>>,
+    busy: AtomicBool,
+}
+
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    pub const fn new() -> Self {
+        Shared {
+            value: UnsafeCell::new(None),
+            busy: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets the state requests are handled with, this should be done before `boot`.
+    /// `value` is handed back if a request is being handled.
+    pub fn init(&self, value: T) -> Result<(), T> {
+        if self.busy.swap(true, Ordering::Acquire) {
+            return Err(value);
+        }
+        unsafe { *self.value.get() = Some(value) };
+        self.busy.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Calls `f` with the state, returns `None` if it hasn't been set or another request is being handled
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        if self.busy.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        let result = unsafe { (*self.value.get()).as_mut() }.map(f);
+        self.busy.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl<T> Default for Shared<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Granularity of the SAU and of `TestTarget`'s region checks
+const GRANULE: usize = 32;
+
+/// Returns true if the non-secure world is allowed to access all of `ptr..ptr + len`, and to write to it if
+/// `write` is set. The check uses the non-secure world's MPU configuration for its current privilege level.
+pub fn is_non_secure(ptr: *const u8, len: usize, write: bool) -> bool {
+    if len == 0 {
+        return true;
+    }
+    let start = ptr as usize;
+    let end = match start.checked_add(len) {
+        Some(end) => end,
+        None => return false,
+    };
+    // the range may span several SAU / IDAU regions, so every granule it touches is checked
+    let mut addr = start;
+    while addr < end {
+        if !granule_allowed(addr, write) {
+            return false;
+        }
+        addr = (addr & !(GRANULE - 1)) + GRANULE;
+    }
+    true
+}
+
+#[cfg(armv8m)]
+fn granule_allowed(addr: usize, write: bool) -> bool {
+    let target = TestTarget::check(addr as *mut u32, AccessType::NonSecure);
+    if write {
+        target.ns_read_and_writable()
+    } else {
+        target.ns_readable()
+    }
+}
+
+/// Off ARMv8-M there's no secure state to check against, this lets the services' veneers be tested on the host
+#[cfg(not(armv8m))]
+fn granule_allowed(_: usize, _: bool) -> bool {
+    true
+}
+
+/// Copies a value out of non-secure memory, so it can't be changed by the non-secure world while it is used.
+/// Returns `None` if `ptr` isn't aligned for `T`, or the non-secure world can't read the value.
+///
+/// # Safety
+/// `ptr` must be valid for reads of a `T`, and any bit pattern must be a valid `T`.
+pub unsafe fn read<T: Copy>(ptr: *const T) -> Option<T> {
+    if ptr as usize % core::mem::align_of::<T>() != 0
+        || !is_non_secure(ptr as *const u8, core::mem::size_of::<T>(), false)
+    {
+        return None;
+    }
+    Some(core::ptr::read_volatile(ptr))
+}
+
+/// Writes a value to non-secure memory. Returns `None` if `ptr` isn't aligned for `T`, or the non-secure world
+/// can't write the value.
+///
+/// # Safety
+/// `ptr` must be valid for writes of a `T`.
+pub unsafe fn write<T>(ptr: *mut T, value: T) -> Option<()> {
+    if ptr as usize % core::mem::align_of::<T>() != 0
+        || !is_non_secure(ptr as *const u8, core::mem::size_of::<T>(), true)
+    {
+        return None;
+    }
+    core::ptr::write_volatile(ptr, value);
+    Some(())
+}
+
+/// Returns a slice of non-secure memory the secure world can read from
+///
+/// # Safety
+/// `ptr` must be valid for reads of `len` bytes for the duration of `'a`.
+pub unsafe fn slice<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        return Some(&[]);
+    }
+    if ptr.is_null() || !is_non_secure(ptr, len, false) {
+        return None;
+    }
+    Some(core::slice::from_raw_parts(ptr, len))
+}
+
+/// Returns a slice of non-secure memory the secure world can write to
+///
+/// # Safety
+/// `ptr` must be valid for reads and writes of `len` bytes for the duration of `'a`.
+pub unsafe fn slice_mut<'a>(ptr: *mut u8, len: usize) -> Option<&'a mut [u8]> {
+    if len == 0 {
+        return Some(&mut []);
+    }
+    if ptr.is_null() || !is_non_secure(ptr, len, true) {
+        return None;
+    }
+    Some(core::slice::from_raw_parts_mut(ptr, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alignment() {
+        let mut words = [0u32; 2];
+        let bytes = words.as_mut_ptr() as *mut u8;
+        unsafe {
+            assert_eq!(read(bytes.add(1) as *const u32), None);
+            assert_eq!(write(bytes.add(2) as *mut u32, 7), None);
+            assert_eq!(write(bytes.add(4) as *mut u32, 7), Some(()));
+            assert_eq!(read(bytes.add(4) as *const u32), Some(7));
+        }
+        assert_eq!(words, [0, 7]);
+    }
+}
@@ -0,0 +1,1061 @@
+//! Serial recovery, which reflashes a device whose non-secure image is blank or broken over a UART, without a debug
+//! probe.
+//!
+//! Recovery speaks SMP, the protocol of MCUboot's serial recovery and Zephyr's mcumgr, over its serial transport, so
+//! the standard host tools work: `mcumgr --conntype serial --connstring dev=/dev/ttyACM0 image upload app.bin`,
+//! `image list`, and `reset`. An uploaded image is written straight to the primary slot and verified once its last
+//! byte arrives. There is nothing to fall back to, so unlike an [`update`](crate::update) it isn't swapped in or run
+//! on trial, and it can't be encrypted.
+//!
+//! Recovery should be entered when [`needed`] says so, after `Update::install` if the update service is used, and
+//! before `boot`. The UART belongs to the secure world, which is the only thing running during recovery.
+use crate::flash::{self, Flash};
+use crate::image::{self, Header, Version};
+use core::fmt::Write;
+use core::ops::Range;
+
+// SMP operations
+const OP_READ: u8 = 0;
+const OP_WRITE: u8 = 2;
+// SMP groups, and their commands
+const GROUP_OS: u16 = 0;
+const OS_RESET: u8 = 5;
+const GROUP_IMAGE: u16 = 1;
+const IMAGE_STATE: u8 = 0;
+const IMAGE_UPLOAD: u8 = 1;
+const SMP_HEADER_SIZE: usize = 8;
+
+// SMP result codes
+const RC_OK: u32 = 0;
+const RC_UNKNOWN: u32 = 1;
+const RC_NO_MEMORY: u32 = 2;
+const RC_INVALID: u32 = 3;
+const RC_NOT_SUPPORTED: u32 = 8;
+const RC_CORRUPT: u32 = 9;
+
+// serial transport frames start with one of these, followed by base64 and a newline
+const FRAME_START: [u8; 2] = [0x06, 0x09];
+const FRAME_CONTINUE: [u8; 2] = [0x04, 0x14];
+/// Longest frame sent, including its marker and newline
+const MAX_FRAME: usize = 127;
+/// Longest frame received
+const MAX_LINE: usize = 512;
+/// Largest packet received, which bounds the size of upload chunks
+const MAX_PACKET: usize = 1024;
+/// Largest packet sent
+const MAX_RESPONSE: usize = 256;
+
+/// A serial port owned by the secure world
+pub trait Serial {
+    /// Blocks until a byte is received
+    fn read(&mut self) -> u8;
+    /// Blocks until `data` has been sent
+    fn write(&mut self, data: &[u8]);
+}
+
+/// Returns true if recovery should be entered: the recovery pin was `held` at reset, or the image in `slot` doesn't
+/// verify against `public_key`
+pub fn needed<F: Flash>(flash: &F, slot: &Range<u32>, public_key: &[u8], held: bool) -> bool {
+    if held {
+        return true;
+    }
+    match image::verify(flash, slot, public_key) {
+        Ok(_) => false,
+        Err(err) => {
+            defmt::warn!("non-secure image failed verification: {}", err);
+            true
+        }
+    }
+}
+
+/// Recovery serves SMP requests on a [`Serial`] port, writing uploaded images to the primary slot.
+pub struct Recovery<'a, F, S> {
+    flash: F,
+    slot: Range<u32>,
+    public_key: &'a [u8],
+    serial: S,
+    /// Length of the image being uploaded, if there is one
+    upload: Option<u32>,
+    written: u32,
+    /// End of the part of the slot erased for the upload
+    erased: u32,
+    /// Data that doesn't fill a write unit yet
+    pending: [u8; 16],
+}
+
+impl<'a, F: Flash, S: Serial> Recovery<'a, F, S> {
+    /// Creates the service for the primary slot `slot`, which has to be aligned to the flash's pages. Images must be
+    /// signed with the private key for `public_key`, a SEC1 encoded P-256 point.
+    pub fn new(
+        flash: F,
+        slot: Range<u32>,
+        public_key: &'a [u8],
+        serial: S,
+    ) -> Result<Self, flash::Error> {
+        if slot.start % F::ERASE_SIZE != 0 || slot.end % F::ERASE_SIZE != 0 {
+            return Err(flash::Error::Unaligned);
+        }
+        Ok(Recovery {
+            flash,
+            erased: slot.start,
+            slot,
+            public_key,
+            serial,
+            upload: None,
+            written: 0,
+            pending: [0xFF; 16],
+        })
+    }
+
+    /// Serves requests until the host asks for a reset, then resets the device
+    pub fn run(mut self) -> ! {
+        self.serve();
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    /// Serves requests, returning once the host asks for a reset and has been answered
+    pub fn serve(&mut self) {
+        defmt::info!("serial recovery");
+        let mut line = [0; MAX_LINE];
+        let mut len = 0;
+        let mut receiver = Receiver {
+            packet: [0; MAX_PACKET],
+            len: None,
+        };
+        loop {
+            let byte = self.serial.read();
+            if byte != b'\n' {
+                // an overlong line is dropped, along with the packet it's part of
+                if len < MAX_LINE {
+                    line[len] = byte;
+                }
+                len += 1;
+                continue;
+            }
+            let complete = if len <= MAX_LINE {
+                receiver.push_line(&line[..len])
+            } else {
+                receiver.len = None;
+                None
+            };
+            len = 0;
+            if let Some(message) = complete {
+                if self.handle(&receiver.packet[2..2 + message]) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Handles an SMP message, returning true if the host asked for a reset
+    fn handle(&mut self, message: &[u8]) -> bool {
+        if message.len() < SMP_HEADER_SIZE {
+            return false;
+        }
+        let (header, body) = message.split_at(SMP_HEADER_SIZE);
+        let op = header[0];
+        let group = u16::from_be_bytes([header[4], header[5]]);
+        let id = header[7];
+        let body = match body.get(..u16::from_be_bytes([header[2], header[3]]) as usize) {
+            Some(body) => body,
+            None => return false,
+        };
+        let mut response = [0; MAX_RESPONSE];
+        let mut cbor = Encoder {
+            buf: &mut response[2 + SMP_HEADER_SIZE..MAX_RESPONSE - 2],
+            len: 0,
+            overflow: false,
+        };
+        let mut reset = false;
+        match (op, group, id) {
+            (OP_READ, GROUP_IMAGE, IMAGE_STATE) => self.list(&mut cbor),
+            (OP_WRITE, GROUP_IMAGE, IMAGE_UPLOAD) => match self.upload(body) {
+                Ok(offset) => {
+                    cbor.map(2);
+                    cbor.text("rc");
+                    cbor.uint(RC_OK);
+                    cbor.text("off");
+                    cbor.uint(offset);
+                }
+                Err(rc) => result(&mut cbor, rc),
+            },
+            (OP_WRITE, GROUP_OS, OS_RESET) => {
+                defmt::info!("reset requested");
+                result(&mut cbor, RC_OK);
+                reset = true;
+            }
+            _ => result(&mut cbor, RC_NOT_SUPPORTED),
+        }
+        if cbor.overflow {
+            cbor.len = 0;
+            cbor.overflow = false;
+            result(&mut cbor, RC_NO_MEMORY);
+        }
+        let len = cbor.len;
+        // the response echoes the request's header, with the operation's response code and the new length
+        response[2..2 + SMP_HEADER_SIZE].copy_from_slice(header);
+        response[2] = op + 1;
+        response[3] = 0;
+        response[4..6].copy_from_slice(&(len as u16).to_be_bytes());
+        self.send(&mut response, SMP_HEADER_SIZE + len);
+        reset
+    }
+
+    /// Lists the image in the primary slot
+    fn list(&self, cbor: &mut Encoder<'_>) {
+        cbor.map(1);
+        cbor.text("images");
+        let header = Header::read(&self.flash, &self.slot)
+            .and_then(|header| image::total_size(&self.flash, &self.slot, &header).map(|_| header));
+        let header = match header {
+            Ok(header) => header,
+            Err(_) => return cbor.array(0),
+        };
+        let mut hash = [0; 32];
+        let has_hash = matches!(
+            image::find_tlv(
+                &self.flash,
+                &self.slot,
+                &header,
+                image::TLV_SHA256,
+                &mut hash
+            ),
+            Ok(Some(32))
+        );
+        let mut version = [0; 32];
+        let version = format_version(header.version, &mut version);
+        cbor.array(1);
+        cbor.map(if has_hash { 4 } else { 3 });
+        cbor.text("slot");
+        cbor.uint(0);
+        cbor.text("version");
+        cbor.text(version);
+        if has_hash {
+            cbor.text("hash");
+            cbor.bytes(&hash);
+        }
+        cbor.text("bootable");
+        cbor.bool(image::verify(&self.flash, &self.slot, self.public_key).is_ok());
+    }
+
+    /// Handles an upload request, returning the offset the host should continue from or an SMP result code
+    fn upload(&mut self, body: &[u8]) -> Result<u32, u32> {
+        let request = UploadRequest::parse(body).ok_or(RC_INVALID)?;
+        if request.image != 0 {
+            return Err(RC_INVALID);
+        }
+        if request.offset == 0 {
+            let len = request.len.ok_or(RC_INVALID)?;
+            if len > self.slot.end - self.slot.start {
+                return Err(RC_INVALID);
+            }
+            defmt::info!("uploading a {} byte image", len);
+            self.upload = Some(len);
+            self.written = 0;
+            self.erased = self.slot.start;
+            self.pending = [0xFF; 16];
+        }
+        let len = self.upload.ok_or(RC_INVALID)?;
+        // the host resends from the offset it's told, e.g. after a lost response
+        if request.offset != self.written {
+            return Ok(self.written);
+        }
+        if request.data.len() as u32 > len - self.written {
+            return Err(RC_INVALID);
+        }
+        let written = self.program(request.data).and_then(|_| {
+            if self.written == len {
+                self.flush()
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(err) = written {
+            defmt::error!("writing the image failed: {}", err);
+            self.upload = None;
+            return Err(RC_UNKNOWN);
+        }
+        if self.written == len {
+            self.upload = None;
+            if let Err(err) = image::verify(&self.flash, &self.slot, self.public_key) {
+                defmt::error!("uploaded image failed verification: {}", err);
+                return Err(RC_CORRUPT);
+            }
+            defmt::info!("image uploaded");
+        }
+        Ok(self.written)
+    }
+
+    /// Erases the slot up to `end`
+    fn erase_to(&mut self, end: u32) -> Result<(), flash::Error> {
+        while self.erased < end {
+            self.flash.erase(self.erased)?;
+            self.erased += F::ERASE_SIZE;
+        }
+        Ok(())
+    }
+
+    fn program(&mut self, mut data: &[u8]) -> Result<(), flash::Error> {
+        let unit = F::WRITE_SIZE as usize;
+        while !data.is_empty() {
+            let buffered = self.written as usize % unit;
+            let n = if buffered == 0 && data.len() >= unit {
+                // whole units are written straight away
+                let n = data.len() - data.len() % unit;
+                let address = self.slot.start + self.written;
+                self.erase_to(address + n as u32)?;
+                self.flash.write(address, &data[..n])?;
+                n
+            } else {
+                let n = (unit - buffered).min(data.len());
+                self.pending[buffered..buffered + n].copy_from_slice(&data[..n]);
+                if buffered + n == unit {
+                    let address = self.slot.start + self.written - buffered as u32;
+                    self.erase_to(address + unit as u32)?;
+                    self.flash.write(address, &self.pending[..unit])?;
+                    self.pending = [0xFF; 16];
+                }
+                n
+            };
+            self.written += n as u32;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    /// Writes out the last, partial, write unit
+    fn flush(&mut self) -> Result<(), flash::Error> {
+        let buffered = self.written % F::WRITE_SIZE;
+        if buffered != 0 {
+            let address = self.slot.start + self.written - buffered;
+            self.erase_to(address + F::WRITE_SIZE)?;
+            self.flash
+                .write(address, &self.pending[..F::WRITE_SIZE as usize])?;
+            self.pending = [0xFF; 16];
+        }
+        Ok(())
+    }
+
+    /// Sends a response, `packet` holds the SMP message of `len` bytes after room for the length, and has room for
+    /// the CRC after it
+    fn send(&mut self, packet: &mut [u8; MAX_RESPONSE], len: usize) {
+        let crc = crc16(&packet[2..2 + len]);
+        packet[2 + len..4 + len].copy_from_slice(&crc.to_be_bytes());
+        packet[..2].copy_from_slice(&((len + 2) as u16).to_be_bytes());
+        // each frame carries a whole number of base64 quads, so it decodes on its own
+        let mut frame = [0; MAX_FRAME];
+        for (i, chunk) in packet[..len + 4]
+            .chunks((MAX_FRAME - 3) / 4 * 3)
+            .enumerate()
+        {
+            let marker = if i == 0 { FRAME_START } else { FRAME_CONTINUE };
+            frame[..2].copy_from_slice(&marker);
+            let n = 2 + base64_encode(chunk, &mut frame[2..]);
+            frame[n] = b'\n';
+            self.serial.write(&frame[..n + 1]);
+        }
+    }
+}
+
+/// Encodes a response holding only a result code
+fn result(cbor: &mut Encoder<'_>, rc: u32) {
+    cbor.map(1);
+    cbor.text("rc");
+    cbor.uint(rc);
+}
+
+/// Formats a version the way mcumgr shows it, with the build number only if it isn't zero
+fn format_version(version: Version, buf: &mut [u8; 32]) -> &str {
+    struct Buf<'a>(&'a mut [u8], usize);
+    impl Write for Buf<'_> {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let buf = self
+                .0
+                .get_mut(self.1..self.1 + s.len())
+                .ok_or(core::fmt::Error)?;
+            buf.copy_from_slice(s.as_bytes());
+            self.1 += s.len();
+            Ok(())
+        }
+    }
+    let mut out = Buf(&mut buf[..], 0);
+    // the longest version, 255.255.65535.4294967295, fits
+    let _ = write!(
+        out,
+        "{}.{}.{}",
+        version.major, version.minor, version.revision
+    );
+    if version.build != 0 {
+        let _ = write!(out, ".{}", version.build);
+    }
+    let len = out.1;
+    core::str::from_utf8(&buf[..len]).unwrap_or_default()
+}
+
+/// Reassembles packets from serial transport frames
+struct Receiver {
+    /// The packet's length, then its SMP message and CRC
+    packet: [u8; MAX_PACKET],
+    /// Bytes of the packet received so far, if a packet has been started
+    len: Option<usize>,
+}
+
+impl Receiver {
+    /// Adds a frame, returning the length of the SMP message once the packet is complete and its CRC checks out
+    fn push_line(&mut self, line: &[u8]) -> Option<usize> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.starts_with(&FRAME_START) {
+            self.len = Some(0);
+        } else if !line.starts_with(&FRAME_CONTINUE) {
+            // console output, or noise
+            return None;
+        }
+        let start = self.len?;
+        self.len = None;
+        let len = start + base64_decode(&line[2..], &mut self.packet[start..])?;
+        if len < 2 {
+            self.len = Some(len);
+            return None;
+        }
+        // the length covers the SMP message and the CRC
+        let total = u16::from_be_bytes([self.packet[0], self.packet[1]]) as usize;
+        if total < SMP_HEADER_SIZE + 2 || total + 2 > MAX_PACKET {
+            return None;
+        }
+        if len < total + 2 {
+            self.len = Some(len);
+            return None;
+        }
+        if crc16(&self.packet[2..total + 2]) != 0 {
+            defmt::warn!("dropped a packet with a bad CRC");
+            return None;
+        }
+        Some(total - 2)
+    }
+}
+
+/// CRC-16/XMODEM, which comes out as zero over data followed by its big endian CRC
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| {
+        let mut crc = crc ^ ((*byte as u16) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `data` as padded base64 into `out`, returning the encoded length
+fn base64_encode(data: &[u8], out: &mut [u8]) -> usize {
+    let mut len = 0;
+    for chunk in data.chunks(3) {
+        let mut bytes = [0; 3];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            out[len + i] = if i <= chunk.len() {
+                BASE64[(bits >> (18 - 6 * i) & 0x3F) as usize]
+            } else {
+                b'='
+            };
+        }
+        len += 4;
+    }
+    len
+}
+
+/// Decodes padded base64 into `out`, returning the decoded length
+fn base64_decode(text: &[u8], out: &mut [u8]) -> Option<usize> {
+    if text.len() % 4 != 0 {
+        return None;
+    }
+    let quads = text.len() / 4;
+    let mut len = 0;
+    for (i, quad) in text.chunks(4).enumerate() {
+        let padding = quad.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding != 0 && i != quads - 1) {
+            return None;
+        }
+        let mut bits = 0;
+        for c in &quad[..4 - padding] {
+            let value = BASE64.iter().position(|b| b == c)? as u32;
+            bits = bits << 6 | value;
+        }
+        bits <<= 6 * padding;
+        let n = 3 - padding;
+        out.get_mut(len..len + n)?
+            .copy_from_slice(&bits.to_be_bytes()[1..1 + n]);
+        len += n;
+    }
+    Some(len)
+}
+
+/// The fields of an image upload request
+struct UploadRequest<'a> {
+    image: u32,
+    offset: u32,
+    /// Length of the whole image, sent with the first chunk
+    len: Option<u32>,
+    data: &'a [u8],
+}
+
+impl<'a> UploadRequest<'a> {
+    fn parse(body: &'a [u8]) -> Option<Self> {
+        let mut cbor = Decoder { data: body, pos: 0 };
+        let entries = cbor.item(MAJOR_MAP)?;
+        let mut request = UploadRequest {
+            image: 0,
+            offset: u32::MAX,
+            len: None,
+            data: &[],
+        };
+        let mut has_data = false;
+        for _ in 0..entries {
+            match cbor.string(MAJOR_TEXT)? {
+                b"image" => request.image = cbor.uint()?,
+                b"off" => request.offset = cbor.uint()?,
+                b"len" => request.len = Some(cbor.uint()?),
+                b"data" => {
+                    request.data = cbor.string(MAJOR_BYTES)?;
+                    has_data = true;
+                }
+                // e.g. the image's SHA-256, which is checked anyway once it's complete
+                _ => cbor.skip(8)?,
+            }
+        }
+        if request.offset == u32::MAX || !has_data {
+            return None;
+        }
+        Some(request)
+    }
+}
+
+// CBOR major types
+const MAJOR_UINT: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const CBOR_FALSE: u8 = 0xF4;
+const CBOR_TRUE: u8 = 0xF5;
+
+/// Encodes the CBOR responses, noting rather than panicking if they don't fit
+struct Encoder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl Encoder<'_> {
+    fn put(&mut self, data: &[u8]) {
+        match self.buf.get_mut(self.len..self.len + data.len()) {
+            Some(buf) => {
+                buf.copy_from_slice(data);
+                self.len += data.len();
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    fn head(&mut self, major: u8, value: u32) {
+        let major = major << 5;
+        match value {
+            0..=23 => self.put(&[major | value as u8]),
+            24..=0xFF => self.put(&[major | 24, value as u8]),
+            0x100..=0xFFFF => {
+                self.put(&[major | 25]);
+                self.put(&(value as u16).to_be_bytes());
+            }
+            _ => {
+                self.put(&[major | 26]);
+                self.put(&value.to_be_bytes());
+            }
+        }
+    }
+
+    fn uint(&mut self, value: u32) {
+        self.head(MAJOR_UINT, value)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.head(MAJOR_BYTES, bytes.len() as u32);
+        self.put(bytes);
+    }
+
+    fn text(&mut self, text: &str) {
+        self.head(MAJOR_TEXT, text.len() as u32);
+        self.put(text.as_bytes());
+    }
+
+    fn array(&mut self, len: u32) {
+        self.head(MAJOR_ARRAY, len)
+    }
+
+    fn map(&mut self, entries: u32) {
+        self.head(MAJOR_MAP, entries)
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.put(&[if value { CBOR_TRUE } else { CBOR_FALSE }])
+    }
+}
+
+/// Decodes the CBOR requests. Only definite lengths are supported, which is what the host tools send.
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    /// Reads an item's head, returning its major type and argument
+    fn head(&mut self) -> Option<(u8, u64)> {
+        let initial = *self.data.get(self.pos)?;
+        self.pos += 1;
+        let info = initial & 0x1F;
+        let value = match info {
+            0..=23 => info as u64,
+            24..=27 => {
+                let bytes = self.take(1 << (info - 24))?;
+                bytes.iter().fold(0, |value, b| value << 8 | *b as u64)
+            }
+            _ => return None,
+        };
+        Some((initial >> 5, value))
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let data = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(data)
+    }
+
+    /// Reads the head of an item of type `major`, returning its argument
+    fn item(&mut self, major: u8) -> Option<u64> {
+        match self.head()? {
+            (m, value) if m == major => Some(value),
+            _ => None,
+        }
+    }
+
+    fn uint(&mut self) -> Option<u32> {
+        u32::try_from(self.item(MAJOR_UINT)?).ok()
+    }
+
+    /// Reads a byte or text string
+    fn string(&mut self, major: u8) -> Option<&'a [u8]> {
+        let len = self.item(major)?;
+        self.take(usize::try_from(len).ok()?)
+    }
+
+    /// Skips an item, nested no deeper than `depth`
+    fn skip(&mut self, depth: u8) -> Option<()> {
+        let depth = depth.checked_sub(1)?;
+        let (major, value) = self.head()?;
+        match major {
+            MAJOR_BYTES | MAJOR_TEXT => {
+                self.take(usize::try_from(value).ok()?)?;
+            }
+            MAJOR_ARRAY => {
+                for _ in 0..value {
+                    self.skip(depth)?;
+                }
+            }
+            MAJOR_MAP => {
+                for _ in 0..value {
+                    self.skip(depth)?;
+                    self.skip(depth)?;
+                }
+            }
+            MAJOR_TAG => self.skip(depth)?,
+            // integers and simple values are just their head
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::test_image;
+    use crate::testing::{Ram, PAGE_SIZE};
+    use std::collections::VecDeque;
+
+    const SLOT: Range<u32> = 0..6 * PAGE_SIZE;
+
+    struct Port {
+        rx: VecDeque<u8>,
+        tx: Vec<u8>,
+    }
+
+    impl Serial for Port {
+        fn read(&mut self) -> u8 {
+            self.rx.pop_front().expect("the host ran out of requests")
+        }
+
+        fn write(&mut self, data: &[u8]) {
+            self.tx.extend(data);
+        }
+    }
+
+    fn encode(data: &[u8]) -> Vec<u8> {
+        let mut text = vec![0; (data.len() + 2) / 3 * 4];
+        let len = base64_encode(data, &mut text);
+        text.truncate(len);
+        text
+    }
+
+    fn decode(text: &[u8]) -> Option<Vec<u8>> {
+        let mut data = vec![0; text.len() / 4 * 3];
+        let len = base64_decode(text, &mut data)?;
+        data.truncate(len);
+        Some(data)
+    }
+
+    fn encoded(f: impl FnOnce(&mut Encoder<'_>)) -> Vec<u8> {
+        // with room for requests that are too large to be received
+        let mut buf = vec![0; 2 * MAX_PACKET];
+        let mut cbor = Encoder {
+            buf: &mut buf,
+            len: 0,
+            overflow: false,
+        };
+        f(&mut cbor);
+        assert!(!cbor.overflow);
+        let len = cbor.len;
+        buf.truncate(len);
+        buf
+    }
+
+    /// Encodes an upload request like mcumgr, which sends the image's length and SHA-256 with the first chunk
+    fn upload(offset: usize, data: &[u8], len: Option<usize>) -> Vec<u8> {
+        encoded(|cbor| {
+            cbor.map(if len.is_some() { 6 } else { 3 });
+            cbor.text("image");
+            cbor.uint(0);
+            if let Some(len) = len {
+                cbor.text("len");
+                cbor.uint(len as u32);
+                cbor.text("sha");
+                cbor.bytes(&[1, 2, 3]);
+                cbor.text("upgrade");
+                cbor.bool(false);
+            }
+            cbor.text("off");
+            cbor.uint(offset as u32);
+            cbor.text("data");
+            cbor.bytes(data);
+        })
+    }
+
+    /// Frames an SMP request after some console output, with `split` bytes of the packet in each frame
+    fn request(op: u8, group: u16, id: u8, body: &[u8], split: usize, bad_crc: bool) -> Vec<u8> {
+        let mut message = vec![op, 0];
+        message.extend((body.len() as u16).to_be_bytes());
+        message.extend(group.to_be_bytes());
+        message.extend([0, id]);
+        message.extend(body);
+        let crc = crc16(&message) ^ bad_crc as u16;
+        let mut packet = ((message.len() + 2) as u16).to_be_bytes().to_vec();
+        packet.extend(message);
+        packet.extend(crc.to_be_bytes());
+        let mut frames = b"console output\r\n".to_vec();
+        for (i, chunk) in packet.chunks(split).enumerate() {
+            frames.extend(if i == 0 { FRAME_START } else { FRAME_CONTINUE });
+            frames.extend(encode(chunk));
+            frames.extend(b"\r\n");
+        }
+        frames
+    }
+
+    /// Reassembles the responses sent to the host, returning the header and body of each
+    fn responses(tx: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut receiver = Receiver {
+            packet: [0; MAX_PACKET],
+            len: None,
+        };
+        tx.split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                assert!(line.len() < MAX_FRAME);
+                let len = receiver.push_line(line)?;
+                let (header, body) = receiver.packet[2..2 + len].split_at(SMP_HEADER_SIZE);
+                Some((header.to_vec(), body.to_vec()))
+            })
+            .collect()
+    }
+
+    /// Decodes a response's result code, and the offset of an upload response
+    fn decode_result(body: &[u8]) -> (u32, Option<u32>) {
+        let mut cbor = Decoder { data: body, pos: 0 };
+        let entries = cbor.item(MAJOR_MAP).unwrap();
+        assert_eq!(cbor.string(MAJOR_TEXT), Some(&b"rc"[..]));
+        let rc = cbor.uint().unwrap();
+        let offset = (entries == 2).then(|| {
+            assert_eq!(cbor.string(MAJOR_TEXT), Some(&b"off"[..]));
+            cbor.uint().unwrap()
+        });
+        assert_eq!(cbor.pos, body.len());
+        (rc, offset)
+    }
+
+    fn serve(flash: &Ram<4>, public_key: &[u8], requests: Vec<u8>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let port = Port {
+            rx: requests.into(),
+            tx: vec![],
+        };
+        let mut recovery = Recovery::new(flash.clone(), SLOT, public_key, port).unwrap();
+        recovery.serve();
+        assert!(recovery.serial.rx.is_empty());
+        responses(&recovery.serial.tx)
+    }
+
+    #[test]
+    fn base64() {
+        // RFC 4648's test vectors
+        for (data, text) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(data.as_bytes()), text.as_bytes());
+            assert_eq!(decode(text.as_bytes()).as_deref(), Some(data.as_bytes()));
+        }
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&all)), Some(all));
+        for text in ["Zg=", "Zg==Zm8=", "Z===", "Zm9*", "Zm=v"] {
+            assert_eq!(decode(text.as_bytes()), None, "{}", text);
+        }
+        assert_eq!(base64_decode(b"Zm9vYmFy", &mut [0; 5]), None);
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(b"123456789\x31\xC3"), 0);
+    }
+
+    #[test]
+    fn cbor() {
+        for (value, bytes) in [
+            (23, &[0x17][..]),
+            (24, &[0x18, 24][..]),
+            (255, &[0x18, 0xFF][..]),
+            (256, &[0x19, 1, 0][..]),
+            (65536, &[0x1A, 0, 1, 0, 0][..]),
+        ] {
+            assert_eq!(encoded(|cbor| cbor.uint(value)), bytes);
+            assert_eq!(
+                Decoder {
+                    data: bytes,
+                    pos: 0
+                }
+                .uint(),
+                Some(value)
+            );
+        }
+        let rc = encoded(|cbor| result(cbor, RC_CORRUPT));
+        assert_eq!(rc, [0xA1, 0x62, b'r', b'c', 0x09]);
+        let mut buf = [0; 4];
+        let mut cbor = Encoder {
+            buf: &mut buf,
+            len: 0,
+            overflow: false,
+        };
+        cbor.bytes(&[0; 4]);
+        assert!(cbor.overflow);
+
+        let body = upload(5, b"chunk", Some(100));
+        let request = UploadRequest::parse(&body).unwrap();
+        assert_eq!(
+            (request.image, request.offset, request.len, request.data),
+            (0, 5, Some(100), &b"chunk"[..])
+        );
+        // entries that aren't used are skipped, however they're nested
+        let nested = encoded(|cbor| {
+            cbor.map(3);
+            cbor.text("x");
+            cbor.array(1);
+            cbor.map(1);
+            cbor.uint(1);
+            cbor.bytes(b"y");
+            cbor.text("off");
+            cbor.uint(0);
+            cbor.text("data");
+            cbor.bytes(b"");
+        });
+        assert!(UploadRequest::parse(&nested).is_some());
+        let no_data = encoded(|cbor| {
+            cbor.map(1);
+            cbor.text("off");
+            cbor.uint(0);
+        });
+        assert!(UploadRequest::parse(&no_data).is_none());
+        // indefinite lengths aren't supported
+        assert!(UploadRequest::parse(&[0xA1, 0x64, b'd', b'a', b't', b'a', 0x5F, 0xFF]).is_none());
+    }
+
+    #[test]
+    fn upload_image() {
+        let public_key = test_image::public_key();
+        let image = test_image::build(3, 1200, true, false);
+        let flash = Ram::<4>::new(6);
+        flash.set_bytes(SLOT.start, &[0x12; 100]);
+        assert!(needed(&flash, &SLOT, &public_key, false));
+
+        let mut requests = request(OP_READ, GROUP_IMAGE, IMAGE_STATE, &[0xA0], 200, false);
+        let mut expected = vec![];
+        for (i, offset) in (0..image.len()).step_by(301).enumerate() {
+            let end = (offset + 301).min(image.len());
+            let len = if offset == 0 { Some(image.len()) } else { None };
+            let body = upload(offset, &image[offset..end], len);
+            requests.extend(request(
+                OP_WRITE,
+                GROUP_IMAGE,
+                IMAGE_UPLOAD,
+                &body,
+                60,
+                false,
+            ));
+            expected.push((RC_OK, Some(end as u32)));
+            if i == 1 {
+                // a chunk that was already written is answered with the offset to carry on from
+                let body = upload(1, &image[1..10], None);
+                requests.extend(request(
+                    OP_WRITE,
+                    GROUP_IMAGE,
+                    IMAGE_UPLOAD,
+                    &body,
+                    60,
+                    false,
+                ));
+                expected.push((RC_OK, Some(end as u32)));
+                // a packet with a bad CRC is dropped
+                let body = upload(end, &image[end..end + 5], None);
+                requests.extend(request(
+                    OP_WRITE,
+                    GROUP_IMAGE,
+                    IMAGE_UPLOAD,
+                    &body,
+                    60,
+                    true,
+                ));
+            }
+        }
+        requests.extend(request(
+            OP_READ,
+            GROUP_IMAGE,
+            IMAGE_STATE,
+            &[0xA0],
+            200,
+            false,
+        ));
+        requests.extend(request(OP_READ, 9, 0, &[0xA0], 200, false));
+        requests.extend(request(OP_WRITE, GROUP_OS, OS_RESET, &[0xA0], 200, false));
+        let responses = serve(&flash, &public_key, requests);
+
+        let uploads = expected.len();
+        assert_eq!(responses.len(), uploads + 4);
+        assert_eq!(responses[0].0[0], OP_READ + 1);
+        let empty = encoded(|cbor| {
+            cbor.map(1);
+            cbor.text("images");
+            cbor.array(0);
+        });
+        assert_eq!(responses[0].1, empty);
+        for ((header, body), expected) in responses[1..].iter().zip(expected) {
+            assert_eq!(header[0], OP_WRITE + 1);
+            assert_eq!(decode_result(body), expected);
+        }
+        let mut hash = [0; 32];
+        let header = Header::read(&flash, &SLOT).unwrap();
+        image::find_tlv(&flash, &SLOT, &header, image::TLV_SHA256, &mut hash).unwrap();
+        let listed = encoded(|cbor| {
+            cbor.map(1);
+            cbor.text("images");
+            cbor.array(1);
+            cbor.map(4);
+            cbor.text("slot");
+            cbor.uint(0);
+            cbor.text("version");
+            cbor.text("3.0.7");
+            cbor.text("hash");
+            cbor.bytes(&hash);
+            cbor.text("bootable");
+            cbor.bool(true);
+        });
+        assert_eq!(responses[uploads + 1].1, listed);
+        assert_eq!(
+            decode_result(&responses[uploads + 2].1),
+            (RC_NOT_SUPPORTED, None)
+        );
+        assert_eq!(decode_result(&responses[uploads + 3].1), (RC_OK, None));
+        assert_eq!(flash.bytes(0..image.len() as u32), image);
+        assert!(!needed(&flash, &SLOT, &public_key, false));
+        assert!(needed(&flash, &SLOT, &public_key, true));
+    }
+
+    #[test]
+    fn bad_uploads() {
+        let public_key = test_image::public_key();
+        let mut image = test_image::build(3, 1200, false, false);
+        image[500] ^= 1;
+        let flash = Ram::<4>::new(6);
+        let too_long = 7 * PAGE_SIZE as usize;
+        // a packet larger than the receive buffer is dropped
+        let mut requests = request(
+            OP_WRITE,
+            GROUP_IMAGE,
+            IMAGE_UPLOAD,
+            &upload(0, &image, Some(image.len())),
+            93,
+            false,
+        );
+        for body in [
+            upload(0, &image[..10], Some(too_long)),
+            upload(10, &image[10..20], None),
+            upload(0, &image[..10], None),
+            upload(0, &image[..800], Some(image.len())),
+            upload(800, &image[800..], None),
+            upload(0, &image[..10], Some(0)),
+        ] {
+            requests.extend(request(
+                OP_WRITE,
+                GROUP_IMAGE,
+                IMAGE_UPLOAD,
+                &body,
+                93,
+                false,
+            ));
+        }
+        requests.extend(request(OP_WRITE, GROUP_OS, OS_RESET, &[0xA0], 200, false));
+        let results: Vec<_> = serve(&flash, &public_key, requests)
+            .iter()
+            .map(|(_, body)| decode_result(body))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                (RC_INVALID, None),
+                (RC_INVALID, None),
+                (RC_INVALID, None),
+                (RC_OK, Some(800)),
+                (RC_CORRUPT, None),
+                (RC_INVALID, None),
+                (RC_OK, None),
+            ]
+        );
+        assert!(needed(&flash, &SLOT, &public_key, false));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::RegionParams;
+use core::cell::Cell;
+use core::ops::Range;
+use core::ptr::{read_volatile, write_volatile};
+
+const FLASH_BASE: u32 = 0x00000000;
+const FLASH_SIZE: u32 = 0x10000;
+const RAM_BASE: u32 = 0x20000000;
+const RAM_SIZE: u32 = 0x4000;
+
+/// Address of the NVM user row, the IDAU and PAC load their configuration from it at reset
+const USER_ROW: u32 = 0x00804000;
+const PORT_NONSEC: u32 = 0x400030B4;
+
+fn read_user_word(word: u32) -> u32 {
+    unsafe { read_volatile((USER_ROW + word * 4) as *const u32) }
+}
+
+/// The memory split defined by the NVM user row fuses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct Fuses {
+    /// Size of the boot region (BOOTPROT / BS), in bytes
+    pub boot_size: u32,
+    /// Size of the boot region's non-secure-callable section (BNSC), in bytes
+    pub boot_nsc_size: u32,
+    /// Size of the secure application region (AS), in bytes
+    pub application_secure_size: u32,
+    /// Size of the secure application region's non-secure-callable section (ANSC), in bytes
+    pub application_nsc_size: u32,
+    /// Size of the secure RAM region (RS), in bytes
+    pub ram_secure_size: u32,
+    /// Peripheral security for bridges A, B, and C, a set bit makes the peripheral non-secure
+    pub nonsec: [u32; 3],
+}
+
+impl Fuses {
+    /// Reads the fuses out of the NVM user row
+    pub fn read() -> Self {
+        let word2 = read_user_word(2);
+        let word3 = read_user_word(3);
+        Fuses {
+            boot_size: (word2 & 0xFF) * 0x100,
+            boot_nsc_size: ((word2 >> 16) & 0x3F) * 0x20,
+            application_secure_size: (word3 & 0xFF) * 0x100,
+            application_nsc_size: ((word3 >> 8) & 0x3F) * 0x20,
+            ram_secure_size: ((word3 >> 24) & 0x7F) * 0x80,
+            // NONSECA-C, word 7 is USERCRC
+            nonsec: [read_user_word(4), read_user_word(5), read_user_word(6)],
+        }
+    }
+
+    /// The flash that the IDAU makes secure, which includes both NSC sections
+    pub fn secure_flash(&self) -> Range<u32> {
+        FLASH_BASE..FLASH_BASE + self.boot_size + self.application_secure_size
+    }
+
+    /// The non-secure-callable section at the end of the secure application region
+    pub fn application_nsc(&self) -> Range<u32> {
+        let end = self.secure_flash().end;
+        end - self.application_nsc_size..end
+    }
+
+    /// The non-secure-callable section at the end of the boot region
+    pub fn boot_nsc(&self) -> Range<u32> {
+        let end = FLASH_BASE + self.boot_size;
+        end - self.boot_nsc_size..end
+    }
+
+    pub fn secure_ram(&self) -> Range<u32> {
+        RAM_BASE..RAM_BASE + self.ram_secure_size
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// The flash region doesn't match the split defined by BOOTPROT and AS
+    FlashMismatch {
+        region: Range<u32>,
+        fuses: Range<u32>,
+    },
+    /// The RAM region doesn't match the split defined by RS
+    RamMismatch {
+        region: Range<u32>,
+        fuses: Range<u32>,
+    },
+    /// The NSC region isn't one of the BNSC or ANSC sections
+    NscMismatch(Range<u32>),
+    /// The peripheral is secure in the user row's NONSEC fuses, so it can't be passed at runtime
+    SecurePeripheral(Peripheral),
+    /// PORTA only has 32 pins
+    InvalidPin(u8),
+}
+
+/// FixedIDAU wraps the SAM L11's IDAU. The SAM L11 doesn't implement a SAU, its memory split is fixed at reset
+/// from the NVM user row, so the `MemoryLayout` is checked against those fuses rather than programmed.
+/// Peripherals are passed through the PAC's and PORT's NONSEC settings.
+pub struct FixedIDAU<'a> {
+    /// Pins on PORTA that are kept secure, every other pin is made non-secure through `PORT.NONSEC`
+    pub secure_pins: &'a [u8],
+    fuses: Cell<Option<Fuses>>,
+}
+
+impl<'a> FixedIDAU<'a> {
+    pub const fn new() -> Self {
+        FixedIDAU {
+            secure_pins: &[],
+            fuses: Cell::new(None),
+        }
+    }
+
+    /// Returns the fuses, they are read once and cached
+    pub fn fuses(&self) -> Fuses {
+        match self.fuses.get() {
+            Some(fuses) => fuses,
+            None => {
+                let fuses = Fuses::read();
+                self.fuses.set(Some(fuses));
+                fuses
+            }
+        }
+    }
+
+    /// Checks that `layout` matches the split the IDAU was configured with
+    pub fn validate(&self, layout: &crate::MemoryLayout) -> Result<(), Error> {
+        let fuses = self.fuses();
+        let secure_flash = fuses.secure_flash();
+        if layout.secure_flash_region != secure_flash {
+            return Err(Error::FlashMismatch {
+                region: layout.secure_flash_region.clone(),
+                fuses: secure_flash,
+            });
+        }
+        let non_secure_flash = secure_flash.end..FLASH_BASE + FLASH_SIZE;
+        if !covers(&non_secure_flash, &layout.non_secure_flash_region) {
+            return Err(Error::FlashMismatch {
+                region: layout.non_secure_flash_region.clone(),
+                fuses: non_secure_flash,
+            });
+        }
+        let secure_ram = fuses.secure_ram();
+        if layout.secure_ram_region != secure_ram {
+            return Err(Error::RamMismatch {
+                region: layout.secure_ram_region.clone(),
+                fuses: secure_ram,
+            });
+        }
+        let non_secure_ram = secure_ram.end..RAM_BASE + RAM_SIZE;
+        if !covers(&non_secure_ram, &layout.non_secure_ram_region) {
+            return Err(Error::RamMismatch {
+                region: layout.non_secure_ram_region.clone(),
+                fuses: non_secure_ram,
+            });
+        }
+        if let Some(nsc) = &layout.nsc_flash_region {
+            self.validate_nsc(nsc)?;
+        }
+        self.validate_pins()
+    }
+
+    fn validate_pins(&self) -> Result<(), Error> {
+        match self.secure_pins.iter().find(|pin| **pin >= 32) {
+            Some(pin) => Err(Error::InvalidPin(*pin)),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_nsc(&self, region: &Range<u32>) -> Result<(), Error> {
+        let fuses = self.fuses();
+        if covers(&fuses.application_nsc(), region) || covers(&fuses.boot_nsc(), region) {
+            Ok(())
+        } else {
+            Err(Error::NscMismatch(region.clone()))
+        }
+    }
+
+    fn validate_peripheral(&self, perph: &Peripheral) -> Result<(), Error> {
+        let (bridge, bit) = perph.nonsec_bit();
+        if self.fuses().nonsec[bridge] & (1 << bit) == 0 {
+            return Err(Error::SecurePeripheral(*perph));
+        }
+        Ok(())
+    }
+}
+
+impl Default for FixedIDAU<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true if `inner` lies within `outer`
+fn covers(outer: &Range<u32>, inner: &Range<u32>) -> bool {
+    inner.start >= outer.start && inner.end <= outer.end && inner.start <= inner.end
+}
+
+impl crate::IDAU for FixedIDAU<'_> {
+    type Peripheral = Peripheral;
+
+    fn set_flash_region_params(&self, region: Range<u32>, params: RegionParams) {
+        let fuses = self.fuses().secure_flash();
+        let matches = if params.secure {
+            region == fuses
+        } else {
+            covers(&(fuses.end..FLASH_BASE + FLASH_SIZE), &region)
+        };
+        if !matches {
+            defmt::panic!(
+                "invalid flash region: {:?}",
+                Error::FlashMismatch { region, fuses }
+            );
+        }
+    }
+
+    fn set_memory_region_params(&self, region: Range<u32>, params: RegionParams) {
+        let fuses = self.fuses().secure_ram();
+        let matches = if params.secure {
+            region == fuses
+        } else {
+            covers(&(fuses.end..RAM_BASE + RAM_SIZE), &region)
+        };
+        if !matches {
+            defmt::panic!(
+                "invalid RAM region: {:?}",
+                Error::RamMismatch { region, fuses }
+            );
+        }
+    }
+
+    fn set_nsc_region(&self, region: Range<u32>) {
+        if let Err(err) = self.validate_nsc(&region) {
+            defmt::panic!("invalid NSC region: {:?}", err);
+        }
+    }
+
+    fn pass_peripheral_non_secure(&self, perph: &Self::Peripheral) {
+        if let Err(err) = self.validate_peripheral(perph) {
+            defmt::panic!("invalid peripheral: {:?}", err);
+        }
+        for id in perph.interrupts() {
+            enable_int(*id);
+        }
+    }
+
+    fn peripheral_interrupts(&self, perph: &Self::Peripheral, f: &mut dyn FnMut(usize)) {
+        for id in perph.interrupts() {
+            f(*id);
+        }
+    }
+
+    fn prepare_boot(&self) {
+        if let Err(err) = self.validate_pins() {
+            defmt::panic!("invalid secure pins: {:?}", err);
+        }
+        // a set bit in PORT.NONSEC makes the pin non-secure
+        let secure_pins = self.secure_pins.iter().fold(0u32, |pins, p| pins | 1 << p);
+        unsafe { write_volatile(PORT_NONSEC as *mut u32, !secure_pins) }
+    }
+
+    /// The memory split and peripheral security come from the fuses, which the IDAU and PAC load at reset and
+    /// can't be changed until the next one, so every attribution is reported as locked.
+    fn report(&self) -> crate::audit::SecurityMap {
+        let mut map = crate::audit::SecurityMap::read_core();
+        let fuses = self.fuses();
+        let nsc = |range: Range<u32>| crate::audit::MemoryAttribution {
+            start: range.start,
+            end: range.end,
+            secure: true,
+            non_secure_callable: true,
+            read: true,
+            write: true,
+            execute: true,
+            locked: true,
+        };
+        let boot_nsc = fuses.boot_nsc();
+        let application_nsc = fuses.application_nsc();
+        map.push_range(FLASH_BASE..boot_nsc.start, true, true);
+        if boot_nsc.start < boot_nsc.end {
+            map.push_memory(nsc(boot_nsc.clone()));
+        }
+        map.push_range(boot_nsc.end..application_nsc.start, true, true);
+        if application_nsc.start < application_nsc.end {
+            map.push_memory(nsc(application_nsc.clone()));
+        }
+        map.push_range(application_nsc.end..FLASH_BASE + FLASH_SIZE, false, true);
+        let secure_ram = fuses.secure_ram();
+        map.push_range(secure_ram.clone(), true, true);
+        map.push_range(secure_ram.end..RAM_BASE + RAM_SIZE, false, true);
+        // a set NONSEC bit makes the peripheral non-secure
+        map.non_secure_peripherals[..3].copy_from_slice(&fuses.nonsec);
+        map
+    }
+}
+
+/// A peripheral that can be made non-secure through the user row's NONSEC fuses.
+///
+/// The PORT is mix-secure, so its pins are handled through `FixedIDAU::secure_pins` instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Peripheral {
+    Wdt,
+    Rtc,
+    Eic,
+    Freqm,
+    Ac,
+    Dmac,
+    Evsys,
+    Sercom0,
+    Sercom1,
+    Sercom2,
+    Tc0,
+    Tc1,
+    Tc2,
+    Adc,
+    Dac,
+    Ptc,
+    Trng,
+    Ccl,
+    Opamp,
+}
+
+impl Peripheral {
+    /// Returns the bridge (A = 0, B = 1, C = 2) and the peripheral's bit in that bridge's NONSEC fuses
+    fn nonsec_bit(&self) -> (usize, u32) {
+        use Peripheral::*;
+        match self {
+            Wdt => (0, 8),
+            Rtc => (0, 9),
+            Eic => (0, 10),
+            Freqm => (0, 11),
+            Ac => (0, 13),
+            Dmac => (1, 3),
+            Evsys => (2, 0),
+            Sercom0 => (2, 1),
+            Sercom1 => (2, 2),
+            Sercom2 => (2, 3),
+            Tc0 => (2, 4),
+            Tc1 => (2, 5),
+            Tc2 => (2, 6),
+            Adc => (2, 7),
+            Dac => (2, 8),
+            Ptc => (2, 9),
+            Trng => (2, 10),
+            Ccl => (2, 11),
+            Opamp => (2, 12),
+        }
+    }
+
+    /// Returns the peripheral's interrupt lines
+    fn interrupts(&self) -> &'static [usize] {
+        use Peripheral::*;
+        match self {
+            Wdt => &[1],
+            Rtc => &[2],
+            Eic => &[3, 4, 5, 6, 7],
+            Freqm => &[8],
+            Dmac => &[11, 12, 13, 14, 15],
+            Evsys => &[16, 17, 18, 19, 20],
+            Sercom0 => &[22, 23, 24, 25],
+            Sercom1 => &[26, 27, 28, 29],
+            Sercom2 => &[30, 31, 32, 33],
+            Tc0 => &[34],
+            Tc1 => &[35],
+            Tc2 => &[36],
+            Adc => &[37, 38],
+            Ac => &[39],
+            Dac => &[40, 41],
+            Ptc => &[42],
+            Trng => &[43],
+            Ccl | Opamp => &[],
+        }
+    }
+}
+
+fn enable_int(id: usize) {
+    let peripherals = unsafe { cortex_m::Peripherals::steal() };
+    unsafe {
+        peripherals.NVIC.icer[id / 32].write(1 << (id % 32));
+    }
+    unsafe {
+        peripherals.NVIC.itns[id / 32].modify(|w| w | 1 << (id & 0x1F));
+    }
+}
+
+impl crate::IntoNonSecure<Peripheral> for Peripheral {
+    fn into_non_secure(self) -> Peripheral {
+        self
+    }
+}
@@ -0,0 +1,1265 @@
+use crate::RegionParams;
+use core::ops::Range;
+use cortex_m::peripheral::sau::{SauRegion, SauRegionAttribute};
+use pac::Interrupt;
+
+#[cfg(all(feature = "stm32l552", feature = "stm32l562"))]
+compile_error!("only one STM32L5 variant feature can be enabled at a time");
+
+#[cfg(feature = "stm32l552")]
+use ::stm32l5::stm32l552 as pac;
+#[cfg(feature = "stm32l562")]
+use ::stm32l5::stm32l562 as pac;
+
+/// The AES and PKA are only on the STM32L562
+#[cfg(all(feature = "crypto", feature = "stm32l562"))]
+pub mod crypto;
+
+/// Clears SEC for a single GPIO pin, so it can be used from the non-secure world. The other pins keep their
+/// security.
+macro_rules! pass_gpio_pin {
+    ($gpio:expr, $pin:expr) => {
+        match $pin {
+            pin @ 0..=15 => $gpio
+                .seccfgr
+                .modify(|_, w| unsafe { w.sec(pin).clear_bit() }),
+            pin => defmt::panic!("invalid GPIO pin {}", pin),
+        }
+    };
+}
+
+/// Clears SECM and DSEC for a single DMA channel, so it can be used from the non-secure world.
+macro_rules! pass_dma_channel {
+    ($dma:expr, $channel:expr) => {
+        match $channel {
+            1 => $dma.ccr1.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            2 => $dma.ccr2.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            3 => $dma.ccr3.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            4 => $dma.ccr4.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            5 => $dma.ccr5.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            6 => $dma.ccr6.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            7 => $dma.ccr7.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            8 => $dma.ccr8.write(|w| w.secm().clear_bit().dsec().clear_bit()),
+            c => defmt::panic!("invalid DMA channel {}", c),
+        }
+    };
+}
+
+/// Size of a single MPCBB block, each VCTR register covers 32 of them
+const MPCBB_BLOCK_SIZE: u32 = 0x100;
+/// Size of a block in a GTZC MPCWM non-secure window, external memory windows must be aligned to this.
+pub const MPCWM_GRANULARITY: u32 = 0x20000;
+/// Number of regions implemented by the SAU
+const SAU_REGIONS: usize = 8;
+/// SAU regions 0-3 are used for non-secure flash, non-secure RAM, NSC and the peripheral space
+const FIXED_SAU_REGIONS: usize = 4;
+
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// An external memory window doesn't start and end on a [`MPCWM_GRANULARITY`] boundary
+    UnalignedWindow { start: u32, end: u32 },
+    /// An external memory window is empty or falls outside of its bank
+    WindowOutOfBounds {
+        bank: ExternalBank,
+        start: u32,
+        end: u32,
+    },
+    /// The bank's MPCWM has no window left for another region
+    NoFreeWindow(ExternalBank),
+    /// The configuration needs more regions than the SAU implements
+    TooManySauRegions(usize),
+}
+
+/// GTZC (Global TrustZone Controller) is the STM32L5's IDAU. It is configured with the external
+/// memory regions and system memory that should be exposed to the non-secure world, everything else
+/// outside of the `MemoryLayout` passed to `boot` remains secure.
+pub struct GTZC<'a> {
+    /// Windows of external memory (OCTOSPI1 or FMC) that should be non-secure. The rest of each
+    /// bank stays secure, which allows for things like a secure update staging area in external flash.
+    pub external_memory: &'a [ExternalMemoryRegion],
+    /// Marks the system memory region (ST bootloader, OTP and engineering bytes) as non-secure.
+    pub non_secure_system_memory: bool,
+    pub rcc: RccSecurity,
+    pub pwr: PwrSecurity,
+    pub exti: ExtiSecurity,
+    pub rtc: RtcSecurity,
+}
+
+impl GTZC<'_> {
+    pub const fn new() -> Self {
+        GTZC {
+            external_memory: &[],
+            non_secure_system_memory: false,
+            rcc: RccSecurity::new(),
+            pwr: PwrSecurity::new(),
+            exti: ExtiSecurity::new(),
+            rtc: RtcSecurity::new(),
+        }
+    }
+}
+
+impl Default for GTZC<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl crate::IDAU for GTZC<'_> {
+    type Peripheral = Peripheral;
+    fn set_flash_region_params(&self, region: Range<u32>, params: RegionParams) {
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+        if !params.secure {
+            defmt::println!("ns region start: {:x} end: {:x}", region.start, region.end);
+            sau.set_region(
+                0,
+                SauRegion {
+                    base_address: region.start,
+                    limit_address: region.end,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    fn set_memory_region_params(&self, region: Range<u32>, params: RegionParams) {
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mpcbb1 = unsafe { &*pac::SEC_GTZC_MPCBB1::PTR };
+        mpcbb1
+            .cr
+            .write(|w| w.srwiladis().set_bit().invsecstate().clear_bit());
+        mpcbb1.lckvtr1.write(|w| unsafe { w.bits(0x0) });
+        for i in 0..12 {
+            mpcbb1.vctr[i].write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        }
+        for i in 12..24 {
+            mpcbb1.vctr[i].write(|w| unsafe { w.bits(0x0) });
+        }
+        let mut sau = p.SAU;
+        if !params.secure {
+            sau.set_region(
+                1,
+                SauRegion {
+                    base_address: region.start,
+                    limit_address: region.end,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    fn set_nsc_region(&self, region: Range<u32>) {
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+        sau.set_region(
+            2,
+            SauRegion {
+                base_address: region.start,
+                limit_address: region.end,
+                attribute: SauRegionAttribute::NonSecureCallable,
+            },
+        )
+        .unwrap();
+    }
+
+    fn pass_peripheral_non_secure(&self, perph: &Self::Peripheral) {
+        let tzsc = unsafe { &*pac::SEC_GTZC_TZSC::PTR };
+        match perph {
+            Peripheral::ADC => {
+                tzsc.seccfgr2.write(|w| w.adcsec().clear_bit());
+            }
+            #[cfg(feature = "stm32l562")]
+            Peripheral::AES => {
+                tzsc.seccfgr2.write(|w| w.aessec().clear_bit());
+            }
+            Peripheral::CRC => {
+                tzsc.seccfgr2.write(|w| w.crcsec().clear_bit());
+            }
+            Peripheral::DFSDM => {
+                tzsc.seccfgr2.write(|w| w.dfsdm1sec().clear_bit());
+            }
+            Peripheral::FSMCReg => {
+                tzsc.seccfgr2.write(|w| w.dfsdm1sec().clear_bit());
+            }
+            #[cfg(feature = "stm32l562")]
+            Peripheral::Hash => {
+                tzsc.seccfgr2.write(|w| w.hashsec().clear_bit());
+            }
+            Peripheral::Icache => {
+                tzsc.seccfgr2.write(|w| w.icachesec().clear_bit());
+            }
+            Peripheral::OctoSPI1 => {
+                tzsc.seccfgr2.write(|w| w.octospi1_regsec().clear_bit());
+            }
+            #[cfg(feature = "stm32l562")]
+            Peripheral::PKA => {
+                tzsc.seccfgr2.write(|w| w.pkasec().clear_bit());
+            }
+            Peripheral::RNG => {
+                tzsc.seccfgr2.write(|w| w.rngsec().clear_bit());
+            }
+            Peripheral::SAI1 => {
+                tzsc.seccfgr2.write(|w| w.sai1sec().clear_bit());
+            }
+            Peripheral::SAI2 => {
+                tzsc.seccfgr2.write(|w| w.sai2sec().clear_bit());
+            }
+            Peripheral::SDMMC1 => {
+                tzsc.seccfgr2.write(|w| w.sdmmc1sec().clear_bit());
+            }
+            Peripheral::Comp => {
+                tzsc.seccfgr1.write(|w| w.compsec().clear_bit());
+            }
+            Peripheral::Crs => {
+                tzsc.seccfgr1.write(|w| w.crssec().clear_bit());
+            }
+            Peripheral::Dac => {
+                tzsc.seccfgr1.write(|w| w.dacsec().clear_bit());
+            }
+            Peripheral::FdCan => {
+                tzsc.seccfgr1.write(|w| w.fdcan1sec().clear_bit());
+            }
+            Peripheral::I2C1 => {
+                tzsc.seccfgr1.write(|w| w.i2c1sec().clear_bit());
+            }
+            Peripheral::I2C2 => {
+                tzsc.seccfgr1.write(|w| w.i2c2sec().clear_bit());
+            }
+            Peripheral::I2C3 => {
+                tzsc.seccfgr1.write(|w| w.i2c3sec().clear_bit());
+            }
+            Peripheral::I2C4 => {
+                tzsc.seccfgr1.write(|w| w.i2c4sec().clear_bit());
+            }
+            Peripheral::IWDG => {
+                tzsc.seccfgr1.write(|w| w.iwdgsec().clear_bit());
+            }
+            Peripheral::LPTIM1 => {
+                tzsc.seccfgr1.write(|w| w.lptim1sec().clear_bit());
+            }
+            Peripheral::LPTIM2 => {
+                tzsc.seccfgr1.write(|w| w.lptim2sec().clear_bit());
+            }
+            Peripheral::LPUart => {
+                tzsc.seccfgr1.write(|w| w.lpuart1sec().clear_bit());
+            }
+            Peripheral::OPAMP => {
+                tzsc.seccfgr1.write(|w| w.opampsec().clear_bit());
+            }
+            Peripheral::SPI1 => {
+                tzsc.seccfgr1.write(|w| w.spi1sec().clear_bit());
+            }
+            Peripheral::SPI2 => {
+                tzsc.seccfgr1.write(|w| w.spi2sec().clear_bit());
+            }
+            Peripheral::SPI3 => {
+                tzsc.seccfgr1.write(|w| w.spi3sec().clear_bit());
+            }
+            Peripheral::Tim1 => {
+                tzsc.seccfgr1.write(|w| w.tim1sec().clear_bit());
+            }
+            Peripheral::Tim2 => {
+                tzsc.seccfgr1.write(|w| w.tim2sec().clear_bit());
+            }
+            Peripheral::Tim3 => {
+                tzsc.seccfgr1.write(|w| w.tim3sec().clear_bit());
+            }
+            Peripheral::Tim4 => {
+                tzsc.seccfgr1.write(|w| w.tim4sec().clear_bit());
+            }
+            Peripheral::Tim5 => {
+                tzsc.seccfgr1.write(|w| w.tim5sec().clear_bit());
+            }
+            Peripheral::Tim6 => {
+                tzsc.seccfgr1.write(|w| w.tim6sec().clear_bit());
+            }
+            Peripheral::Tim7 => {
+                tzsc.seccfgr1.write(|w| w.tim7sec().clear_bit());
+            }
+            Peripheral::Tim8 => {
+                tzsc.seccfgr2.write(|w| w.tim8sec().clear_bit());
+            }
+            Peripheral::Tim15 => {
+                tzsc.seccfgr2.write(|w| w.tim15sec().clear_bit());
+            }
+            Peripheral::Tim16 => {
+                tzsc.seccfgr2.write(|w| w.tim16sec().clear_bit());
+            }
+            Peripheral::Tim17 => {
+                tzsc.seccfgr2.write(|w| w.tim17sec().clear_bit());
+            }
+            Peripheral::TSC => {
+                tzsc.seccfgr2.write(|w| w.tscsec().clear_bit());
+            }
+            Peripheral::Uart4 => {
+                tzsc.seccfgr1.write(|w| w.uart4sec().clear_bit());
+            }
+            Peripheral::Uart5 => {
+                tzsc.seccfgr1.write(|w| w.uart5sec().clear_bit());
+            }
+            Peripheral::Ucpd1 => {
+                tzsc.seccfgr1.write(|w| w.ucpd1sec().clear_bit());
+            }
+            Peripheral::Usart1 => {
+                tzsc.seccfgr2.write(|w| w.usart1sec().clear_bit());
+            }
+            Peripheral::Usart2 => {
+                tzsc.seccfgr1.write(|w| w.usart2sec().clear_bit());
+            }
+            Peripheral::Usart3 => {
+                tzsc.seccfgr1.write(|w| w.usart3sec().clear_bit());
+            }
+            Peripheral::USBFS => {
+                tzsc.seccfgr1.write(|w| w.usbfssec().clear_bit());
+            }
+            Peripheral::VrefBuf => {
+                tzsc.seccfgr1.write(|w| w.vrefbufsec().clear_bit());
+            }
+            Peripheral::WWDG => {
+                tzsc.seccfgr1.write(|w| w.wwdgsec().clear_bit());
+            }
+            Peripheral::GPIOA(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOA::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOB(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOB::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOC(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOC::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOD(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOD::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOE(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOE::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOF(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOF::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOG(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOG::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOH(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOH::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::DMA1(channel) => {
+                let dma1 = unsafe { &*pac::SEC_DMA1::PTR };
+                pass_dma_channel!(dma1, *channel);
+                pass_dmamux_channel(*channel - 1);
+            }
+            Peripheral::DMA2(channel) => {
+                let dma2 = unsafe { &*pac::SEC_DMA2::PTR };
+                pass_dma_channel!(dma2, *channel);
+                pass_dmamux_channel(*channel + 7);
+            }
+        }
+        perph.for_each_interrupt(enable_int);
+    }
+
+    fn peripheral_interrupts(&self, perph: &Self::Peripheral, f: &mut dyn FnMut(usize)) {
+        perph.for_each_interrupt(f);
+    }
+
+    fn prepare_boot(&self) {
+        let windows = match self.external_memory_windows() {
+            Ok(windows) => windows,
+            Err(err) => defmt::panic!("invalid GTZC configuration: {:?}", err),
+        };
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+        let syscfg = unsafe { &*pac::SYSCFG::PTR };
+        syscfg
+            .seccfgr
+            .write(|w| w.syscfgsec().clear_bit().classbsec().clear_bit());
+        // enable illegal access detection for every peripheral and memory
+        let tzic = unsafe { &*pac::SEC_GTZC_TZIC::PTR };
+        tzic.ier1.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        tzic.ier2.write(|w| unsafe { w.bits(0x3FFFFFFF) });
+        tzic.ier3.write(|w| unsafe { w.bits(0x000000FF) });
+        self.rcc.apply();
+        self.pwr.apply();
+        self.exti.apply();
+        self.rtc.apply();
+        // set all peripheral memory blocks as non-secure GTZC
+        sau.set_region(
+            3,
+            SauRegion {
+                base_address: 0x40000000,
+                limit_address: 0x4FFFFFFF,
+                attribute: SauRegionAttribute::NonSecure,
+            },
+        )
+        .unwrap();
+        let mut sau_region = 4;
+        if self.non_secure_system_memory {
+            // system memory contains the ST bootloader, OTP area and engineering bytes
+            sau.set_region(
+                sau_region,
+                SauRegion {
+                    base_address: 0x0BF90000,
+                    limit_address: 0x0BFA8FFF,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+            sau_region += 1;
+        }
+        self.set_external_memory_windows(windows);
+        for region in self.external_memory {
+            // the SAU needs to mark the window non-secure as well, otherwise non-secure accesses
+            // never reach the MPCWM
+            sau.set_region(
+                sau_region,
+                SauRegion {
+                    base_address: region.non_secure_window.start,
+                    limit_address: region.non_secure_window.end - 1,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+            sau_region += 1;
+        }
+        sau.enable();
+    }
+
+    fn report(&self) -> crate::audit::SecurityMap {
+        let mut map = crate::audit::SecurityMap::read_core();
+        // a cleared bit in SECCFGR makes the peripheral non-secure
+        let tzsc = unsafe { &*pac::SEC_GTZC_TZSC::PTR };
+        map.non_secure_peripherals[0] = !tzsc.seccfgr1.read().bits();
+        map.non_secure_peripherals[1] = !tzsc.seccfgr2.read().bits();
+
+        // a set bit in VCTR makes the block secure
+        let mpcbb1 = unsafe { &*pac::SEC_GTZC_MPCBB1::PTR };
+        let mpcbb2 = unsafe { &*pac::SEC_GTZC_MPCBB2::PTR };
+        let mut sram1 = [0u32; 24];
+        for (i, bits) in sram1.iter_mut().enumerate() {
+            *bits = mpcbb1.vctr[i].read().bits();
+        }
+        let mut sram2 = [0u32; 8];
+        for (i, bits) in sram2.iter_mut().enumerate() {
+            *bits = mpcbb2.vctr[i].read().bits();
+        }
+        let sram1_lock = mpcbb1.lckvtr1.read().bits();
+        let sram2_lock = mpcbb2.lckvtr1.read().bits();
+        map.push_blocks(0x20000000, MPCBB_BLOCK_SIZE, &sram1, sram1_lock);
+        map.push_blocks(0x20030000, MPCBB_BLOCK_SIZE, &sram2, sram2_lock);
+
+        // TZSC_CR.LCK locks the watermarks along with the rest of the TZSC
+        let locked = tzsc.cr.read().bits() & 1 != 0;
+        let nswmr = [
+            tzsc.mpcwm1_nswmr1.read().bits(),
+            tzsc.mpcwm1_nswmr2.read().bits(),
+            tzsc.mpcwm2_nswmr1.read().bits(),
+            tzsc.mpcwm2_nswmr2.read().bits(),
+            tzsc.mpcwm3_nswmr1.read().bits(),
+        ];
+        for bank in [
+            ExternalBank::FMCNor,
+            ExternalBank::FMCNand,
+            ExternalBank::OctoSPI1,
+        ] {
+            let (first, count) = bank.windows();
+            // MPCWM3 only has one window
+            let window = |i: usize| {
+                if i >= count {
+                    return 0..0;
+                }
+                let bits = nswmr[first + i];
+                let start = bank.base_address() + (bits & 0x7FF) * MPCWM_GRANULARITY;
+                start..start + ((bits >> 16) & 0xFFF) * MPCWM_GRANULARITY
+            };
+            let bank_range = bank.base_address()..bank.base_address() + bank.size();
+            map.push_watermarks(bank_range, [window(0), window(1)], locked);
+        }
+        map
+    }
+}
+
+/// Marks a DMAMUX1 channel (and so its request line) as non-secure. DMAMUX1 channels 0-7
+/// feed DMA1 channels 1-8, and channels 8-15 feed DMA2 channels 1-8.
+fn pass_dmamux_channel(channel: usize) {
+    let dmamux = unsafe { &*pac::SEC_DMAMUX1::PTR };
+    // NOTE: only the channel's SECCFGR bit is cleared. The overrun flags and the DMAMUX1_OVR interrupt
+    // are shared between every channel, so they stay secure and the interrupt is never targeted at
+    // the non-secure world.
+    dmamux
+        .seccfgr
+        .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << channel)) });
+}
+
+impl GTZC<'_> {
+    /// Checks that every external memory window fits its bank's MPCWM, and that the SAU has enough regions
+    /// for the windows and system memory on top of the flash, RAM, NSC and peripheral regions.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.external_memory_windows().map(|_| ())
+    }
+
+    /// Works out the NSWMR values for MPCWM1 (2 windows), MPCWM2 (2 windows), and MPCWM3 (1 window).
+    /// Every window that isn't listed in `external_memory` is given a length of zero, so the entire
+    /// bank it guards stays secure.
+    fn external_memory_windows(&self) -> Result<[u32; 5], Error> {
+        let needed =
+            FIXED_SAU_REGIONS + self.non_secure_system_memory as usize + self.external_memory.len();
+        if needed > SAU_REGIONS {
+            return Err(Error::TooManySauRegions(needed));
+        }
+        let mut windows = [0u32; 5];
+        let mut used = [0usize; 3];
+        for region in self.external_memory {
+            let bank = region.bank;
+            let window = &region.non_secure_window;
+            if window.start % MPCWM_GRANULARITY != 0 || window.end % MPCWM_GRANULARITY != 0 {
+                return Err(Error::UnalignedWindow {
+                    start: window.start,
+                    end: window.end,
+                });
+            }
+            if window.start < bank.base_address()
+                || window.end > bank.base_address() + bank.size()
+                || window.start >= window.end
+            {
+                return Err(Error::WindowOutOfBounds {
+                    bank,
+                    start: window.start,
+                    end: window.end,
+                });
+            }
+            let (first, count) = bank.windows();
+            let slot = used[bank as usize];
+            if slot >= count {
+                return Err(Error::NoFreeWindow(bank));
+            }
+            used[bank as usize] += 1;
+            let start = (window.start - bank.base_address()) / MPCWM_GRANULARITY;
+            let length = (window.end - window.start) / MPCWM_GRANULARITY;
+            windows[first + slot] = (start & 0x7FF) | ((length & 0xFFF) << 16);
+        }
+        Ok(windows)
+    }
+
+    /// Programs the MPCWM watermark controllers
+    fn set_external_memory_windows(&self, windows: [u32; 5]) {
+        let tzsc = unsafe { &*pac::SEC_GTZC_TZSC::PTR };
+        tzsc.mpcwm1_nswmr1.write(|w| unsafe { w.bits(windows[0]) });
+        tzsc.mpcwm1_nswmr2.write(|w| unsafe { w.bits(windows[1]) });
+        tzsc.mpcwm2_nswmr1.write(|w| unsafe { w.bits(windows[2]) });
+        tzsc.mpcwm2_nswmr2.write(|w| unsafe { w.bits(windows[3]) });
+        tzsc.mpcwm3_nswmr1.write(|w| unsafe { w.bits(windows[4]) });
+    }
+}
+
+/// Security attributes for the RCC, each field that is `true` can only be configured by secure code.
+/// By default every clock is configurable from the non-secure world.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RccSecurity {
+    pub hsi: bool,
+    pub hse: bool,
+    pub msi: bool,
+    pub lsi: bool,
+    pub lse: bool,
+    /// The system clock source switch
+    pub sysclk: bool,
+    /// AHB and APB prescalers
+    pub prescalers: bool,
+    pub pll: bool,
+    pub pllsai1: bool,
+    pub pllsai2: bool,
+    pub clk48m: bool,
+    pub hsi48: bool,
+    /// The "remove reset flag" bit
+    pub reset_flags: bool,
+}
+
+impl RccSecurity {
+    pub const fn new() -> Self {
+        RccSecurity {
+            hsi: false,
+            hse: false,
+            msi: false,
+            lsi: false,
+            lse: false,
+            sysclk: false,
+            prescalers: false,
+            pll: false,
+            pllsai1: false,
+            pllsai2: false,
+            clk48m: false,
+            hsi48: false,
+            reset_flags: false,
+        }
+    }
+
+    fn apply(&self) {
+        let rcc = unsafe { &*pac::SEC_RCC::PTR };
+        rcc.seccfgr.write(|w| {
+            w.hsisec().bit(self.hsi);
+            w.hsesec().bit(self.hse);
+            w.msisec().bit(self.msi);
+            w.lsisec().bit(self.lsi);
+            w.lsesec().bit(self.lse);
+            w.sysclksec().bit(self.sysclk);
+            w.prescsec().bit(self.prescalers);
+            w.pllsec().bit(self.pll);
+            w.pllsai1sec().bit(self.pllsai1);
+            w.pllsai2sec().bit(self.pllsai2);
+            w.clk48msec().bit(self.clk48m);
+            w.hsi48sec().bit(self.hsi48);
+            w.rmvfsec().bit(self.reset_flags);
+            w
+        });
+    }
+}
+
+/// Security attributes for the PWR controller, each field that is `true` can only be configured by secure code.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PwrSecurity {
+    /// Low-power modes
+    pub low_power_modes: bool,
+    /// Voltage detection and monitoring
+    pub voltage_detection: bool,
+    /// Backup domain
+    pub vbat: bool,
+    /// Pull-up / pull-down configuration applied in standby and shutdown
+    pub pull_configuration: bool,
+    /// Mask of the secure wakeup pins, bit 0 is WKUP1
+    pub wakeup_pins: u8,
+}
+
+impl PwrSecurity {
+    pub const fn new() -> Self {
+        PwrSecurity {
+            low_power_modes: false,
+            voltage_detection: false,
+            vbat: false,
+            pull_configuration: false,
+            wakeup_pins: 0,
+        }
+    }
+
+    fn apply(&self) {
+        let pwr = unsafe { &*pac::SEC_PWR::PTR };
+        let wakeup_pin = |n: u8| self.wakeup_pins & 1 << n != 0;
+        pwr.seccfgr.write(|w| {
+            w.wup1sec().bit(wakeup_pin(0));
+            w.wup2sec().bit(wakeup_pin(1));
+            w.wup3sec().bit(wakeup_pin(2));
+            w.wup4sec().bit(wakeup_pin(3));
+            w.wup5sec().bit(wakeup_pin(4));
+            w.lpmsec().bit(self.low_power_modes);
+            w.vdmsec().bit(self.voltage_detection);
+            w.vbsec().bit(self.vbat);
+            w.apcsec().bit(self.pull_configuration);
+            w
+        });
+    }
+}
+
+/// Security and privilege attributes for the EXTI lines, bit `n` of each mask configures line `n`.
+///
+/// The interrupts for EXTI0-15 are targeted at the non-secure world for every line that isn't secure.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExtiSecurity {
+    pub secure_lines: u64,
+    pub privileged_lines: u64,
+}
+
+impl ExtiSecurity {
+    pub const fn new() -> Self {
+        ExtiSecurity {
+            secure_lines: 0,
+            privileged_lines: 0,
+        }
+    }
+
+    fn apply(&self) {
+        let exti = unsafe { &*pac::SEC_EXTI::PTR };
+        exti.seccfgr1
+            .write(|w| unsafe { w.bits(self.secure_lines as u32) });
+        exti.seccfgr2
+            .write(|w| unsafe { w.bits((self.secure_lines >> 32) as u32) });
+        exti.privcfgr1
+            .write(|w| unsafe { w.bits(self.privileged_lines as u32) });
+        exti.privcfgr2
+            .write(|w| unsafe { w.bits((self.privileged_lines >> 32) as u32) });
+        let interrupts = [
+            Interrupt::EXTI0,
+            Interrupt::EXTI1,
+            Interrupt::EXTI2,
+            Interrupt::EXTI3,
+            Interrupt::EXTI4,
+            Interrupt::EXTI5,
+            Interrupt::EXTI6,
+            Interrupt::EXTI7,
+            Interrupt::EXTI8,
+            Interrupt::EXTI9,
+            Interrupt::EXTI10,
+            Interrupt::EXTI11,
+            Interrupt::EXTI12,
+            Interrupt::EXTI13,
+            Interrupt::EXTI14,
+            Interrupt::EXTI15,
+        ];
+        for (line, int) in interrupts.iter().enumerate() {
+            if self.secure_lines & (1 << line) == 0 {
+                enable_int(*int as usize);
+            }
+        }
+    }
+}
+
+/// Splits the RTC and TAMP between the secure and non-secure worlds.
+///
+/// Writing these registers requires backup domain write access (`PWR_CR1.DBP`) to be enabled before `boot`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RtcSecurity {
+    /// Makes every RTC register secure
+    pub rtc: bool,
+    /// Makes the tamper configuration secure
+    pub tamp: bool,
+    /// Backup registers below this index can only be read and written by secure code
+    pub secure_backup_registers: u8,
+    /// Backup registers below this index can only be written by secure code
+    pub write_protected_backup_registers: u8,
+}
+
+impl RtcSecurity {
+    pub const fn new() -> Self {
+        RtcSecurity {
+            rtc: false,
+            tamp: false,
+            secure_backup_registers: 0,
+            write_protected_backup_registers: 0,
+        }
+    }
+
+    fn apply(&self) {
+        let rtc = unsafe { &*pac::SEC_RTC::PTR };
+        let tamp = unsafe { &*pac::SEC_TAMP::PTR };
+        // DECPROT is the global RTC protection bit
+        rtc.smcr
+            .write(|w| unsafe { w.bits((self.rtc as u32) << 15) });
+        let bits = self.secure_backup_registers as u32
+            | (self.write_protected_backup_registers as u32) << 16
+            | (self.tamp as u32) << 31;
+        tamp.smcr.write(|w| unsafe { w.bits(bits) });
+        if !self.rtc {
+            enable_int(Interrupt::RTC as usize);
+        }
+        if !self.tamp {
+            enable_int(Interrupt::TAMP as usize);
+        }
+    }
+}
+
+/// External memory banks that are guarded by one of the GTZC MPCWM watermark controllers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum ExternalBank {
+    /// The OCTOSPI1 memory-mapped bank, guarded by MPCWM2
+    OctoSPI1,
+    /// FMC bank 1 (NOR / PSRAM / SRAM), guarded by MPCWM1
+    FMCNor,
+    /// FMC bank 3 (NAND), guarded by MPCWM3
+    FMCNand,
+}
+
+impl ExternalBank {
+    pub fn base_address(&self) -> u32 {
+        match self {
+            ExternalBank::OctoSPI1 => 0x90000000,
+            ExternalBank::FMCNor => 0x60000000,
+            ExternalBank::FMCNand => 0x80000000,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        0x10000000
+    }
+
+    /// Returns the index of the bank's first window, and the number of windows it supports
+    fn windows(&self) -> (usize, usize) {
+        match self {
+            ExternalBank::FMCNor => (0, 2),
+            ExternalBank::OctoSPI1 => (2, 2),
+            ExternalBank::FMCNand => (4, 1),
+        }
+    }
+}
+
+/// ExternalMemoryRegion marks a window of an external memory bank as non-secure.
+///
+/// `non_secure_window` is an absolute address range, both ends must be aligned to [`MPCWM_GRANULARITY`].
+#[derive(Clone, Debug)]
+pub struct ExternalMemoryRegion {
+    pub bank: ExternalBank,
+    pub non_secure_window: Range<u32>,
+}
+
+pub enum Peripheral {
+    ADC,
+    #[cfg(feature = "stm32l562")]
+    AES,
+    CRC,
+    DFSDM,
+    FSMCReg,
+    #[cfg(feature = "stm32l562")]
+    Hash,
+    Icache,
+    OctoSPI1,
+    #[cfg(feature = "stm32l562")]
+    PKA,
+    RNG,
+    SAI1,
+    SAI2,
+    SDMMC1,
+    Comp,
+    Crs,
+    Dac,
+    FdCan,
+    I2C1,
+    I2C2,
+    I2C3,
+    I2C4,
+    IWDG,
+    LPTIM1,
+    LPTIM2,
+    LPUart,
+    OPAMP,
+    SPI1,
+    SPI2,
+    SPI3,
+    Tim1,
+    Tim2,
+    Tim3,
+    Tim4,
+    Tim5,
+    Tim6,
+    Tim7,
+    Tim8,
+    Tim15,
+    Tim16,
+    Tim17,
+    TSC,
+    Uart4,
+    Uart5,
+    Ucpd1,
+    Usart1,
+    Usart2,
+    Usart3,
+    USBFS,
+    VrefBuf,
+    WWDG,
+    GPIOA(usize),
+    GPIOB(usize),
+    GPIOC(usize),
+    GPIOD(usize),
+    GPIOE(usize),
+    GPIOF(usize),
+    GPIOG(usize),
+    GPIOH(usize),
+    /// A single DMA1 channel (1-8)
+    DMA1(usize),
+    /// A single DMA2 channel (1-8)
+    DMA2(usize),
+}
+impl Peripheral {
+    pub fn all() -> &'static [Peripheral] {
+        use Peripheral::*;
+        &[
+            ADC,
+            #[cfg(feature = "stm32l562")]
+            AES,
+            CRC,
+            DFSDM,
+            FSMCReg,
+            #[cfg(feature = "stm32l562")]
+            Hash,
+            Icache,
+            OctoSPI1,
+            #[cfg(feature = "stm32l562")]
+            PKA,
+            RNG,
+            SAI1,
+            SAI2,
+            SDMMC1,
+            Comp,
+            Crs,
+            Dac,
+            FdCan,
+            I2C1,
+            I2C2,
+            I2C3,
+            I2C4,
+            IWDG,
+            LPTIM1,
+            LPTIM2,
+            LPUart,
+            OPAMP,
+            SPI1,
+            SPI2,
+            SPI3,
+            Tim1,
+            Tim2,
+            Tim3,
+            Tim4,
+            Tim5,
+            Tim6,
+            Tim7,
+            Tim8,
+            Tim15,
+            Tim16,
+            Tim17,
+            TSC,
+            Uart4,
+            Uart5,
+            Ucpd1,
+            Usart1,
+            Usart2,
+            Usart3,
+            USBFS,
+            VrefBuf,
+            WWDG,
+            GPIOA(1),
+            GPIOA(2),
+            GPIOA(3),
+            GPIOA(4),
+            GPIOA(5),
+            GPIOA(6),
+            GPIOA(7),
+            GPIOA(8),
+            GPIOA(9),
+            GPIOA(10),
+            GPIOA(11),
+            GPIOA(12),
+            GPIOA(13),
+            GPIOA(14),
+            GPIOA(15),
+            GPIOB(1),
+            GPIOB(2),
+            GPIOB(3),
+            GPIOB(4),
+            GPIOB(5),
+            GPIOB(6),
+            GPIOB(7),
+            GPIOB(8),
+            GPIOB(9),
+            GPIOB(10),
+            GPIOB(11),
+            GPIOB(12),
+            GPIOB(13),
+            GPIOB(14),
+            GPIOB(15),
+            GPIOC(1),
+            GPIOC(2),
+            GPIOC(3),
+            GPIOC(4),
+            GPIOC(5),
+            GPIOC(6),
+            GPIOC(7),
+            GPIOC(8),
+            GPIOC(9),
+            GPIOC(10),
+            GPIOC(11),
+            GPIOC(12),
+            GPIOC(13),
+            GPIOC(14),
+            GPIOC(15),
+            GPIOD(1),
+            GPIOD(2),
+            GPIOD(3),
+            GPIOD(4),
+            GPIOD(5),
+            GPIOD(6),
+            GPIOD(7),
+            GPIOD(8),
+            GPIOD(9),
+            GPIOD(10),
+            GPIOD(11),
+            GPIOD(12),
+            GPIOD(13),
+            GPIOD(14),
+            GPIOD(15),
+            GPIOE(1),
+            GPIOE(2),
+            GPIOE(3),
+            GPIOE(4),
+            GPIOE(5),
+            GPIOE(6),
+            GPIOE(7),
+            GPIOE(8),
+            GPIOE(9),
+            GPIOE(10),
+            GPIOE(11),
+            GPIOE(12),
+            GPIOE(13),
+            GPIOE(14),
+            GPIOE(15),
+            GPIOF(1),
+            GPIOF(2),
+            GPIOF(3),
+            GPIOF(4),
+            GPIOF(5),
+            GPIOF(6),
+            GPIOF(7),
+            GPIOF(8),
+            GPIOF(9),
+            GPIOF(10),
+            GPIOF(11),
+            GPIOF(12),
+            GPIOF(13),
+            GPIOG(1),
+            GPIOG(2),
+            GPIOG(3),
+            GPIOG(4),
+            GPIOG(5),
+            GPIOG(6),
+            GPIOG(7),
+            GPIOG(8),
+            GPIOG(9),
+            GPIOG(10),
+            GPIOG(11),
+            GPIOG(12),
+            GPIOG(13),
+            GPIOG(14),
+            GPIOG(15),
+            GPIOH(1),
+            GPIOH(2),
+            GPIOH(3),
+            GPIOH(4),
+            GPIOH(5),
+            GPIOH(6),
+            GPIOH(7),
+            GPIOH(8),
+            GPIOH(9),
+            GPIOH(10),
+            GPIOH(11),
+            GPIOH(12),
+            GPIOH(13),
+            GPIOH(14),
+            GPIOH(15),
+            DMA1(1),
+            DMA1(2),
+            DMA1(3),
+            DMA1(4),
+            DMA1(5),
+            DMA1(6),
+            DMA1(7),
+            DMA1(8),
+            DMA2(1),
+            DMA2(2),
+            DMA2(3),
+            DMA2(4),
+            DMA2(5),
+            DMA2(6),
+            DMA2(7),
+            DMA2(8),
+        ]
+    }
+}
+
+impl Peripheral {
+    /// Calls `f` with each of the peripheral's interrupt lines
+    fn for_each_interrupt(&self, mut f: impl FnMut(usize)) {
+        match self {
+            Peripheral::Icache => f(Interrupt::ICACHE as usize),
+            Peripheral::OctoSPI1 => {
+                f(Interrupt::OCTOSPI1 as usize);
+            }
+            #[cfg(feature = "stm32l562")]
+            Peripheral::PKA => {
+                f(Interrupt::PKA as usize);
+            }
+            Peripheral::RNG => {
+                f(Interrupt::RNG as usize);
+            }
+            Peripheral::SAI1 => {
+                f(Interrupt::SAI1 as usize);
+            }
+            Peripheral::SAI2 => {
+                f(Interrupt::SAI2 as usize);
+            }
+            Peripheral::SDMMC1 => {
+                f(Interrupt::SDMMC1 as usize);
+            }
+            Peripheral::Comp => {
+                f(Interrupt::COMP as usize);
+            }
+            Peripheral::FdCan => {
+                f(Interrupt::FDCAN1_IT0 as usize);
+                f(Interrupt::FDCAN1_IT1 as usize);
+            }
+            Peripheral::I2C1 => {
+                f(Interrupt::I2C1_EV as usize);
+                f(Interrupt::I2C1_ER as usize);
+            }
+            Peripheral::I2C2 => {
+                f(Interrupt::I2C2_EV as usize);
+                f(Interrupt::I2C2_ER as usize);
+            }
+            Peripheral::I2C3 => {
+                f(Interrupt::I2C3_EV as usize);
+                f(Interrupt::I2C3_ER as usize);
+            }
+            Peripheral::I2C4 => {
+                f(Interrupt::I2C4_EV as usize);
+                f(Interrupt::I2C4_ER as usize);
+            }
+            Peripheral::LPTIM1 => f(Interrupt::LPTIM1 as usize),
+            Peripheral::LPTIM2 => f(Interrupt::LPTIM2 as usize),
+            Peripheral::LPUart => f(Interrupt::LPUART1 as usize),
+            Peripheral::SPI1 => f(Interrupt::SPI1 as usize),
+            Peripheral::SPI2 => f(Interrupt::SPI2 as usize),
+            Peripheral::SPI3 => f(Interrupt::SPI3 as usize),
+            Peripheral::Tim1 => {
+                f(Interrupt::TIM1_BRK as usize);
+                f(Interrupt::TIM1_CC as usize);
+                f(Interrupt::TIM1_TRG_COM as usize);
+                f(Interrupt::TIM1_UP as usize);
+            }
+            Peripheral::Tim2 => f(Interrupt::TIM2 as usize),
+            Peripheral::Tim3 => f(Interrupt::TIM3 as usize),
+            Peripheral::Tim4 => f(Interrupt::TIM4 as usize),
+            Peripheral::Tim5 => f(Interrupt::TIM5 as usize),
+            Peripheral::Tim6 => f(Interrupt::TIM6 as usize),
+            Peripheral::Tim7 => f(Interrupt::TIM7 as usize),
+            Peripheral::Tim8 => {
+                f(Interrupt::TIM8_BRK as usize);
+                f(Interrupt::TIM8_CC as usize);
+                f(Interrupt::TIM8_TRG_COM as usize);
+                f(Interrupt::TIM8_UP as usize);
+            }
+            Peripheral::Tim15 => f(Interrupt::TIM15 as usize),
+            Peripheral::Tim16 => f(Interrupt::TIM16 as usize),
+            Peripheral::Tim17 => f(Interrupt::TIM17 as usize),
+            Peripheral::TSC => f(Interrupt::TSC as usize),
+            Peripheral::Uart4 => f(Interrupt::UART4 as usize),
+            Peripheral::Uart5 => f(Interrupt::UART5 as usize),
+            Peripheral::Ucpd1 => f(Interrupt::UCPD1 as usize),
+            Peripheral::Usart1 => f(Interrupt::USART1 as usize),
+            Peripheral::Usart2 => f(Interrupt::USART2 as usize),
+            Peripheral::Usart3 => f(Interrupt::USART3 as usize),
+            Peripheral::USBFS => f(Interrupt::USB_FS as usize),
+            Peripheral::WWDG => f(Interrupt::WWDG as usize),
+            Peripheral::DMA1(channel) => match channel {
+                1 => f(Interrupt::DMA1_CH1 as usize),
+                2 => f(Interrupt::DMA1_CH2 as usize),
+                3 => f(Interrupt::DMA1_CH3 as usize),
+                4 => f(Interrupt::DMA1_CH4 as usize),
+                5 => f(Interrupt::DMA1_CH5 as usize),
+                6 => f(Interrupt::DMA1_CH6 as usize),
+                7 => f(Interrupt::DMA1_CH7 as usize),
+                8 => f(Interrupt::DMA1_CHANNEL8 as usize),
+                _ => {}
+            },
+            Peripheral::DMA2(channel) => match channel {
+                1 => f(Interrupt::DMA2_CH1 as usize),
+                2 => f(Interrupt::DMA2_CH2 as usize),
+                3 => f(Interrupt::DMA2_CH3 as usize),
+                4 => f(Interrupt::DMA2_CH4 as usize),
+                5 => f(Interrupt::DMA2_CH5 as usize),
+                6 => f(Interrupt::DMA2_CH6 as usize),
+                7 => f(Interrupt::DMA2_CH7 as usize),
+                8 => f(Interrupt::DMA2_CH8 as usize),
+                _ => {}
+            },
+            _ => {}
+        };
+    }
+}
+
+fn enable_int(id: usize) {
+    let peripherals = unsafe { cortex_m::Peripherals::steal() };
+    unsafe {
+        peripherals.NVIC.icer[id / 32].write(1 << (id % 32));
+    }
+    unsafe {
+        peripherals.NVIC.itns[id / 32].modify(|w| w | 1 << (id & 0x1F));
+    }
+}
+
+impl crate::IntoNonSecure<Peripheral> for Peripheral {
+    fn into_non_secure(self) -> Peripheral {
+        self
+    }
+}
+
+/// Maps the secure aliases of PAC peripherals (`SEC_*`) to their descriptors. Secure code uses the `SEC_` alias, so
+/// passing it to `boot` consumes the handle secure code would otherwise keep using.
+macro_rules! impl_into_non_secure {
+    ($($(#[$attr:meta])* $pac:ident => $variant:expr),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            impl crate::IntoNonSecure<Peripheral> for pac::$pac {
+                fn into_non_secure(self) -> Peripheral {
+                    #[allow(unused_imports)]
+                    use Peripheral::*;
+                    $variant
+                }
+            }
+        )*
+    };
+}
+
+impl_into_non_secure! {
+    #[cfg(feature = "stm32l562")]
+    SEC_AES => AES,
+    SEC_CRC => CRC,
+    SEC_DFSDM1 => DFSDM,
+    #[cfg(feature = "stm32l562")]
+    SEC_HASH => Hash,
+    SEC_ICACHE => Icache,
+    SEC_OCTOSPI1 => OctoSPI1,
+    #[cfg(feature = "stm32l562")]
+    SEC_PKA => PKA,
+    SEC_RNG => RNG,
+    SEC_SAI1 => SAI1,
+    SEC_SAI2 => SAI2,
+    SEC_SDMMC1 => SDMMC1,
+    SEC_COMP => Comp,
+    SEC_CRS => Crs,
+    SEC_DAC => Dac,
+    SEC_FDCAN1 => FdCan,
+    SEC_I2C1 => I2C1,
+    SEC_I2C2 => I2C2,
+    SEC_I2C3 => I2C3,
+    SEC_I2C4 => I2C4,
+    SEC_IWDG => IWDG,
+    SEC_LPTIM1 => LPTIM1,
+    SEC_LPTIM2 => LPTIM2,
+    SEC_LPUART1 => LPUart,
+    SEC_OPAMP => OPAMP,
+    SEC_SPI1 => SPI1,
+    SEC_SPI2 => SPI2,
+    SEC_SPI3 => SPI3,
+    SEC_TIM1 => Tim1,
+    SEC_TIM2 => Tim2,
+    SEC_TIM3 => Tim3,
+    SEC_TIM4 => Tim4,
+    SEC_TIM5 => Tim5,
+    SEC_TIM6 => Tim6,
+    SEC_TIM7 => Tim7,
+    SEC_TIM8 => Tim8,
+    SEC_TIM15 => Tim15,
+    SEC_TIM16 => Tim16,
+    SEC_TIM17 => Tim17,
+    SEC_TSC => TSC,
+    SEC_UART4 => Uart4,
+    SEC_UART5 => Uart5,
+    SEC_UCPD1 => Ucpd1,
+    SEC_USART1 => Usart1,
+    SEC_USART2 => Usart2,
+    SEC_USART3 => Usart3,
+    SEC_USB => USBFS,
+    SEC_VREFBUF => VrefBuf,
+    SEC_WWDG => WWDG,
+}
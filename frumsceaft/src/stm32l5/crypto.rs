@@ -0,0 +1,377 @@
+use super::pac;
+use crate::crypto::Error;
+use core::ptr::{read_volatile, write_volatile};
+
+// AES register offsets, pulled from RM0438 chapter 33
+const AES_CR: u32 = 0x00;
+const AES_SR: u32 = 0x04;
+const AES_DINR: u32 = 0x08;
+const AES_DOUTR: u32 = 0x0C;
+const AES_KEYR0: u32 = 0x10;
+const AES_KEYR4: u32 = 0x30;
+
+const AES_CR_EN: u32 = 1 << 0;
+const AES_CR_CCFC: u32 = 1 << 7;
+const AES_CR_KEYSIZE_256: u32 = 1 << 18;
+const AES_SR_CCF: u32 = 1 << 0;
+
+// PKA register offsets, pulled from RM0438 chapter 34
+const PKA_CR: u32 = 0x00;
+const PKA_SR: u32 = 0x04;
+const PKA_CLRFR: u32 = 0x08;
+const PKA_RAM: u32 = 0x400;
+const PKA_RAM_END: u32 = 0x11F8;
+
+const PKA_CR_EN: u32 = 1 << 0;
+const PKA_CR_START: u32 = 1 << 1;
+const PKA_SR_PROCENDF: u32 = 1 << 17;
+const PKA_MODE_ECDSA_SIGN: u32 = 0x24;
+const PKA_MODE_ECDSA_VERIFY: u32 = 0x26;
+
+// ECDSA signature PKA RAM offsets
+const SIGN_ORDER_NB_BITS: u32 = 0x400;
+const SIGN_MOD_NB_BITS: u32 = 0x404;
+const SIGN_A_COEFF_SIGN: u32 = 0x408;
+const SIGN_A_COEFF: u32 = 0x40C;
+const SIGN_MOD_GF: u32 = 0x460;
+const SIGN_K: u32 = 0x508;
+const SIGN_POINT_X: u32 = 0x55C;
+const SIGN_POINT_Y: u32 = 0x5B0;
+const SIGN_HASH_E: u32 = 0xDE8;
+const SIGN_PRIVATE_KEY_D: u32 = 0xE3C;
+const SIGN_ORDER_N: u32 = 0xE94;
+const SIGN_ERROR: u32 = 0xEE8;
+const SIGN_R: u32 = 0x700;
+const SIGN_S: u32 = 0x754;
+
+// ECDSA verification PKA RAM offsets
+const VERIFY_ORDER_NB_BITS: u32 = 0x404;
+const VERIFY_MOD_NB_BITS: u32 = 0x4B4;
+const VERIFY_A_COEFF_SIGN: u32 = 0x45C;
+const VERIFY_A_COEFF: u32 = 0x460;
+const VERIFY_MOD_GF: u32 = 0x4B8;
+const VERIFY_POINT_X: u32 = 0x5E8;
+const VERIFY_POINT_Y: u32 = 0x63C;
+const VERIFY_PUBLIC_KEY_X: u32 = 0xF40;
+const VERIFY_PUBLIC_KEY_Y: u32 = 0xF94;
+const VERIFY_R: u32 = 0x1098;
+const VERIFY_S: u32 = 0xA44;
+const VERIFY_HASH_E: u32 = 0xFE8;
+const VERIFY_ORDER_N: u32 = 0xD5C;
+const VERIFY_RESULT: u32 = 0x5B0;
+
+// secp256r1, big endian. The `a` coefficient is -3, the PKA takes its sign and magnitude separately.
+const P256_P: [u8; 32] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+const P256_N: [u8; 32] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xBC, 0xE6, 0xFA, 0xAD, 0xA7, 0x17, 0x9E, 0x84, 0xF3, 0xB9, 0xCA, 0xC2, 0xFC, 0x63, 0x25, 0x51,
+];
+const P256_GX: [u8; 32] = [
+    0x6B, 0x17, 0xD1, 0xF2, 0xE1, 0x2C, 0x42, 0x47, 0xF8, 0xBC, 0xE6, 0xE5, 0x63, 0xA4, 0x40, 0xF2,
+    0x77, 0x03, 0x7D, 0x81, 0x2D, 0xEB, 0x33, 0xA0, 0xF4, 0xA1, 0x39, 0x45, 0xD8, 0x98, 0xC2, 0x96,
+];
+const P256_GY: [u8; 32] = [
+    0x4F, 0xE3, 0x42, 0xE2, 0xFE, 0x1A, 0x7F, 0x9B, 0x8E, 0xE7, 0xEB, 0x4A, 0x7C, 0x0F, 0x9E, 0x16,
+    0x2B, 0xCE, 0x33, 0x57, 0x6B, 0x31, 0x5E, 0xCE, 0xCB, 0xB6, 0x40, 0x68, 0x37, 0xBF, 0x51, 0xF5,
+];
+const P256_A_MAGNITUDE: u32 = 3;
+
+fn aes_base() -> u32 {
+    pac::SEC_AES::PTR as u32
+}
+
+fn pka_base() -> u32 {
+    pac::SEC_PKA::PTR as u32
+}
+
+fn reg_read(base: u32, offset: u32) -> u32 {
+    unsafe { read_volatile((base + offset) as *const u32) }
+}
+
+fn reg_write(base: u32, offset: u32, value: u32) {
+    unsafe { write_volatile((base + offset) as *mut u32, value) }
+}
+
+/// Writes a 256 bit big endian operand to the PKA RAM, the PKA takes operands least significant word first,
+/// followed by a zero word
+fn pka_write_operand(offset: u32, operand: &[u8; 32]) {
+    for (i, word) in operand.rchunks_exact(4).enumerate() {
+        let word = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        reg_write(pka_base(), offset + 4 * i as u32, word);
+    }
+    reg_write(pka_base(), offset + 32, 0);
+}
+
+/// Writes a single word operand to the PKA RAM, followed by a zero word
+fn pka_write_word(offset: u32, value: u32) {
+    reg_write(pka_base(), offset, value);
+    reg_write(pka_base(), offset + 4, 0);
+}
+
+/// Reads a 256 bit operand from the PKA RAM into big endian bytes
+fn pka_read_operand(offset: u32, out: &mut [u8]) {
+    for (i, word) in out.rchunks_exact_mut(4).enumerate() {
+        word.copy_from_slice(&reg_read(pka_base(), offset + 4 * i as u32).to_be_bytes());
+    }
+}
+
+/// Runs a PKA operation on the operands already in its RAM
+fn pka_run(mode: u32) {
+    reg_write(pka_base(), PKA_CR, PKA_CR_EN | mode << 8);
+    reg_write(pka_base(), PKA_CR, PKA_CR_EN | PKA_CR_START | mode << 8);
+    while reg_read(pka_base(), PKA_SR) & PKA_SR_PROCENDF == 0 {}
+    reg_write(pka_base(), PKA_CLRFR, PKA_SR_PROCENDF);
+}
+
+/// Overwrites the PKA RAM, so no private key or nonce is left behind
+fn pka_clear() {
+    for offset in (PKA_RAM..PKA_RAM_END).step_by(4) {
+        reg_write(pka_base(), offset, 0);
+    }
+    reg_write(pka_base(), PKA_CR, 0);
+}
+
+/// Writes the curve parameters shared by signing and verification
+fn pka_write_curve(order_nb_bits: u32, mod_nb_bits: u32, a_sign: u32, a: u32, p: u32) {
+    pka_write_word(order_nb_bits, 256);
+    pka_write_word(mod_nb_bits, 256);
+    pka_write_word(a_sign, 1);
+    let mut magnitude = [0u8; 32];
+    magnitude[28..].copy_from_slice(&P256_A_MAGNITUDE.to_be_bytes());
+    pka_write_operand(a, &magnitude);
+    pka_write_operand(p, &P256_P);
+}
+
+/// Encrypts a single block in place using AES-ECB. `key` is 16 or 32 bytes, the key registers are cleared
+/// once the block is done.
+fn aes_encrypt_block(key: &[u8], block: &mut [u8; 16]) {
+    let aes = aes_base();
+    // the key can only be written while the AES is disabled, ECB encryption is mode 0
+    let keysize = if key.len() == 32 {
+        AES_CR_KEYSIZE_256
+    } else {
+        0
+    };
+    reg_write(aes, AES_CR, keysize);
+    // KEYR0 holds the least significant word, KEYR4-7 are only used by 256 bit keys
+    for (i, word) in key.rchunks_exact(4).enumerate() {
+        let word = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        let offset = if i < 4 { AES_KEYR0 } else { AES_KEYR4 - 16 };
+        reg_write(aes, offset + 4 * i as u32, word);
+    }
+    reg_write(aes, AES_CR, keysize | AES_CR_EN);
+    for word in block.chunks_exact(4) {
+        reg_write(
+            aes,
+            AES_DINR,
+            u32::from_be_bytes([word[0], word[1], word[2], word[3]]),
+        );
+    }
+    while reg_read(aes, AES_SR) & AES_SR_CCF == 0 {}
+    for word in block.chunks_exact_mut(4) {
+        word.copy_from_slice(&reg_read(aes, AES_DOUTR).to_be_bytes());
+    }
+    reg_write(aes, AES_CR, keysize | AES_CR_EN | AES_CR_CCFC);
+    reg_write(aes, AES_CR, 0);
+    for i in 0..4 {
+        reg_write(aes, AES_KEYR0 + 4 * i, 0);
+        reg_write(aes, AES_KEYR4 + 4 * i, 0);
+    }
+}
+
+/// Encrypts or decrypts `data` in place using AES-CTR, `counter` is the initial counter block
+fn aes_ctr(key: &[u8], counter: &mut [u8; 16], data: &mut [u8]) {
+    for chunk in data.chunks_mut(16) {
+        let mut keystream = *counter;
+        aes_encrypt_block(key, &mut keystream);
+        for (d, k) in chunk.iter_mut().zip(keystream.iter()) {
+            *d ^= k;
+        }
+        // GCM increments the last 32 bits of the counter block
+        let next = u32::from_be_bytes([counter[12], counter[13], counter[14], counter[15]])
+            .wrapping_add(1);
+        counter[12..].copy_from_slice(&next.to_be_bytes());
+    }
+}
+
+/// AesPkaBackend runs the crypto service's AES-GCM on the STM32L562's AES peripheral, and ECDSA signing and
+/// verification on its PKA. GHASH, SHA-256, HMAC, ECDH and public key derivation run in software. Random numbers
+/// come from `entropy`.
+///
+/// The AES and PKA should be left secure, i.e. not passed to `boot`.
+pub struct AesPkaBackend {
+    pub entropy: fn(&mut [u8]) -> Result<(), Error>,
+}
+
+impl AesPkaBackend {
+    /// Clocks the AES and PKA, this is safe to repeat
+    fn enable_clocks() {
+        let rcc = unsafe { &*pac::SEC_RCC::PTR };
+        rcc.ahb2enr
+            .modify(|_, w| w.aesen().set_bit().pkaen().set_bit());
+    }
+
+    /// Returns the GCM tag over `aad` and `ciphertext`, `j0` is the first counter block
+    fn gcm_tag(key: &[u8], j0: &[u8; 16], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+        use ghash::universal_hash::{NewUniversalHash, UniversalHash};
+        // the hash key is the encrypted zero block
+        let mut h = [0u8; 16];
+        aes_encrypt_block(key, &mut h);
+        let mut ghash = ghash::GHash::new(&h.into());
+        ghash.update_padded(aad);
+        ghash.update_padded(ciphertext);
+        let mut lengths = [0u8; 16];
+        lengths[..8].copy_from_slice(&(aad.len() as u64 * 8).to_be_bytes());
+        lengths[8..].copy_from_slice(&(ciphertext.len() as u64 * 8).to_be_bytes());
+        ghash.update(&lengths.into());
+        let mut tag: [u8; 16] = ghash.finalize().into_bytes().into();
+        let mut mask = *j0;
+        aes_encrypt_block(key, &mut mask);
+        for (t, m) in tag.iter_mut().zip(mask.iter()) {
+            *t ^= m;
+        }
+        tag
+    }
+
+    /// Returns the first counter block for a 96 bit nonce
+    fn gcm_j0(key: &[u8], nonce: &[u8; 12]) -> Result<[u8; 16], Error> {
+        if key.len() != 16 && key.len() != 32 {
+            return Err(Error::InvalidArgument);
+        }
+        let mut j0 = [0u8; 16];
+        j0[..12].copy_from_slice(nonce);
+        j0[15] = 1;
+        Ok(j0)
+    }
+
+    /// Runs a single ECDSA signature with the nonce `k`, returning `None` if the PKA rejected it
+    fn sign_with(private_key: &[u8; 32], hash: &[u8; 32], k: &[u8; 32]) -> Option<[u8; 64]> {
+        pka_write_curve(
+            SIGN_ORDER_NB_BITS,
+            SIGN_MOD_NB_BITS,
+            SIGN_A_COEFF_SIGN,
+            SIGN_A_COEFF,
+            SIGN_MOD_GF,
+        );
+        pka_write_operand(SIGN_K, k);
+        pka_write_operand(SIGN_POINT_X, &P256_GX);
+        pka_write_operand(SIGN_POINT_Y, &P256_GY);
+        pka_write_operand(SIGN_HASH_E, hash);
+        pka_write_operand(SIGN_PRIVATE_KEY_D, private_key);
+        pka_write_operand(SIGN_ORDER_N, &P256_N);
+        pka_run(PKA_MODE_ECDSA_SIGN);
+        let mut signature = [0u8; 64];
+        let valid = reg_read(pka_base(), SIGN_ERROR) == 0;
+        if valid {
+            pka_read_operand(SIGN_R, &mut signature[..32]);
+            pka_read_operand(SIGN_S, &mut signature[32..]);
+        }
+        pka_clear();
+        valid.then(|| signature)
+    }
+}
+
+impl crate::crypto::Backend for AesPkaBackend {
+    fn random(&self, buf: &mut [u8]) -> Result<(), Error> {
+        (self.entropy)(buf)
+    }
+
+    fn aes_gcm_encrypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<[u8; 16], Error> {
+        let j0 = Self::gcm_j0(key, nonce)?;
+        Self::enable_clocks();
+        // the message is encrypted with the counter blocks following j0
+        let mut counter = j0;
+        counter[15] = 2;
+        aes_ctr(key, &mut counter, buf);
+        Ok(Self::gcm_tag(key, &j0, aad, buf))
+    }
+
+    fn aes_gcm_decrypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8; 16],
+    ) -> Result<(), Error> {
+        let j0 = Self::gcm_j0(key, nonce)?;
+        Self::enable_clocks();
+        if !crate::crypto::ct_eq(&Self::gcm_tag(key, &j0, aad, buf), tag) {
+            return Err(Error::InvalidSignature);
+        }
+        let mut counter = j0;
+        counter[15] = 2;
+        aes_ctr(key, &mut counter, buf);
+        Ok(())
+    }
+
+    fn ecdsa_p256_sign(&self, private_key: &[u8; 32], hash: &[u8; 32]) -> Result<[u8; 64], Error> {
+        Self::enable_clocks();
+        let mut k = [0u8; 32];
+        // a random 256 bit value is almost always a valid nonce
+        let mut result = Err(Error::InsufficientEntropy);
+        for _ in 0..8 {
+            if let Err(err) = (self.entropy)(&mut k) {
+                result = Err(err);
+                break;
+            }
+            // the nonce has to be in [1, n - 1], big endian byte arrays compare as integers
+            if k == [0; 32] || k >= P256_N {
+                continue;
+            }
+            if let Some(signature) = Self::sign_with(private_key, hash, &k) {
+                result = Ok(signature);
+                break;
+            }
+        }
+        for byte in k.iter_mut() {
+            unsafe { write_volatile(byte, 0) };
+        }
+        result
+    }
+
+    fn ecdsa_p256_verify(&self, public_key: &[u8], hash: &[u8; 32], signature: &[u8]) -> bool {
+        // the PKA doesn't check the point is on the curve
+        if !crate::crypto::software::p256_valid_public_key(public_key) || signature.len() != 64 {
+            return false;
+        }
+        let (x, y) = (&public_key[1..33], &public_key[33..65]);
+        let (r, s) = (&signature[..32], &signature[32..]);
+        let operand = |bytes: &[u8]| -> [u8; 32] { bytes.try_into().unwrap() };
+        for scalar in [r, s] {
+            if operand(scalar) == [0; 32] || operand(scalar) >= P256_N {
+                return false;
+            }
+        }
+        Self::enable_clocks();
+        pka_write_curve(
+            VERIFY_ORDER_NB_BITS,
+            VERIFY_MOD_NB_BITS,
+            VERIFY_A_COEFF_SIGN,
+            VERIFY_A_COEFF,
+            VERIFY_MOD_GF,
+        );
+        pka_write_operand(VERIFY_POINT_X, &P256_GX);
+        pka_write_operand(VERIFY_POINT_Y, &P256_GY);
+        pka_write_operand(VERIFY_PUBLIC_KEY_X, &operand(x));
+        pka_write_operand(VERIFY_PUBLIC_KEY_Y, &operand(y));
+        pka_write_operand(VERIFY_R, &operand(r));
+        pka_write_operand(VERIFY_S, &operand(s));
+        pka_write_operand(VERIFY_HASH_E, hash);
+        pka_write_operand(VERIFY_ORDER_N, &P256_N);
+        pka_run(PKA_MODE_ECDSA_VERIFY);
+        // a result of zero means the signature is valid
+        let valid = reg_read(pka_base(), VERIFY_RESULT) == 0;
+        pka_clear();
+        valid
+    }
+}
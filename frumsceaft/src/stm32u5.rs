@@ -0,0 +1,739 @@
+use crate::RegionParams;
+use core::cell::Cell;
+use core::ops::Range;
+use cortex_m::peripheral::sau::{SauRegion, SauRegionAttribute};
+use pac::Interrupt;
+
+#[cfg(all(feature = "stm32u575", feature = "stm32u585"))]
+compile_error!("only one STM32U5 variant feature can be enabled at a time");
+
+#[cfg(feature = "stm32u575")]
+use ::stm32u5::stm32u575 as pac;
+#[cfg(feature = "stm32u585")]
+use ::stm32u5::stm32u585 as pac;
+
+/// Clears the SECCFGR bit of a single GPIO pin, so it can be used from the non-secure world. The other pins keep
+/// their security.
+macro_rules! pass_gpio_pin {
+    ($gpio:expr, $pin:expr) => {
+        match $pin {
+            pin @ 0..=15 => $gpio
+                .seccfgr
+                .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << pin)) }),
+            pin => defmt::panic!("invalid GPIO pin {}", pin),
+        }
+    };
+}
+
+/// Size of a single MPCBB block, the smallest unit of SRAM that can be made non-secure.
+pub const MPCBB_BLOCK_SIZE: u32 = 0x200;
+/// Size of the SRAM covered by one MPCBB SECCFGR register (32 blocks).
+const MPCBB_SUPERBLOCK_SIZE: u32 = MPCBB_BLOCK_SIZE * 32;
+/// Size of a block in a GTZC MPCWM subregion
+const MPCWM_GRANULARITY: u32 = 0x20000;
+/// Size of each external memory bank guarded by a GTZC MPCWM
+const MPCWM_BANK_SIZE: u32 = 0x10000000;
+
+/// Sets or clears the security bits for every block of `$region` that falls inside of the SRAM bank
+/// starting at `$base`, which is guarded by `$registers` SECCFGR registers of `$mpcbb`.
+macro_rules! set_mpcbb_blocks {
+    ($mpcbb:expr, $base:expr, $registers:expr, $region:expr, $secure:expr) => {
+        $mpcbb
+            .cr
+            .modify(|_, w| w.srwiladis().set_bit().invsecstate().clear_bit());
+        for i in 0..$registers {
+            let mask = block_mask($base + i as u32 * MPCBB_SUPERBLOCK_SIZE, &$region);
+            if mask != 0 {
+                $mpcbb.seccfgr[i].modify(|r, w| unsafe {
+                    if $secure {
+                        w.bits(r.bits() | mask)
+                    } else {
+                        w.bits(r.bits() & !mask)
+                    }
+                });
+            }
+        }
+    };
+}
+
+/// Adds the security of every block of the SRAM bank starting at `$base`, which is guarded by `$registers`
+/// SECCFGR registers of `$mpcbb`, to `$map`
+macro_rules! read_mpcbb_blocks {
+    ($map:expr, $mpcbb:expr, $base:expr, $registers:expr) => {
+        let mut secure = [0u32; $registers];
+        for (i, bits) in secure.iter_mut().enumerate() {
+            *bits = $mpcbb.seccfgr[i].read().bits();
+        }
+        let locked = $mpcbb.cfglockr1.read().bits();
+        $map.push_blocks($base, MPCBB_BLOCK_SIZE, &secure, locked);
+    };
+}
+
+/// Returns a mask of the blocks in the 32 block superblock starting at `superblock` that start inside `region`
+fn block_mask(superblock: u32, region: &Range<u32>) -> u32 {
+    let mut mask = 0;
+    for bit in 0..32 {
+        let block = superblock + bit * MPCBB_BLOCK_SIZE;
+        if block >= region.start && block < region.end {
+            mask |= 1 << bit;
+        }
+    }
+    mask
+}
+
+/// GTZC is the STM32U5's IDAU, it is split across GTZC1 (most of the chip) and GTZC2 (the SmartRun domain).
+///
+/// Every peripheral that isn't passed to `boot` is made secure before booting the non-secure world.
+pub struct GTZC {
+    peripherals_secured: Cell<bool>,
+}
+
+impl GTZC {
+    pub const fn new() -> Self {
+        GTZC {
+            peripherals_secured: Cell::new(false),
+        }
+    }
+
+    /// Marks every peripheral controlled by the TZSCs as secure, this only happens once,
+    /// before the first peripheral is passed to the non-secure world.
+    fn secure_peripherals(&self) {
+        if self.peripherals_secured.replace(true) {
+            return;
+        }
+        let tzsc1 = unsafe { &*pac::SEC_GTZC1_TZSC::PTR };
+        let tzsc2 = unsafe { &*pac::SEC_GTZC2_TZSC::PTR };
+        tzsc1.seccfgr1.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        tzsc1.seccfgr2.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        tzsc1.seccfgr3.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        tzsc2.seccfgr1.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+    }
+}
+
+impl Default for GTZC {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl crate::IDAU for GTZC {
+    type Peripheral = Peripheral;
+    fn set_flash_region_params(&self, region: Range<u32>, params: RegionParams) {
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+        if !params.secure {
+            defmt::println!("ns region start: {:x} end: {:x}", region.start, region.end);
+            sau.set_region(
+                0,
+                SauRegion {
+                    base_address: region.start,
+                    limit_address: region.end,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    fn set_memory_region_params(&self, region: Range<u32>, params: RegionParams) {
+        // the MPCBBs work on the non-secure alias, so strip the secure alias bit
+        let blocks = (region.start & !0x10000000)..(region.end & !0x10000000);
+        let mpcbb1 = unsafe { &*pac::SEC_GTZC1_MPCBB1::PTR };
+        let mpcbb2 = unsafe { &*pac::SEC_GTZC1_MPCBB2::PTR };
+        let mpcbb3 = unsafe { &*pac::SEC_GTZC1_MPCBB3::PTR };
+        let mpcbb4 = unsafe { &*pac::SEC_GTZC2_MPCBB4::PTR };
+        // SRAM1: 192K
+        set_mpcbb_blocks!(mpcbb1, 0x20000000, 12, blocks, params.secure);
+        // SRAM2: 64K
+        set_mpcbb_blocks!(mpcbb2, 0x20030000, 4, blocks, params.secure);
+        // SRAM3: 512K
+        set_mpcbb_blocks!(mpcbb3, 0x20040000, 32, blocks, params.secure);
+        // SRAM4: 16K
+        set_mpcbb_blocks!(mpcbb4, 0x28000000, 1, blocks, params.secure);
+        if !params.secure {
+            let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+            let mut sau = p.SAU;
+            sau.set_region(
+                1,
+                SauRegion {
+                    base_address: region.start,
+                    limit_address: region.end,
+                    attribute: SauRegionAttribute::NonSecure,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    fn set_nsc_region(&self, region: Range<u32>) {
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+        sau.set_region(
+            2,
+            SauRegion {
+                base_address: region.start,
+                limit_address: region.end,
+                attribute: SauRegionAttribute::NonSecureCallable,
+            },
+        )
+        .unwrap();
+    }
+
+    fn pass_peripheral_non_secure(&self, perph: &Self::Peripheral) {
+        self.secure_peripherals();
+        match perph {
+            Peripheral::GPIOA(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOA::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOB(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOB::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOC(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOC::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOD(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOD::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOE(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOE::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOF(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOF::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOG(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOG::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOH(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOH::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPIOI(p) => {
+                let gpio = unsafe { &*pac::SEC_GPIOI::PTR };
+                pass_gpio_pin!(gpio, *p);
+            }
+            Peripheral::GPDMA1(channel) => {
+                if *channel > 15 {
+                    defmt::panic!("invalid GPDMA1 channel {}", channel);
+                }
+                let gpdma = unsafe { &*pac::SEC_GPDMA1::PTR };
+                gpdma
+                    .seccfgr
+                    .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << *channel)) });
+            }
+            perph => {
+                // every other peripheral is controlled by a single bit in one of the TZSCs
+                let (tzsc, register, bit) = perph.security_bit();
+                let tzsc1 = unsafe { &*pac::SEC_GTZC1_TZSC::PTR };
+                let tzsc2 = unsafe { &*pac::SEC_GTZC2_TZSC::PTR };
+                let mask = !(1 << bit);
+                match (tzsc, register) {
+                    (1, 1) => tzsc1
+                        .seccfgr1
+                        .modify(|r, w| unsafe { w.bits(r.bits() & mask) }),
+                    (1, 2) => tzsc1
+                        .seccfgr2
+                        .modify(|r, w| unsafe { w.bits(r.bits() & mask) }),
+                    (1, 3) => tzsc1
+                        .seccfgr3
+                        .modify(|r, w| unsafe { w.bits(r.bits() & mask) }),
+                    _ => tzsc2
+                        .seccfgr1
+                        .modify(|r, w| unsafe { w.bits(r.bits() & mask) }),
+                }
+            }
+        }
+        perph.for_each_interrupt(enable_int);
+    }
+
+    fn peripheral_interrupts(&self, perph: &Self::Peripheral, f: &mut dyn FnMut(usize)) {
+        perph.for_each_interrupt(f);
+    }
+
+    fn prepare_boot(&self) {
+        self.secure_peripherals();
+        let p = unsafe { cortex_m::peripheral::Peripherals::steal() };
+        let mut sau = p.SAU;
+        let syscfg = unsafe { &*pac::SEC_SYSCFG::PTR };
+        syscfg
+            .seccfgr
+            .write(|w| w.syscfgsec().clear_bit().classbsec().clear_bit());
+        // enable illegal access detection for every peripheral and memory
+        let tzic1 = unsafe { &*pac::SEC_GTZC1_TZIC::PTR };
+        tzic1.ier1.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        tzic1.ier2.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        tzic1.ier3.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        tzic1.ier4.write(|w| unsafe { w.bits(0xFFFFFFFF) });
+        // set all peripheral memory blocks as non-secure, the TZSCs decide what is accessible
+        sau.set_region(
+            3,
+            SauRegion {
+                base_address: 0x40000000,
+                limit_address: 0x4FFFFFFF,
+                attribute: SauRegionAttribute::NonSecure,
+            },
+        )
+        .unwrap();
+        sau.enable();
+    }
+
+    fn report(&self) -> crate::audit::SecurityMap {
+        let mut map = crate::audit::SecurityMap::read_core();
+        // a cleared bit in SECCFGR makes the peripheral non-secure
+        let tzsc1 = unsafe { &*pac::SEC_GTZC1_TZSC::PTR };
+        let tzsc2 = unsafe { &*pac::SEC_GTZC2_TZSC::PTR };
+        map.non_secure_peripherals[0] = !tzsc1.seccfgr1.read().bits();
+        map.non_secure_peripherals[1] = !tzsc1.seccfgr2.read().bits();
+        map.non_secure_peripherals[2] = !tzsc1.seccfgr3.read().bits();
+        map.non_secure_peripherals[3] = !tzsc2.seccfgr1.read().bits();
+
+        // a set bit in SECCFGR makes the block secure
+        let mpcbb1 = unsafe { &*pac::SEC_GTZC1_MPCBB1::PTR };
+        let mpcbb2 = unsafe { &*pac::SEC_GTZC1_MPCBB2::PTR };
+        let mpcbb3 = unsafe { &*pac::SEC_GTZC1_MPCBB3::PTR };
+        let mpcbb4 = unsafe { &*pac::SEC_GTZC2_MPCBB4::PTR };
+        read_mpcbb_blocks!(map, mpcbb1, 0x20000000, 12);
+        read_mpcbb_blocks!(map, mpcbb2, 0x20030000, 4);
+        read_mpcbb_blocks!(map, mpcbb3, 0x20040000, 32);
+        read_mpcbb_blocks!(map, mpcbb4, 0x28000000, 1);
+
+        // only enabled, non-secure subregions open a watermark bank to the non-secure world
+        let locked = tzsc1.cr.read().bits() & 1 != 0;
+        let window = |base: u32, cfgr: u32, ar: u32| {
+            if cfgr & 1 == 0 || cfgr & (1 << 8) != 0 {
+                return 0..0;
+            }
+            let start = base + (ar & 0x7FF) * MPCWM_GRANULARITY;
+            start..start + ((ar >> 16) & 0xFFF) * MPCWM_GRANULARITY
+        };
+        // FMC bank 1 (NOR / PSRAM / SRAM), guarded by MPCWM2
+        let fmc_nor = 0x60000000;
+        map.push_watermarks(
+            fmc_nor..fmc_nor + MPCWM_BANK_SIZE,
+            [
+                window(
+                    fmc_nor,
+                    tzsc1.mpcwm2acfgr.read().bits(),
+                    tzsc1.mpcwm2ar.read().bits(),
+                ),
+                window(
+                    fmc_nor,
+                    tzsc1.mpcwm2bcfgr.read().bits(),
+                    tzsc1.mpcwm2br.read().bits(),
+                ),
+            ],
+            locked,
+        );
+        // FMC bank 3 (NAND), guarded by MPCWM3
+        let fmc_nand = 0x80000000;
+        map.push_watermarks(
+            fmc_nand..fmc_nand + MPCWM_BANK_SIZE,
+            [window(
+                fmc_nand,
+                tzsc1.mpcwm3acfgr.read().bits(),
+                tzsc1.mpcwm3ar.read().bits(),
+            )],
+            locked,
+        );
+        // the OCTOSPI1 memory-mapped bank, guarded by MPCWM1
+        let octospi1 = 0x90000000;
+        map.push_watermarks(
+            octospi1..octospi1 + MPCWM_BANK_SIZE,
+            [
+                window(
+                    octospi1,
+                    tzsc1.mpcwm1acfgr.read().bits(),
+                    tzsc1.mpcwm1ar.read().bits(),
+                ),
+                window(
+                    octospi1,
+                    tzsc1.mpcwm1bcfgr.read().bits(),
+                    tzsc1.mpcwm1br.read().bits(),
+                ),
+            ],
+            locked,
+        );
+        map
+    }
+}
+
+pub enum Peripheral {
+    Tim2,
+    Tim3,
+    Tim4,
+    Tim5,
+    Tim6,
+    Tim7,
+    WWDG,
+    IWDG,
+    SPI2,
+    Usart2,
+    Usart3,
+    Uart4,
+    Uart5,
+    I2C1,
+    I2C2,
+    Crs,
+    I2C4,
+    LPTIM2,
+    FdCan,
+    Ucpd1,
+    Tim1,
+    SPI1,
+    Tim8,
+    Usart1,
+    Tim15,
+    Tim16,
+    Tim17,
+    SAI1,
+    SAI2,
+    MDF1,
+    Cordic,
+    FMAC,
+    CRC,
+    TSC,
+    DMA2D,
+    Icache,
+    Dcache1,
+    ADC12,
+    DCMI,
+    OTG,
+    #[cfg(feature = "stm32u585")]
+    AES,
+    Hash,
+    RNG,
+    #[cfg(feature = "stm32u585")]
+    PKA,
+    #[cfg(feature = "stm32u585")]
+    SAES,
+    OctoSPIM,
+    SDMMC1,
+    SDMMC2,
+    FSMCReg,
+    OctoSPI1,
+    OctoSPI2,
+    RAMCFG,
+    SPI3,
+    LPUart,
+    I2C3,
+    LPTIM1,
+    LPTIM3,
+    LPTIM4,
+    OPAMP,
+    Comp,
+    ADC4,
+    VrefBuf,
+    Dac,
+    ADF1,
+    GPIOA(usize),
+    GPIOB(usize),
+    GPIOC(usize),
+    GPIOD(usize),
+    GPIOE(usize),
+    GPIOF(usize),
+    GPIOG(usize),
+    GPIOH(usize),
+    GPIOI(usize),
+    /// A single GPDMA1 channel (0-15)
+    GPDMA1(usize),
+}
+
+impl Peripheral {
+    /// Returns the TZSC (1 or 2), the SECCFGR register, and the bit that controls the peripheral's security
+    fn security_bit(&self) -> (u8, u8, u8) {
+        use Peripheral::*;
+        match self {
+            Tim2 => (1, 1, 0),
+            Tim3 => (1, 1, 1),
+            Tim4 => (1, 1, 2),
+            Tim5 => (1, 1, 3),
+            Tim6 => (1, 1, 4),
+            Tim7 => (1, 1, 5),
+            WWDG => (1, 1, 6),
+            IWDG => (1, 1, 7),
+            SPI2 => (1, 1, 8),
+            Usart2 => (1, 1, 9),
+            Usart3 => (1, 1, 10),
+            Uart4 => (1, 1, 11),
+            Uart5 => (1, 1, 12),
+            I2C1 => (1, 1, 13),
+            I2C2 => (1, 1, 14),
+            Crs => (1, 1, 15),
+            I2C4 => (1, 1, 16),
+            LPTIM2 => (1, 1, 17),
+            FdCan => (1, 1, 18),
+            Ucpd1 => (1, 1, 19),
+            Tim1 => (1, 2, 0),
+            SPI1 => (1, 2, 1),
+            Tim8 => (1, 2, 2),
+            Usart1 => (1, 2, 3),
+            Tim15 => (1, 2, 4),
+            Tim16 => (1, 2, 5),
+            Tim17 => (1, 2, 6),
+            SAI1 => (1, 2, 7),
+            SAI2 => (1, 2, 8),
+            MDF1 => (1, 3, 0),
+            Cordic => (1, 3, 1),
+            FMAC => (1, 3, 2),
+            CRC => (1, 3, 3),
+            TSC => (1, 3, 4),
+            DMA2D => (1, 3, 5),
+            Icache => (1, 3, 6),
+            Dcache1 => (1, 3, 7),
+            ADC12 => (1, 3, 8),
+            DCMI => (1, 3, 9),
+            OTG => (1, 3, 10),
+            #[cfg(feature = "stm32u585")]
+            AES => (1, 3, 11),
+            Hash => (1, 3, 12),
+            RNG => (1, 3, 13),
+            #[cfg(feature = "stm32u585")]
+            PKA => (1, 3, 14),
+            #[cfg(feature = "stm32u585")]
+            SAES => (1, 3, 15),
+            OctoSPIM => (1, 3, 16),
+            SDMMC1 => (1, 3, 17),
+            SDMMC2 => (1, 3, 18),
+            FSMCReg => (1, 3, 19),
+            OctoSPI1 => (1, 3, 20),
+            OctoSPI2 => (1, 3, 21),
+            RAMCFG => (1, 3, 22),
+            SPI3 => (2, 1, 0),
+            LPUart => (2, 1, 1),
+            I2C3 => (2, 1, 2),
+            LPTIM1 => (2, 1, 3),
+            LPTIM3 => (2, 1, 4),
+            LPTIM4 => (2, 1, 5),
+            OPAMP => (2, 1, 6),
+            Comp => (2, 1, 7),
+            ADC4 => (2, 1, 8),
+            VrefBuf => (2, 1, 9),
+            Dac => (2, 1, 11),
+            ADF1 => (2, 1, 12),
+            GPIOA(_) | GPIOB(_) | GPIOC(_) | GPIOD(_) | GPIOE(_) | GPIOF(_) | GPIOG(_)
+            | GPIOH(_) | GPIOI(_) | GPDMA1(_) => {
+                unreachable!("GPIO and GPDMA security is configured per pin / channel")
+            }
+        }
+    }
+
+    /// Calls `f` with each of the peripheral's interrupt lines
+    fn for_each_interrupt(&self, mut f: impl FnMut(usize)) {
+        match self {
+            Peripheral::Tim2 => f(Interrupt::TIM2 as usize),
+            Peripheral::Tim3 => f(Interrupt::TIM3 as usize),
+            Peripheral::Tim4 => f(Interrupt::TIM4 as usize),
+            Peripheral::Tim5 => f(Interrupt::TIM5 as usize),
+            Peripheral::Tim6 => f(Interrupt::TIM6 as usize),
+            Peripheral::Tim7 => f(Interrupt::TIM7 as usize),
+            Peripheral::WWDG => f(Interrupt::WWDG as usize),
+            Peripheral::IWDG => f(Interrupt::IWDG as usize),
+            Peripheral::SPI1 => f(Interrupt::SPI1 as usize),
+            Peripheral::SPI2 => f(Interrupt::SPI2 as usize),
+            Peripheral::SPI3 => f(Interrupt::SPI3 as usize),
+            Peripheral::Usart1 => f(Interrupt::USART1 as usize),
+            Peripheral::Usart2 => f(Interrupt::USART2 as usize),
+            Peripheral::Usart3 => f(Interrupt::USART3 as usize),
+            Peripheral::Uart4 => f(Interrupt::UART4 as usize),
+            Peripheral::Uart5 => f(Interrupt::UART5 as usize),
+            Peripheral::LPUart => f(Interrupt::LPUART1 as usize),
+            Peripheral::I2C1 => {
+                f(Interrupt::I2C1_EV as usize);
+                f(Interrupt::I2C1_ER as usize);
+            }
+            Peripheral::I2C2 => {
+                f(Interrupt::I2C2_EV as usize);
+                f(Interrupt::I2C2_ER as usize);
+            }
+            Peripheral::I2C3 => {
+                f(Interrupt::I2C3_EV as usize);
+                f(Interrupt::I2C3_ER as usize);
+            }
+            Peripheral::I2C4 => {
+                f(Interrupt::I2C4_EV as usize);
+                f(Interrupt::I2C4_ER as usize);
+            }
+            Peripheral::Crs => f(Interrupt::CRS as usize),
+            Peripheral::LPTIM1 => f(Interrupt::LPTIM1 as usize),
+            Peripheral::LPTIM2 => f(Interrupt::LPTIM2 as usize),
+            Peripheral::LPTIM3 => f(Interrupt::LPTIM3 as usize),
+            Peripheral::FdCan => {
+                f(Interrupt::FDCAN1_IT0 as usize);
+                f(Interrupt::FDCAN1_IT1 as usize);
+            }
+            Peripheral::Ucpd1 => f(Interrupt::UCPD1 as usize),
+            Peripheral::Tim1 => {
+                f(Interrupt::TIM1_BRK as usize);
+                f(Interrupt::TIM1_CC as usize);
+                f(Interrupt::TIM1_TRG_COM as usize);
+                f(Interrupt::TIM1_UP as usize);
+            }
+            Peripheral::Tim8 => {
+                f(Interrupt::TIM8_BRK as usize);
+                f(Interrupt::TIM8_CC as usize);
+                f(Interrupt::TIM8_TRG_COM as usize);
+                f(Interrupt::TIM8_UP as usize);
+            }
+            Peripheral::Tim15 => f(Interrupt::TIM15 as usize),
+            Peripheral::Tim16 => f(Interrupt::TIM16 as usize),
+            Peripheral::Tim17 => f(Interrupt::TIM17 as usize),
+            Peripheral::SAI1 => f(Interrupt::SAI1 as usize),
+            Peripheral::SAI2 => f(Interrupt::SAI2 as usize),
+            Peripheral::Cordic => f(Interrupt::CORDIC as usize),
+            Peripheral::FMAC => f(Interrupt::FMAC as usize),
+            Peripheral::TSC => f(Interrupt::TSC as usize),
+            Peripheral::DMA2D => f(Interrupt::DMA2D as usize),
+            Peripheral::Icache => f(Interrupt::ICACHE as usize),
+            Peripheral::Dcache1 => f(Interrupt::DCACHE1 as usize),
+            Peripheral::ADC12 => f(Interrupt::ADC1 as usize),
+            Peripheral::ADC4 => f(Interrupt::ADC4 as usize),
+            Peripheral::OTG => f(Interrupt::OTG_FS as usize),
+            #[cfg(feature = "stm32u585")]
+            Peripheral::AES => f(Interrupt::AES as usize),
+            Peripheral::Hash => f(Interrupt::HASH as usize),
+            Peripheral::RNG => f(Interrupt::RNG as usize),
+            #[cfg(feature = "stm32u585")]
+            Peripheral::PKA => f(Interrupt::PKA as usize),
+            #[cfg(feature = "stm32u585")]
+            Peripheral::SAES => f(Interrupt::SAES as usize),
+            Peripheral::SDMMC1 => f(Interrupt::SDMMC1 as usize),
+            Peripheral::SDMMC2 => f(Interrupt::SDMMC2 as usize),
+            Peripheral::FSMCReg => f(Interrupt::FMC as usize),
+            Peripheral::OctoSPI1 => f(Interrupt::OCTOSPI1 as usize),
+            Peripheral::OctoSPI2 => f(Interrupt::OCTOSPI2 as usize),
+            Peripheral::Comp => f(Interrupt::COMP as usize),
+            Peripheral::Dac => f(Interrupt::DAC1 as usize),
+            Peripheral::ADF1 => f(Interrupt::ADF1 as usize),
+            Peripheral::MDF1 => {
+                f(Interrupt::MDF1_FLT0 as usize);
+                f(Interrupt::MDF1_FLT1 as usize);
+                f(Interrupt::MDF1_FLT2 as usize);
+                f(Interrupt::MDF1_FLT3 as usize);
+                f(Interrupt::MDF1_FLT4 as usize);
+                f(Interrupt::MDF1_FLT5 as usize);
+            }
+            Peripheral::LPTIM4 => f(Interrupt::LPTIM4 as usize),
+            Peripheral::RAMCFG => f(Interrupt::RAMCFG as usize),
+            Peripheral::DCMI => f(Interrupt::DCMI_PSSI as usize),
+            // GPDMA1_CH0-7 and GPDMA1_CH8-15 are each contiguous in the vector table
+            Peripheral::GPDMA1(channel @ 0..=7) => f(Interrupt::GPDMA1_CH0 as usize + channel),
+            Peripheral::GPDMA1(channel @ 8..=15) => f(Interrupt::GPDMA1_CH8 as usize + channel - 8),
+            Peripheral::GPDMA1(_) => {}
+            // these have no interrupt of their own, GPIO pins raise theirs through the EXTI
+            Peripheral::OctoSPIM
+            | Peripheral::CRC
+            | Peripheral::OPAMP
+            | Peripheral::VrefBuf
+            | Peripheral::GPIOA(_)
+            | Peripheral::GPIOB(_)
+            | Peripheral::GPIOC(_)
+            | Peripheral::GPIOD(_)
+            | Peripheral::GPIOE(_)
+            | Peripheral::GPIOF(_)
+            | Peripheral::GPIOG(_)
+            | Peripheral::GPIOH(_)
+            | Peripheral::GPIOI(_) => {}
+        };
+    }
+}
+
+fn enable_int(id: usize) {
+    let peripherals = unsafe { cortex_m::Peripherals::steal() };
+    unsafe {
+        peripherals.NVIC.icer[id / 32].write(1 << (id % 32));
+    }
+    unsafe {
+        peripherals.NVIC.itns[id / 32].modify(|w| w | 1 << (id & 0x1F));
+    }
+}
+
+impl crate::IntoNonSecure<Peripheral> for Peripheral {
+    fn into_non_secure(self) -> Peripheral {
+        self
+    }
+}
+
+/// Maps the secure aliases of PAC peripherals (`SEC_*`) to their descriptors. Secure code uses the `SEC_` alias, so
+/// passing it to `boot` consumes the handle secure code would otherwise keep using.
+macro_rules! impl_into_non_secure {
+    ($($(#[$attr:meta])* $pac:ident => $variant:expr),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            impl crate::IntoNonSecure<Peripheral> for pac::$pac {
+                fn into_non_secure(self) -> Peripheral {
+                    #[allow(unused_imports)]
+                    use Peripheral::*;
+                    $variant
+                }
+            }
+        )*
+    };
+}
+
+impl_into_non_secure! {
+    SEC_TIM1 => Tim1,
+    SEC_TIM2 => Tim2,
+    SEC_TIM3 => Tim3,
+    SEC_TIM4 => Tim4,
+    SEC_TIM5 => Tim5,
+    SEC_TIM6 => Tim6,
+    SEC_TIM7 => Tim7,
+    SEC_TIM8 => Tim8,
+    SEC_TIM15 => Tim15,
+    SEC_TIM16 => Tim16,
+    SEC_TIM17 => Tim17,
+    SEC_WWDG => WWDG,
+    SEC_IWDG => IWDG,
+    SEC_SPI1 => SPI1,
+    SEC_SPI2 => SPI2,
+    SEC_SPI3 => SPI3,
+    SEC_USART1 => Usart1,
+    SEC_USART2 => Usart2,
+    SEC_USART3 => Usart3,
+    SEC_UART4 => Uart4,
+    SEC_UART5 => Uart5,
+    SEC_LPUART1 => LPUart,
+    SEC_I2C1 => I2C1,
+    SEC_I2C2 => I2C2,
+    SEC_I2C3 => I2C3,
+    SEC_I2C4 => I2C4,
+    SEC_CRS => Crs,
+    SEC_LPTIM1 => LPTIM1,
+    SEC_LPTIM2 => LPTIM2,
+    SEC_LPTIM3 => LPTIM3,
+    SEC_LPTIM4 => LPTIM4,
+    SEC_FDCAN1 => FdCan,
+    SEC_UCPD1 => Ucpd1,
+    SEC_SAI1 => SAI1,
+    SEC_SAI2 => SAI2,
+    SEC_CORDIC => Cordic,
+    SEC_FMAC => FMAC,
+    SEC_CRC => CRC,
+    SEC_TSC => TSC,
+    SEC_DMA2D => DMA2D,
+    SEC_ICACHE => Icache,
+    SEC_DCMI => DCMI,
+    #[cfg(feature = "stm32u585")]
+    SEC_AES => AES,
+    SEC_HASH => Hash,
+    SEC_RNG => RNG,
+    #[cfg(feature = "stm32u585")]
+    SEC_PKA => PKA,
+    #[cfg(feature = "stm32u585")]
+    SEC_SAES => SAES,
+    SEC_OCTOSPIM => OctoSPIM,
+    SEC_SDMMC1 => SDMMC1,
+    SEC_SDMMC2 => SDMMC2,
+    SEC_OCTOSPI1 => OctoSPI1,
+    SEC_OCTOSPI2 => OctoSPI2,
+    SEC_RAMCFG => RAMCFG,
+    SEC_COMP => Comp,
+    SEC_OPAMP => OPAMP,
+    SEC_VREFBUF => VrefBuf,
+}
@@ -0,0 +1,1142 @@
+//! Protected storage for the non-secure world, modelled on the PSA Internal Trusted and Protected Storage APIs.
+//!
+//! Objects are identified by a 64-bit UID, and kept in a log spread over two or more pages of secure flash.
+//! Every change appends a new record, so an interrupted write never affects the previous version of an object:
+//! a record only counts once its commit unit, which is written last, holds the CRC of its data. When the head page
+//! fills up the next page is opened, and the live records of the oldest page are copied forward before it is
+//! erased, so one page is always kept erased. Copies keep their sequence number, and the oldest page is marked
+//! obsolete before it is erased, so a copy or erase interrupted by a power failure is redone on the next mount.
+//!
+//! Objects are encrypted with AES-CTR when a [`Cipher`] is configured, which should use a device-unique key that
+//! never leaves the secure world (e.g. a locked KMU key slot on the nRF5340). The nonce is made from the UID and
+//! the record's sequence number, which is never reused for data.
+//!
+//! The non-secure world reaches the service through the veneers generated by [`storage_veneers`](crate::storage_veneers).
+use crate::flash::{self, Flash};
+use crate::nsc::{self, status};
+use core::ops::Range;
+
+pub type Uid = u64;
+/// UIDs with the top bit set are reserved for the secure world, e.g. for the crypto service's persistent keys.
+/// The non-secure world can't access them through the veneers.
+pub const SECURE_UID_BASE: Uid = 1 << 63;
+
+/// The object can't be changed or removed once it has been written
+pub const FLAG_WRITE_ONCE: u32 = 1 << 0;
+/// The object is stored in plaintext, even if a [`Cipher`] is configured
+pub const FLAG_NO_CONFIDENTIALITY: u32 = 1 << 1;
+/// Accepted for compatibility with PSA. Objects aren't protected against being rolled back by someone with physical
+/// access to the flash, so this has no effect.
+pub const FLAG_NO_REPLAY_PROTECTION: u32 = 1 << 2;
+const USER_FLAGS: u32 = FLAG_WRITE_ONCE | FLAG_NO_CONFIDENTIALITY | FLAG_NO_REPLAY_PROTECTION;
+// flags only used in records
+const RECORD_REMOVED: u32 = 1 << 30;
+const RECORD_ENCRYPTED: u32 = 1 << 31;
+
+const PAGE_MAGIC: u32 = 0x4653_5450;
+const RECORD_MAGIC: u32 = 0x4653_5452;
+/// A page starts with a header unit, followed by a unit that is written when the page is made obsolete
+const PAGE_HEADER_SIZE: u32 = 32;
+const OBSOLETE_OFFSET: u32 = 16;
+const RECORD_HEADER_SIZE: u32 = 32;
+/// Size of the buffer data is moved through, this is a multiple of the AES block size and of `Flash::WRITE_SIZE`
+const CHUNK: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    Flash(flash::Error),
+    DoesNotExist,
+    AlreadyExists,
+    /// The object was written with [`FLAG_WRITE_ONCE`]
+    NotPermitted,
+    InvalidArgument,
+    /// Unknown flags were passed
+    NotSupported,
+    InsufficientStorage,
+    /// The object's data doesn't match the CRC it was committed with
+    DataCorrupt,
+    /// The storage region isn't aligned to the flash's pages, or has fewer than two pages
+    InvalidRegion,
+    /// The [`Cipher`] failed
+    Cipher,
+    /// The service hasn't been initialised, or is already handling a request
+    BadState,
+}
+
+impl From<flash::Error> for Error {
+    fn from(err: flash::Error) -> Self {
+        Error::Flash(err)
+    }
+}
+
+impl Error {
+    /// Returns the PSA status code for the error
+    pub fn status(self) -> i32 {
+        match self {
+            Error::Flash(_) | Error::Cipher => status::STORAGE_FAILURE,
+            Error::DoesNotExist => status::DOES_NOT_EXIST,
+            Error::AlreadyExists => status::ALREADY_EXISTS,
+            Error::NotPermitted => status::NOT_PERMITTED,
+            Error::InvalidArgument | Error::InvalidRegion => status::INVALID_ARGUMENT,
+            Error::NotSupported => status::NOT_SUPPORTED,
+            Error::InsufficientStorage => status::INSUFFICIENT_STORAGE,
+            Error::DataCorrupt => status::DATA_CORRUPT,
+            Error::BadState => status::BAD_STATE,
+        }
+    }
+}
+
+/// Encrypts and decrypts objects with a stream cipher (AES-CTR)
+pub trait Cipher {
+    /// Applies the keystream for the initial counter block `nonce` to `data`, starting `offset` bytes into the
+    /// keystream. `offset` is always a multiple of 16.
+    fn apply_keystream(&self, nonce: &[u8; 16], offset: u32, data: &mut [u8]) -> Result<(), Error>;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct Info {
+    /// The size the object can grow to through `set_extended`
+    pub capacity: u32,
+    pub size: u32,
+    pub flags: u32,
+}
+
+/// A committed or uncommitted record in the log
+#[derive(Clone, Copy)]
+struct Record {
+    address: u32,
+    uid: Uid,
+    flags: u32,
+    length: u32,
+    capacity: u32,
+    sequence: u32,
+    /// CRC of the stored data, if the record was committed
+    crc: Option<u32>,
+}
+
+impl Record {
+    fn removed(&self) -> bool {
+        self.flags & RECORD_REMOVED != 0
+    }
+
+    fn info(&self) -> Info {
+        Info {
+            capacity: self.capacity,
+            size: self.length,
+            flags: self.flags & USER_FLAGS,
+        }
+    }
+}
+
+enum Slot {
+    Erased,
+    /// The record header was torn, so nothing after it can be trusted
+    Corrupt,
+    Record(Record),
+}
+
+enum PageState {
+    Erased,
+    Used(u32),
+    /// The page was marked obsolete, or its header or an erase was interrupted
+    Dirty,
+}
+
+/// Storage keeps objects in a log in secure flash, see the [module documentation](self).
+pub struct Storage<'a, F> {
+    flash: F,
+    cipher: Option<&'a (dyn Cipher + Sync)>,
+    base: u32,
+    pages: u32,
+    oldest: u32,
+    head: u32,
+    /// Address the next record is written to
+    write_address: u32,
+    next_sequence: u32,
+}
+
+impl<'a, F: Flash> Storage<'a, F> {
+    /// Mounts the log in `region`, which must be aligned to the flash's pages and cover at least two of them.
+    /// An empty region is formatted, and any copy or erase interrupted by a power failure is finished.
+    pub fn new(
+        flash: F,
+        region: Range<u32>,
+        cipher: Option<&'a (dyn Cipher + Sync)>,
+    ) -> Result<Self, Error> {
+        if !F::WRITE_SIZE.is_power_of_two()
+            || F::WRITE_SIZE > 16
+            || region.start % F::ERASE_SIZE != 0
+            || region.end % F::ERASE_SIZE != 0
+            || region.end < region.start + 2 * F::ERASE_SIZE
+        {
+            return Err(Error::InvalidRegion);
+        }
+        let mut storage = Storage {
+            flash,
+            cipher,
+            base: region.start,
+            pages: (region.end - region.start) / F::ERASE_SIZE,
+            oldest: 0,
+            head: 0,
+            write_address: 0,
+            next_sequence: 1,
+        };
+        storage.mount()?;
+        Ok(storage)
+    }
+
+    fn mount(&mut self) -> Result<(), Error> {
+        let mut oldest: Option<(u32, u32)> = None;
+        let mut head: Option<(u32, u32)> = None;
+        for page in 0..self.pages {
+            match self.page_state(page)? {
+                PageState::Used(sequence) => {
+                    if oldest.map_or(true, |(_, s)| sequence < s) {
+                        oldest = Some((page, sequence));
+                    }
+                    if head.map_or(true, |(_, s)| sequence > s) {
+                        head = Some((page, sequence));
+                    }
+                }
+                PageState::Dirty => self.flash.erase(self.page_address(page))?,
+                PageState::Erased => {}
+            }
+        }
+        let (oldest, head) = match (oldest, head) {
+            (Some(oldest), Some(head)) => (oldest, head),
+            _ => {
+                // nothing has been written yet
+                self.oldest = 0;
+                self.head = 0;
+                return self.open_page(0);
+            }
+        };
+        self.oldest = oldest.0;
+        self.head = head.0;
+        self.next_sequence = head.1 + 1;
+        // uncommitted records count too, some of their data may have been encrypted with their sequence number
+        // before the power was lost
+        let mut max_sequence = 0;
+        for i in 0..self.used_pages() {
+            self.page_entries((self.oldest + i) % self.pages, |record| {
+                max_sequence = max_sequence.max(record.sequence)
+            })?;
+        }
+        self.next_sequence = self.next_sequence.max(max_sequence + 1);
+        if self.used_pages() == self.pages {
+            // power was lost while the oldest page was being copied to the head, which only holds copies,
+            // so the copy is started over
+            self.flash.erase(self.page_address(self.head))?;
+            self.open_page(self.head)?;
+            return self.collect();
+        }
+        self.write_address = self.page_records(self.head, |_| {})?;
+        Ok(())
+    }
+
+    fn page_address(&self, page: u32) -> u32 {
+        self.base + page * F::ERASE_SIZE
+    }
+
+    fn used_pages(&self) -> u32 {
+        (self.head + self.pages - self.oldest) % self.pages + 1
+    }
+
+    fn commit_size() -> u32 {
+        F::WRITE_SIZE.max(8)
+    }
+
+    fn record_size(length: u32) -> u32 {
+        RECORD_HEADER_SIZE + Self::commit_size() + align_up(length, F::WRITE_SIZE)
+    }
+
+    /// The largest object that fits in a page
+    pub fn max_object_size(&self) -> u32 {
+        (F::ERASE_SIZE - PAGE_HEADER_SIZE - RECORD_HEADER_SIZE - Self::commit_size())
+            & !(F::WRITE_SIZE - 1)
+    }
+
+    fn read_word(&self, address: u32) -> Result<u32, Error> {
+        let mut word = [0; 4];
+        self.flash.read(address, &mut word)?;
+        Ok(u32::from_le_bytes(word))
+    }
+
+    fn page_state(&self, page: u32) -> Result<PageState, Error> {
+        let address = self.page_address(page);
+        let mut header = [0; 12];
+        self.flash.read(address, &mut header)?;
+        let [magic, sequence, check] = words(&header);
+        if magic == PAGE_MAGIC && check == !sequence {
+            if self.read_word(address + OBSOLETE_OFFSET)? == 0xFFFFFFFF {
+                return Ok(PageState::Used(sequence));
+            }
+            return Ok(PageState::Dirty);
+        }
+        let mut buf = [0; CHUNK];
+        for offset in (0..F::ERASE_SIZE).step_by(CHUNK) {
+            self.flash.read(address + offset, &mut buf)?;
+            if buf.iter().any(|b| *b != 0xFF) {
+                return Ok(PageState::Dirty);
+            }
+        }
+        Ok(PageState::Erased)
+    }
+
+    /// Writes the header of the erased `page`, and makes it the head of the log
+    fn open_page(&mut self, page: u32) -> Result<(), Error> {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let mut header = [0xFF; 16];
+        header[0..4].copy_from_slice(&PAGE_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        header[8..12].copy_from_slice(&(!sequence).to_le_bytes());
+        let address = self.page_address(page);
+        self.flash.write(address, &header)?;
+        self.head = page;
+        self.write_address = address + PAGE_HEADER_SIZE;
+        Ok(())
+    }
+
+    fn record_at(&self, address: u32, end: u32) -> Result<Slot, Error> {
+        if address + RECORD_HEADER_SIZE > end {
+            return Ok(Slot::Corrupt);
+        }
+        let mut header = [0; RECORD_HEADER_SIZE as usize];
+        self.flash.read(address, &mut header)?;
+        if header.iter().all(|b| *b == 0xFF) {
+            return Ok(Slot::Erased);
+        }
+        let [magic, flags, uid_low, uid_high, length, capacity, sequence, check] = words(&header);
+        if magic != RECORD_MAGIC
+            || check != crc32(0, &header[..28])
+            || address + Self::record_size(length) > end
+        {
+            return Ok(Slot::Corrupt);
+        }
+        let mut commit = [0; 8];
+        self.flash.read(address + RECORD_HEADER_SIZE, &mut commit)?;
+        let [crc, crc_check] = words(&commit);
+        Ok(Slot::Record(Record {
+            address,
+            uid: (uid_high as u64) << 32 | uid_low as u64,
+            flags,
+            length,
+            capacity,
+            sequence,
+            crc: if crc_check == !crc { Some(crc) } else { None },
+        }))
+    }
+
+    /// Calls `f` with every record in `page` that has a valid header, committed or not, and returns the address
+    /// after the last record
+    fn page_entries(&self, page: u32, mut f: impl FnMut(&Record)) -> Result<u32, Error> {
+        let end = self.page_address(page) + F::ERASE_SIZE;
+        let mut address = self.page_address(page) + PAGE_HEADER_SIZE;
+        loop {
+            match self.record_at(address, end)? {
+                Slot::Erased => return Ok(address),
+                Slot::Corrupt => return Ok(end),
+                Slot::Record(record) => {
+                    f(&record);
+                    address += Self::record_size(record.length);
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with every committed record in `page`, and returns the address after the last record
+    fn page_records(&self, page: u32, mut f: impl FnMut(&Record)) -> Result<u32, Error> {
+        self.page_entries(page, |record| {
+            if record.crc.is_some() {
+                f(record);
+            }
+        })
+    }
+
+    /// Calls `f` with every committed record, from the oldest page to the head
+    fn scan(&self, mut f: impl FnMut(&Record)) -> Result<(), Error> {
+        for i in 0..self.used_pages() {
+            self.page_records((self.oldest + i) % self.pages, &mut f)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the latest record for `uid`, which may be a removal
+    fn find(&self, uid: Uid) -> Result<Option<Record>, Error> {
+        let mut latest: Option<Record> = None;
+        self.scan(|record| {
+            if record.uid == uid && latest.map_or(true, |l| record.sequence > l.sequence) {
+                latest = Some(*record);
+            }
+        })?;
+        Ok(latest)
+    }
+
+    fn find_live(&self, uid: Uid) -> Result<Record, Error> {
+        match self.find(uid)? {
+            Some(record) if !record.removed() => Ok(record),
+            _ => Err(Error::DoesNotExist),
+        }
+    }
+
+    /// Makes sure a record of `size` bytes fits in the head page, moving on to the next page if needed
+    fn reserve(&mut self, size: u32) -> Result<(), Error> {
+        if size > F::ERASE_SIZE - PAGE_HEADER_SIZE {
+            return Err(Error::InsufficientStorage);
+        }
+        for _ in 0..=self.pages {
+            if self.write_address + size <= self.page_address(self.head) + F::ERASE_SIZE {
+                return Ok(());
+            }
+            self.open_page((self.head + 1) % self.pages)?;
+            if self.used_pages() == self.pages {
+                self.collect()?;
+            }
+        }
+        Err(Error::InsufficientStorage)
+    }
+
+    /// Copies the live records of the oldest page to the head, then erases it
+    fn collect(&mut self) -> Result<(), Error> {
+        let victim = self.oldest;
+        let end = self.page_address(victim) + F::ERASE_SIZE;
+        let mut address = self.page_address(victim) + PAGE_HEADER_SIZE;
+        while let Slot::Record(record) = self.record_at(address, end)? {
+            address += Self::record_size(record.length);
+            if record.crc.is_some() && self.needs_copy(&record, victim)? {
+                self.copy_record(&record)?;
+            }
+        }
+        self.flash.write(
+            self.page_address(victim) + OBSOLETE_OFFSET,
+            &[0; 16][..F::WRITE_SIZE as usize],
+        )?;
+        self.flash.erase(self.page_address(victim))?;
+        self.oldest = (victim + 1) % self.pages;
+        Ok(())
+    }
+
+    /// Returns true if `record` in the `victim` page needs to be copied before the page is erased
+    fn needs_copy(&self, record: &Record, victim: u32) -> Result<bool, Error> {
+        let mut latest = 0;
+        let mut copied = false;
+        let mut elsewhere = false;
+        for i in 0..self.used_pages() {
+            let page = (self.oldest + i) % self.pages;
+            self.page_records(page, |r| {
+                if r.uid == record.uid {
+                    latest = latest.max(r.sequence);
+                    if page != victim {
+                        elsewhere = true;
+                        copied |= r.sequence == record.sequence;
+                    }
+                }
+            })?;
+        }
+        // a removal only needs to be kept while older records for the object are left
+        Ok(latest == record.sequence && !copied && (!record.removed() || elsewhere))
+    }
+
+    /// Copies `record` as is to the head, with the commit unit written last
+    fn copy_record(&mut self, record: &Record) -> Result<(), Error> {
+        let size = Self::record_size(record.length);
+        if self.write_address + size > self.page_address(self.head) + F::ERASE_SIZE {
+            return Err(Error::InsufficientStorage);
+        }
+        let destination = self.write_address;
+        self.write_address += size;
+        let commit_end = RECORD_HEADER_SIZE + Self::commit_size();
+        self.copy(record.address, destination, 0..RECORD_HEADER_SIZE)?;
+        self.copy(record.address, destination, commit_end..size)?;
+        self.copy(record.address, destination, RECORD_HEADER_SIZE..commit_end)
+    }
+
+    fn copy(&mut self, from: u32, to: u32, range: Range<u32>) -> Result<(), Error> {
+        let mut buf = [0; CHUNK];
+        for offset in range.clone().step_by(CHUNK) {
+            let len = (range.end - offset).min(CHUNK as u32) as usize;
+            self.flash.read(from + offset, &mut buf[..len])?;
+            self.flash.write(to + offset, &buf[..len])?;
+        }
+        Ok(())
+    }
+
+    fn nonce(uid: Uid, sequence: u32) -> [u8; 16] {
+        let mut nonce = [0; 16];
+        nonce[..8].copy_from_slice(&uid.to_be_bytes());
+        nonce[8..12].copy_from_slice(&sequence.to_be_bytes());
+        nonce
+    }
+
+    /// Appends a record, `source` fills in the object's data a chunk at a time, given the offset of the chunk
+    fn append(
+        &mut self,
+        uid: Uid,
+        mut flags: u32,
+        length: u32,
+        capacity: u32,
+        mut source: impl FnMut(&Self, u32, &mut [u8]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let size = Self::record_size(length);
+        self.reserve(size)?;
+        let cipher = match self.cipher {
+            Some(cipher) if flags & FLAG_NO_CONFIDENTIALITY == 0 && length != 0 => {
+                flags |= RECORD_ENCRYPTED;
+                Some(cipher)
+            }
+            _ => None,
+        };
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let address = self.write_address;
+        // the space is used up even if a write fails, as it may have been partly programmed
+        self.write_address += size;
+
+        let mut header = [0; RECORD_HEADER_SIZE as usize];
+        let fields = [
+            RECORD_MAGIC,
+            flags,
+            uid as u32,
+            (uid >> 32) as u32,
+            length,
+            capacity,
+            sequence,
+        ];
+        for (bytes, field) in header.chunks_exact_mut(4).zip(fields.iter()) {
+            bytes.copy_from_slice(&field.to_le_bytes());
+        }
+        let check = crc32(0, &header[..28]);
+        header[28..].copy_from_slice(&check.to_le_bytes());
+        self.flash.write(address, &header)?;
+
+        let data_address = address + RECORD_HEADER_SIZE + Self::commit_size();
+        let nonce = Self::nonce(uid, sequence);
+        let mut crc = 0;
+        let mut buf = [0; CHUNK];
+        for offset in (0..length).step_by(CHUNK) {
+            let len = (length - offset).min(CHUNK as u32) as usize;
+            source(self, offset, &mut buf[..len])?;
+            if let Some(cipher) = cipher {
+                cipher.apply_keystream(&nonce, offset, &mut buf[..len])?;
+            }
+            crc = crc32(crc, &buf[..len]);
+            let padded = align_up(len as u32, F::WRITE_SIZE) as usize;
+            buf[len..padded].fill(0xFF);
+            self.flash.write(data_address + offset, &buf[..padded])?;
+        }
+
+        let mut commit = [0xFF; 16];
+        commit[..4].copy_from_slice(&crc.to_le_bytes());
+        commit[4..8].copy_from_slice(&(!crc).to_le_bytes());
+        self.flash.write(
+            address + RECORD_HEADER_SIZE,
+            &commit[..Self::commit_size() as usize],
+        )?;
+        Ok(())
+    }
+
+    /// Reads `buf.len()` bytes of `record`'s data from `offset`, the caller checks the range
+    fn read_data(&self, record: &Record, offset: u32, buf: &mut [u8]) -> Result<(), Error> {
+        let data_address = record.address + RECORD_HEADER_SIZE + Self::commit_size();
+        let nonce = Self::nonce(record.uid, record.sequence);
+        let end = offset + buf.len() as u32;
+        let mut chunk = [0; CHUNK];
+        // the data is read in whole chunks, so decryption always starts on a block boundary
+        let mut chunk_start = offset - offset % CHUNK as u32;
+        while chunk_start < end {
+            let len = (record.length - chunk_start).min(CHUNK as u32) as usize;
+            self.flash
+                .read(data_address + chunk_start, &mut chunk[..len])?;
+            if record.flags & RECORD_ENCRYPTED != 0 {
+                match self.cipher {
+                    Some(cipher) => {
+                        cipher.apply_keystream(&nonce, chunk_start, &mut chunk[..len])?
+                    }
+                    None => return Err(Error::Cipher),
+                }
+            }
+            let from = offset.max(chunk_start);
+            let to = end.min(chunk_start + len as u32);
+            buf[(from - offset) as usize..(to - offset) as usize].copy_from_slice(
+                &chunk[(from - chunk_start) as usize..(to - chunk_start) as usize],
+            );
+            chunk_start += CHUNK as u32;
+        }
+        Ok(())
+    }
+
+    /// Checks `record`'s stored data against the CRC it was committed with
+    fn verify(&self, record: &Record) -> Result<(), Error> {
+        let data_address = record.address + RECORD_HEADER_SIZE + Self::commit_size();
+        let mut crc = 0;
+        let mut buf = [0; CHUNK];
+        for offset in (0..record.length).step_by(CHUNK) {
+            let len = (record.length - offset).min(CHUNK as u32) as usize;
+            self.flash.read(data_address + offset, &mut buf[..len])?;
+            crc = crc32(crc, &buf[..len]);
+        }
+        if Some(crc) != record.crc {
+            return Err(Error::DataCorrupt);
+        }
+        Ok(())
+    }
+
+    fn check_flags(uid: Uid, flags: u32) -> Result<(), Error> {
+        if uid == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        if flags & !USER_FLAGS != 0 {
+            return Err(Error::NotSupported);
+        }
+        Ok(())
+    }
+
+    /// Creates an empty object that can grow to `capacity` bytes through [`Storage::set_extended`]
+    pub fn create(&mut self, uid: Uid, capacity: u32, flags: u32) -> Result<(), Error> {
+        Self::check_flags(uid, flags)?;
+        if self.find_live(uid).is_ok() {
+            return Err(Error::AlreadyExists);
+        }
+        if capacity > self.max_object_size() {
+            return Err(Error::InsufficientStorage);
+        }
+        self.append(uid, flags, 0, capacity, |_, _, _| Ok(()))
+    }
+
+    /// Creates or replaces the object `uid` with `data`
+    pub fn set(&mut self, uid: Uid, data: &[u8], flags: u32) -> Result<(), Error> {
+        Self::check_flags(uid, flags)?;
+        if let Ok(existing) = self.find_live(uid) {
+            if existing.flags & FLAG_WRITE_ONCE != 0 {
+                return Err(Error::NotPermitted);
+            }
+        }
+        if data.len() as u32 > self.max_object_size() {
+            return Err(Error::InsufficientStorage);
+        }
+        let length = data.len() as u32;
+        self.append(uid, flags, length, length, |_, offset, buf| {
+            let offset = offset as usize;
+            buf.copy_from_slice(&data[offset..offset + buf.len()]);
+            Ok(())
+        })
+    }
+
+    /// Writes `data` into the object `uid` at `offset`, growing it up to its capacity. `offset` can't be past the
+    /// object's current size.
+    pub fn set_extended(&mut self, uid: Uid, offset: u32, data: &[u8]) -> Result<(), Error> {
+        let existing = self.find_live(uid)?;
+        if existing.flags & FLAG_WRITE_ONCE != 0 {
+            return Err(Error::NotPermitted);
+        }
+        let end = offset
+            .checked_add(data.len() as u32)
+            .ok_or(Error::InvalidArgument)?;
+        if offset > existing.length || end > existing.capacity {
+            return Err(Error::InvalidArgument);
+        }
+        self.verify(&existing)?;
+        let length = existing.length.max(end);
+        let flags = existing.flags & USER_FLAGS;
+        self.append(
+            uid,
+            flags,
+            length,
+            existing.capacity,
+            |storage, chunk, buf| {
+                // the chunk is made of the existing data, overwritten by `data` where they overlap
+                let chunk_end = chunk + buf.len() as u32;
+                if chunk < existing.length {
+                    let old_end = chunk_end.min(existing.length);
+                    storage.read_data(&existing, chunk, &mut buf[..(old_end - chunk) as usize])?;
+                }
+                let from = offset.max(chunk);
+                let to = end.min(chunk_end);
+                if from < to {
+                    buf[(from - chunk) as usize..(to - chunk) as usize]
+                        .copy_from_slice(&data[(from - offset) as usize..(to - offset) as usize]);
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Reads the object `uid` from `offset` into `buf`, returning the number of bytes read
+    pub fn get(&self, uid: Uid, offset: u32, buf: &mut [u8]) -> Result<usize, Error> {
+        let record = self.find_live(uid)?;
+        if offset > record.length {
+            return Err(Error::InvalidArgument);
+        }
+        self.verify(&record)?;
+        let len = buf.len().min((record.length - offset) as usize);
+        self.read_data(&record, offset, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    pub fn info(&self, uid: Uid) -> Result<Info, Error> {
+        Ok(self.find_live(uid)?.info())
+    }
+
+    /// Removes the object `uid`
+    pub fn remove(&mut self, uid: Uid) -> Result<(), Error> {
+        let existing = self.find_live(uid)?;
+        if existing.flags & FLAG_WRITE_ONCE != 0 {
+            return Err(Error::NotPermitted);
+        }
+        self.append(uid, RECORD_REMOVED, 0, 0, |_, _, _| Ok(()))
+    }
+}
+
+fn align_up(value: u32, align: u32) -> u32 {
+    (value + align - 1) & !(align - 1)
+}
+
+fn words<const N: usize>(bytes: &[u8]) -> [u32; N] {
+    let mut words = [0; N];
+    for (word, bytes) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    words
+}
+
+/// CRC-32 (IEEE), continuing from `crc`
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Service shares a [`Storage`] between the NSC veneers, a request made while another is being handled fails with
+/// [`Error::BadState`].
+pub type Service<'a, F> = nsc::Shared<Storage<'a, F>>;
+
+/// A request from the non-secure world, passed to the veneers by pointer.
+/// Fields that aren't used by an operation are ignored.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Request {
+    pub uid: Uid,
+    /// The object's data for `set`, `set_extended` and `get`, and a [`Info`] for `info`
+    pub data: *mut u8,
+    /// Length of `data`, set to the number of bytes read by `get`
+    pub data_length: u32,
+    /// Offset into the object for `set_extended` and `get`
+    pub offset: u32,
+    /// Capacity for `create`
+    pub capacity: u32,
+    pub flags: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Operation {
+    Create,
+    Set,
+    SetExtended,
+    Get,
+    Info,
+    Remove,
+}
+
+/// Handles a request from the non-secure world, returning a PSA status code. This is called by the veneers
+/// generated by [`storage_veneers`](crate::storage_veneers).
+// the request, and the buffer it points to, are checked against the non-secure world's permissions before use
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn handle<F: Flash>(
+    service: &Service<'_, F>,
+    operation: Operation,
+    request: *mut Request,
+) -> i32 {
+    let result = match unsafe { nsc::read(request) } {
+        Some(req) if req.uid & SECURE_UID_BASE != 0 => Err(Error::NotPermitted),
+        Some(req) => service
+            .with(|storage| handle_request(storage, operation, request, req))
+            .unwrap_or(Err(Error::BadState)),
+        None => Err(Error::InvalidArgument),
+    };
+    match result {
+        Ok(()) => status::SUCCESS,
+        Err(err) => err.status(),
+    }
+}
+
+fn handle_request<F: Flash>(
+    storage: &mut Storage<'_, F>,
+    operation: Operation,
+    request: *mut Request,
+    req: Request,
+) -> Result<(), Error> {
+    let len = req.data_length as usize;
+    match operation {
+        Operation::Create => storage.create(req.uid, req.capacity, req.flags),
+        Operation::Set => {
+            let data = unsafe { nsc::slice(req.data, len) }.ok_or(Error::InvalidArgument)?;
+            storage.set(req.uid, data, req.flags)
+        }
+        Operation::SetExtended => {
+            let data = unsafe { nsc::slice(req.data, len) }.ok_or(Error::InvalidArgument)?;
+            storage.set_extended(req.uid, req.offset, data)
+        }
+        Operation::Get => {
+            let buf = unsafe { nsc::slice_mut(req.data, len) }.ok_or(Error::InvalidArgument)?;
+            let read = storage.get(req.uid, req.offset, buf)?;
+            let length = unsafe { core::ptr::addr_of_mut!((*request).data_length) };
+            unsafe { nsc::write(length, read as u32) }.ok_or(Error::InvalidArgument)
+        }
+        Operation::Info => {
+            let info = storage.info(req.uid)?;
+            let ptr = req.data as *mut Info;
+            if len < core::mem::size_of::<Info>() {
+                return Err(Error::InvalidArgument);
+            }
+            unsafe { nsc::write(ptr, info) }.ok_or(Error::InvalidArgument)
+        }
+        Operation::Remove => storage.remove(req.uid),
+    }
+}
+
+/// Generates the storage service's NSC veneers for `$service`, a `static` [`storage::Service`](crate::storage::Service).
+/// The crate using this needs `#![feature(cmse_nonsecure_entry)]`.
+///
+/// Each veneer takes a pointer to a [`storage::Request`](crate::storage::Request) in non-secure memory, and returns a
+/// PSA status code: `frumsceaft_storage_create`, `frumsceaft_storage_set`, `frumsceaft_storage_set_extended`,
+/// `frumsceaft_storage_get`, `frumsceaft_storage_info`, and `frumsceaft_storage_remove`.
+/// ```ignore
+/// static STORAGE: frumsceaft::storage::Service<frumsceaft::nrf53::flash::Nvmc> = frumsceaft::storage::Service::new();
+/// frumsceaft::storage_veneers!(STORAGE);
+/// ```
+#[macro_export]
+macro_rules! storage_veneers {
+    ($service:path) => {
+        $crate::storage_veneers!(@veneer $service, frumsceaft_storage_create, Create);
+        $crate::storage_veneers!(@veneer $service, frumsceaft_storage_set, Set);
+        $crate::storage_veneers!(@veneer $service, frumsceaft_storage_set_extended, SetExtended);
+        $crate::storage_veneers!(@veneer $service, frumsceaft_storage_get, Get);
+        $crate::storage_veneers!(@veneer $service, frumsceaft_storage_info, Info);
+        $crate::storage_veneers!(@veneer $service, frumsceaft_storage_remove, Remove);
+    };
+    (@veneer $service:path, $name:ident, $operation:ident) => {
+        #[no_mangle]
+        #[cmse_nonsecure_entry]
+        pub extern "C" fn $name(request: *mut $crate::storage::Request) -> i32 {
+            $crate::storage::handle(&$service, $crate::storage::Operation::$operation, request)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{power_cuts, power_loss_tests, Ram, PAGE_SIZE};
+    use std::collections::BTreeMap;
+
+    const REGION: Range<u32> = 0..6 * PAGE_SIZE;
+    const UIDS: [Uid; 4] = [1, 2, 3, 9];
+
+    /// Stands in for AES-CTR, the keystream only depends on the nonce and the position in it
+    struct Xor;
+
+    impl Cipher for Xor {
+        fn apply_keystream(
+            &self,
+            nonce: &[u8; 16],
+            offset: u32,
+            data: &mut [u8],
+        ) -> Result<(), Error> {
+            for (i, byte) in data.iter_mut().enumerate() {
+                let position = offset as usize + i;
+                *byte ^= nonce[position % 16] ^ (position / 16) as u8 ^ 0x5A;
+            }
+            Ok(())
+        }
+    }
+
+    fn contents<const W: u32>(storage: &Storage<'_, Ram<W>>, uid: Uid) -> Option<Vec<u8>> {
+        let info = match storage.info(uid) {
+            Ok(info) => info,
+            Err(Error::DoesNotExist) => return None,
+            Err(err) => panic!("{:?}", err),
+        };
+        let mut data = vec![0; info.size as usize];
+        assert_eq!(storage.get(uid, 0, &mut data), Ok(data.len()));
+        Some(data)
+    }
+
+    fn snapshot<const W: u32>(storage: &Storage<'_, Ram<W>>) -> BTreeMap<Uid, Vec<u8>> {
+        UIDS.iter()
+            .filter_map(|uid| Some((*uid, contents(storage, *uid)?)))
+            .collect()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn objects() {
+        let flash = Ram::<4>::new(6);
+        let mut storage = Storage::new(flash.clone(), REGION, Some(&Xor)).unwrap();
+        let mut buf = [0; 6];
+        assert_eq!(storage.get(1, 0, &mut buf), Err(Error::DoesNotExist));
+        storage.set(1, b"first object", 0).unwrap();
+        storage
+            .set(2, b"plaintext", FLAG_NO_CONFIDENTIALITY)
+            .unwrap();
+        let stored = flash.bytes(REGION);
+        assert!(!contains(&stored, b"first object"));
+        assert!(contains(&stored, b"plaintext"));
+        assert_eq!(storage.get(1, 6, &mut buf), Ok(6));
+        assert_eq!(&buf, b"object");
+        assert_eq!(storage.get(1, 13, &mut buf), Err(Error::InvalidArgument));
+        assert_eq!(
+            storage.info(2),
+            Ok(Info {
+                capacity: 9,
+                size: 9,
+                flags: FLAG_NO_CONFIDENTIALITY
+            })
+        );
+
+        // objects grow up to their capacity
+        storage.create(3, 80, 0).unwrap();
+        assert_eq!(storage.create(3, 80, 0), Err(Error::AlreadyExists));
+        let data: Vec<u8> = (0..80).collect();
+        storage.set_extended(3, 0, &data[..50]).unwrap();
+        storage.set_extended(3, 40, &data[40..]).unwrap();
+        assert_eq!(contents(&storage, 3), Some(data.clone()));
+        assert_eq!(
+            storage.set_extended(3, 70, &[0; 11]),
+            Err(Error::InvalidArgument)
+        );
+        storage.remove(3).unwrap();
+        storage.create(3, 80, 0).unwrap();
+        assert_eq!(
+            storage.set_extended(3, 1, b"gap"),
+            Err(Error::InvalidArgument)
+        );
+
+        storage.set(4, b"once", FLAG_WRITE_ONCE).unwrap();
+        assert_eq!(storage.set(4, b"twice", 0), Err(Error::NotPermitted));
+        assert_eq!(storage.set_extended(4, 0, b"x"), Err(Error::NotPermitted));
+        assert_eq!(storage.remove(4), Err(Error::NotPermitted));
+
+        storage.remove(1).unwrap();
+        assert_eq!(storage.remove(1), Err(Error::DoesNotExist));
+        assert_eq!(storage.set(0, b"", 0), Err(Error::InvalidArgument));
+        assert_eq!(storage.set(5, b"", 1 << 8), Err(Error::NotSupported));
+        let large = vec![0; storage.max_object_size() as usize + 1];
+        assert_eq!(storage.set(5, &large, 0), Err(Error::InsufficientStorage));
+
+        let storage = Storage::new(flash.clone(), REGION, Some(&Xor)).unwrap();
+        assert_eq!(contents(&storage, 1), None);
+        assert_eq!(contents(&storage, 2).as_deref(), Some(&b"plaintext"[..]));
+        assert_eq!(contents(&storage, 3), Some(vec![]));
+        assert_eq!(contents(&storage, 4).as_deref(), Some(&b"once"[..]));
+        assert_eq!(storage.info(3).map(|info| info.capacity), Ok(80));
+
+        // a flipped bit is caught by the record's CRC
+        let record = storage.find_live(2).unwrap();
+        let address = record.address + RECORD_HEADER_SIZE + Storage::<Ram<4>>::commit_size();
+        flash.set_bytes(address, &[flash.bytes(address..address + 1)[0] ^ 1]);
+        assert_eq!(storage.get(2, 0, &mut buf), Err(Error::DataCorrupt));
+        assert_eq!(
+            Storage::new(flash, 0..PAGE_SIZE, None).err(),
+            Some(Error::InvalidRegion)
+        );
+    }
+
+    #[derive(Debug)]
+    enum Op {
+        Create(Uid, u32),
+        Set(Uid, Vec<u8>),
+        Extend(Uid, u32, Vec<u8>),
+        Remove(Uid),
+    }
+
+    impl Op {
+        fn apply<F: Flash>(&self, storage: &mut Storage<'_, F>) -> Result<(), Error> {
+            match self {
+                Op::Create(uid, capacity) => storage.create(*uid, *capacity, 0),
+                Op::Set(uid, data) => storage.set(*uid, data, 0),
+                Op::Extend(uid, offset, data) => storage.set_extended(*uid, *offset, data),
+                Op::Remove(uid) => storage.remove(*uid),
+            }
+        }
+
+        fn model(&self, objects: &mut BTreeMap<Uid, Vec<u8>>) {
+            match self {
+                Op::Create(uid, _) => {
+                    objects.insert(*uid, vec![]);
+                }
+                Op::Set(uid, data) => {
+                    objects.insert(*uid, data.clone());
+                }
+                Op::Extend(uid, offset, data) => {
+                    let object = objects.get_mut(uid).unwrap();
+                    object.truncate(*offset as usize);
+                    object.extend(data);
+                }
+                Op::Remove(uid) => {
+                    objects.remove(uid);
+                }
+            }
+        }
+    }
+
+    /// Writes enough to go round the pages a few times, so pages are collected with live, replaced, and removed
+    /// objects in them
+    fn workload() -> Vec<Op> {
+        let mut ops = vec![Op::Create(9, 120)];
+        for round in 0..6u32 {
+            for uid in 1..=3u32 {
+                let len = 20 + 13 * ((round + uid) % 5) as usize;
+                let data = (0..len).map(|i| (i as u32 * uid + round) as u8).collect();
+                ops.push(Op::Set(uid as Uid, data));
+            }
+            ops.push(Op::Extend(9, round * 20, vec![round as u8; 20]));
+            if round % 2 == 1 {
+                ops.push(Op::Remove((round % 3 + 1) as Uid));
+            }
+        }
+        ops
+    }
+
+    /// Cuts the power at every write and erase of every operation, and again at every write and erase of the mount
+    /// that follows. The object has to be left as it was before the operation or after it, and the operation has to
+    /// go through when it's retried.
+    fn journal<const W: u32>(cipher: Option<&'static (dyn Cipher + Sync)>) {
+        let mut flash = Ram::<W>::new(6);
+        let mut objects = BTreeMap::new();
+        for op in workload() {
+            let before = objects.clone();
+            op.model(&mut objects);
+            let applied = |flash: &Ram<W>| match Storage::new(flash.clone(), REGION, cipher)
+                .and_then(|mut storage| op.apply(&mut storage))
+            {
+                Ok(()) => true,
+                Err(err) => {
+                    assert_eq!(err, Error::Flash(flash::Error::Controller));
+                    false
+                }
+            };
+            flash = power_cuts(&flash, applied, |interrupted, cut, applied| {
+                if applied {
+                    return;
+                }
+                let mounted = |flash: &Ram<W>| Storage::new(flash.clone(), REGION, cipher).is_ok();
+                power_cuts(interrupted, mounted, |remounted, mount_cut, _| {
+                    let mut storage = Storage::new(remounted.clone(), REGION, cipher).unwrap();
+                    let found = snapshot(&storage);
+                    assert!(
+                        found == before || found == objects,
+                        "{:?} cut after {} then {}",
+                        op,
+                        cut,
+                        mount_cut
+                    );
+                    match op.apply(&mut storage) {
+                        Ok(()) | Err(Error::AlreadyExists) | Err(Error::DoesNotExist) => {}
+                        Err(err) => panic!("{:?} failed after a power cut: {:?}", op, err),
+                    }
+                    assert_eq!(snapshot(&storage), objects);
+                });
+            });
+        }
+        let storage = Storage::new(flash, REGION, cipher).unwrap();
+        assert_eq!(snapshot(&storage), objects);
+    }
+
+    /// Adds the records in `flash` with a valid header, committed or not, to `seen`, which maps the nonce of every
+    /// record written so far to its stored bytes. A nonce is only allowed to turn up again in a copy of its record.
+    fn check_nonces<const W: u32>(seen: &mut BTreeMap<(Uid, u32), Vec<u8>>, flash: &Ram<W>) {
+        // not mounted, so nothing is finished or erased before the records are read
+        let storage = Storage {
+            flash: flash.clone(),
+            cipher: None,
+            base: REGION.start,
+            pages: REGION.end / PAGE_SIZE,
+            oldest: 0,
+            head: 0,
+            write_address: 0,
+            next_sequence: 1,
+        };
+        for page in 0..storage.pages {
+            storage
+                .page_entries(page, |record| {
+                    let size = Storage::<Ram<W>>::record_size(record.length);
+                    let stored = flash.bytes(record.address..record.address + size);
+                    let previous = seen
+                        .entry((record.uid, record.sequence))
+                        .or_insert_with(|| stored.clone());
+                    // an interrupted write or copy leaves some of the record erased
+                    assert!(
+                        previous.len() == stored.len()
+                            && previous
+                                .iter()
+                                .zip(&stored)
+                                .all(|(a, b)| a == b || *a == 0xFF || *b == 0xFF),
+                        "nonce of {} sequence {} reused",
+                        record.uid,
+                        record.sequence
+                    );
+                    for (a, b) in previous.iter_mut().zip(&stored) {
+                        *a &= b;
+                    }
+                })
+                .unwrap();
+        }
+    }
+
+    /// Cuts the power at every write and erase of every operation, then writes something else to the object. The
+    /// interrupted record may have had some of its data encrypted already, so its nonce mustn't be used again.
+    fn nonces<const W: u32>() {
+        let mut flash = Ram::<W>::new(6);
+        let mut seen = BTreeMap::new();
+        for op in workload() {
+            let uid = match op {
+                Op::Set(uid, _) | Op::Extend(uid, _, _) => uid,
+                Op::Create(..) | Op::Remove(_) => {
+                    let mut storage = Storage::new(flash.clone(), REGION, Some(&Xor)).unwrap();
+                    op.apply(&mut storage).unwrap();
+                    continue;
+                }
+            };
+            let applied = |flash: &Ram<W>| {
+                Storage::new(flash.clone(), REGION, Some(&Xor))
+                    .and_then(|mut storage| op.apply(&mut storage))
+                    .is_ok()
+            };
+            flash = power_cuts(&flash, applied, |interrupted, _, applied| {
+                if applied {
+                    return;
+                }
+                let mut seen = seen.clone();
+                check_nonces(&mut seen, interrupted);
+                let mut storage = Storage::new(interrupted.clone(), REGION, Some(&Xor)).unwrap();
+                storage.set(uid, &[0xA5; 40], 0).unwrap();
+                check_nonces(&mut seen, interrupted);
+            });
+            check_nonces(&mut seen, &flash);
+        }
+    }
+
+    fn power_loss<const W: u32>() {
+        journal::<W>(Some(&Xor));
+        journal::<W>(None);
+        nonces::<W>();
+    }
+
+    power_loss_tests!(power_loss);
+}
@@ -0,0 +1,185 @@
+//! Helpers shared by the services' host tests.
+//!
+//! On the target the firmware provides defmt's logger, and `defmt.x` its timestamp and panic handler. The host tests
+//! link without either, so these stand in for them.
+use crate::flash::{Error, Flash};
+use core::ops::Range;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_: &[u8]) {}
+}
+
+defmt::timestamp!("");
+
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
+
+pub(crate) const PAGE_SIZE: u32 = 256;
+
+/// Flash kept in RAM, which can lose power part way through a sequence of writes and erases. It starts at address 0,
+/// with 256 byte pages and `W` byte write units. Clones share the memory and the power, so a test can hold on to
+/// one to look at the flash or cut the power.
+#[derive(Clone)]
+pub(crate) struct Ram<const W: u32> {
+    data: Rc<RefCell<Vec<u8>>>,
+    /// Writes and erases left before the power is cut
+    budget: Rc<Cell<Option<u32>>>,
+}
+
+impl<const W: u32> Ram<W> {
+    pub(crate) fn new(pages: u32) -> Self {
+        Ram {
+            data: Rc::new(RefCell::new(vec![0xFF; (pages * PAGE_SIZE) as usize])),
+            budget: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Returns a copy of the flash, with the power on
+    pub(crate) fn snapshot(&self) -> Self {
+        Ram {
+            data: Rc::new(RefCell::new(self.data.borrow().clone())),
+            budget: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Cuts the power after `operations` more writes and erases, every write and erase fails after that. The
+    /// interrupted write leaves the first half of its data programmed, and the interrupted erase leaves the page
+    /// as it was.
+    pub(crate) fn cut_after(&self, operations: u32) {
+        self.budget.set(Some(operations));
+    }
+
+    /// Turns the power back on
+    pub(crate) fn restore(&self) {
+        self.budget.set(None);
+    }
+
+    pub(crate) fn bytes(&self, range: Range<u32>) -> Vec<u8> {
+        self.data.borrow()[range.start as usize..range.end as usize].to_vec()
+    }
+
+    /// Overwrites the flash at `address` with `data`, regardless of what was there
+    pub(crate) fn set_bytes(&self, address: u32, data: &[u8]) {
+        let address = address as usize;
+        self.data.borrow_mut()[address..address + data.len()].copy_from_slice(data);
+    }
+
+    /// Takes one write or erase from the budget, returning false if the power is cut
+    fn powered(&self) -> bool {
+        match self.budget.get() {
+            Some(0) => false,
+            Some(left) => {
+                self.budget.set(Some(left - 1));
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn check(&self, address: u32, len: usize) -> Result<usize, Error> {
+        let start = address as usize;
+        if start + len > self.data.borrow().len() {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(start)
+    }
+}
+
+impl<const W: u32> Flash for Ram<W> {
+    const ERASE_SIZE: u32 = PAGE_SIZE;
+    const WRITE_SIZE: u32 = W;
+
+    fn read(&self, address: u32, buf: &mut [u8]) -> Result<(), Error> {
+        let start = self.check(address, buf.len())?;
+        buf.copy_from_slice(&self.data.borrow()[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        let start = self.check(address, data.len())?;
+        if address % W != 0 || data.len() as u32 % W != 0 {
+            return Err(Error::Unaligned);
+        }
+        let mut flash = self.data.borrow_mut();
+        let target = &mut flash[start..start + data.len()];
+        for (i, unit) in target.chunks(W as usize).enumerate() {
+            assert!(
+                unit.iter().all(|b| *b == 0xFF),
+                "write unit at {:#x} wasn't erased",
+                address as usize + i * W as usize
+            );
+        }
+        if !self.powered() {
+            let half = data.len() / 2;
+            target[..half].copy_from_slice(&data[..half]);
+            return Err(Error::Controller);
+        }
+        target.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn erase(&mut self, address: u32) -> Result<(), Error> {
+        let start = self.check(address, PAGE_SIZE as usize)?;
+        if address % PAGE_SIZE != 0 {
+            return Err(Error::Unaligned);
+        }
+        if !self.powered() {
+            return Err(Error::Controller);
+        }
+        self.data.borrow_mut()[start..start + PAGE_SIZE as usize].fill(0xFF);
+        Ok(())
+    }
+}
+
+/// Runs `f` on copies of `flash` with the power cut after 0, 1, 2, ... writes and erases, until `f` returns true
+/// because it got through. `then` is called with each copy once the power is back on, the cut, and whether `f`
+/// got through. Returns the copy `f` got through on.
+pub(crate) fn power_cuts<const W: u32>(
+    flash: &Ram<W>,
+    mut f: impl FnMut(&Ram<W>) -> bool,
+    mut then: impl FnMut(&Ram<W>, u32, bool),
+) -> Ram<W> {
+    for cut in 0.. {
+        let copy = flash.snapshot();
+        copy.cut_after(cut);
+        let done = f(&copy);
+        copy.restore();
+        then(&copy, cut, done);
+        if done {
+            return copy;
+        }
+    }
+    unreachable!()
+}
+
+/// Generates the `power_loss_*_writes` tests, which run `$f::<W>()` on flash written a byte, a word, and a quad
+/// word at a time
+macro_rules! power_loss_tests {
+    ($f:ident) => {
+        #[test]
+        fn power_loss_byte_writes() {
+            $f::<1>();
+        }
+
+        #[test]
+        fn power_loss_word_writes() {
+            $f::<4>();
+        }
+
+        #[test]
+        fn power_loss_quad_word_writes() {
+            $f::<16>();
+        }
+    };
+}
+pub(crate) use power_loss_tests;
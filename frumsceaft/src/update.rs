@@ -0,0 +1,871 @@
+//! Firmware update for the non-secure image, modelled on the PSA Firmware Update API.
+//!
+//! The non-secure world streams a signed [`image`](crate::image) into a staging slot in secure flash with `begin`,
+//! `write`, and `finish`. `finish` verifies the image, and stages it to be installed by [`Update::install`], which
+//! should be called on every reset before `boot`. Installing swaps the primary slot, which the non-secure world
+//! runs from, with the staging slot a sector at a time through a scratch sector. Every step of the swap is appended
+//! to a log in a status region, so a swap interrupted by a power failure is resumed by the next `install`.
+//!
+//! The new image runs on trial: it has to call `accept` once it's working, otherwise the next `install` swaps the
+//! previous image back. A power failure before the trial image has booted also counts as a failed trial.
+//!
+//! Encrypted images are decrypted into the primary slot as they are installed, with the content key unwrapped by the
+//! [`KeyUnwrap`] given to [`Update::new`]. The staging slot keeps the previous image in plaintext, so it has to be in
+//! secure flash.
+//!
+//! The primary slot starts with the image header, so `MemoryLayout::non_secure_vector_table` should be set to the
+//! start of the slot plus the image's header size. The non-secure world reaches the service through the veneers
+//! generated by [`update_veneers`](crate::update_veneers).
+use crate::flash::{self, Flash};
+use crate::image::encrypted::{self, ContentKey, KeyUnwrap, MAX_WRAPPED_KEY_SIZE};
+use crate::image::{self, Header, Version, HEADER_SIZE};
+use crate::nsc::{self, status};
+use core::ops::Range;
+
+// status log records
+const RECORD_INSTALL: u32 = 0x4657_5549;
+const RECORD_STEP: u32 = 0x4657_5553;
+const RECORD_TRIAL: u32 = 0x4657_5554;
+const RECORD_REVERT: u32 = 0x4657_5552;
+const RECORD_FAILED: u32 = 0x4657_5546;
+/// Records the status region has room for on top of the swap steps, which leaves room for records lost to power
+/// failures
+const SPARE_RECORDS: u32 = 12;
+/// Size of the buffer sectors are copied through
+const CHUNK: usize = 256;
+/// Size of the install info at the start of the status region, which holds the staged image's header and wrapped
+/// content key so an interrupted swap can carry on decrypting it. The log follows.
+const INSTALL_INFO_SIZE: u32 = 176;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    Flash(flash::Error),
+    /// The staged image failed verification
+    Image(image::Error),
+    /// The operation isn't allowed in the current [`State`], or the service is already handling a request
+    BadState,
+    /// A write doesn't continue from the end of the data written so far
+    InvalidArgument,
+    /// The image doesn't fit in the staging slot
+    InsufficientStorage,
+    /// A region isn't aligned to the flash's pages, the slots aren't the same size, the scratch region is smaller
+    /// than a page, or the status region is too small
+    InvalidRegion,
+}
+
+impl From<flash::Error> for Error {
+    fn from(err: flash::Error) -> Self {
+        Error::Flash(err)
+    }
+}
+
+impl Error {
+    /// Returns the PSA status code for the error
+    pub fn status(self) -> i32 {
+        match self {
+            Error::Flash(_) | Error::Image(image::Error::Flash(_)) => status::STORAGE_FAILURE,
+            Error::Image(
+                image::Error::HashMismatch
+                | image::Error::InvalidSignature
+                | image::Error::Decryption,
+            ) => status::INVALID_SIGNATURE,
+            Error::Image(_) | Error::InvalidArgument | Error::InvalidRegion => {
+                status::INVALID_ARGUMENT
+            }
+            Error::BadState => status::BAD_STATE,
+            Error::InsufficientStorage => status::INSUFFICIENT_STORAGE,
+        }
+    }
+}
+
+/// State of the update, the values match the PSA Firmware Update component states
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum State {
+    /// No update is in progress
+    Ready = 0,
+    /// An image is being written to the staging slot
+    Writing = 1,
+    /// A verified image will be installed on the next reset
+    Staged = 3,
+    /// The last image failed verification, or was swapped back because it wasn't accepted
+    Failed = 4,
+    /// A new image is running, and will be swapped back on the next reset unless it is accepted
+    Trial = 5,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct Info {
+    /// The [`State`]
+    pub state: u32,
+    /// Version of the image in the primary slot
+    pub version: Version,
+    /// Version of the image in the staging slot, which is the previous image while a new one is on trial. This is
+    /// zero if the staging slot doesn't hold a complete image.
+    pub staging_version: Version,
+    /// Number of bytes written to the staging slot
+    pub written: u32,
+}
+
+/// The status log, as read from flash
+#[derive(Default)]
+struct Log {
+    /// Index of the first unused record
+    next: u32,
+    install: bool,
+    /// Swap steps done since the last install or revert record
+    steps: u32,
+    trial: bool,
+    revert: bool,
+    failed: bool,
+}
+
+/// Update keeps the state of a firmware update, and installs staged images.
+pub struct Update<'a, F> {
+    flash: F,
+    primary: Range<u32>,
+    staging: Range<u32>,
+    scratch: u32,
+    status: Range<u32>,
+    public_key: &'a [u8],
+    decryption: Option<&'a (dyn KeyUnwrap + Sync)>,
+    state: State,
+    next_record: u32,
+    written: u32,
+    /// Data that doesn't fill a write unit yet
+    pending: [u8; 16],
+}
+
+impl<'a, F: Flash> Update<'a, F> {
+    /// Creates the service. `primary` and `staging` are the slots, which must be the same size, `scratch` has to
+    /// hold a page, and `status` needs 176 bytes plus room for six 8 byte records per page in a slot (records are
+    /// `WRITE_SIZE` bytes on flash that writes more than 8 bytes at a time). Images must be signed with the private
+    /// key for `public_key`, a SEC1 encoded P-256 point. Encrypted images are only accepted if `decryption` is set.
+    pub fn new(
+        flash: F,
+        primary: Range<u32>,
+        staging: Range<u32>,
+        scratch: Range<u32>,
+        status: Range<u32>,
+        public_key: &'a [u8],
+        decryption: Option<&'a (dyn KeyUnwrap + Sync)>,
+    ) -> Result<Self, Error> {
+        let aligned = |r: &Range<u32>| {
+            r.start % F::ERASE_SIZE == 0 && r.end % F::ERASE_SIZE == 0 && r.start < r.end
+        };
+        if !aligned(&primary)
+            || !aligned(&staging)
+            || !aligned(&scratch)
+            || !aligned(&status)
+            || primary.end - primary.start != staging.end - staging.start
+        {
+            return Err(Error::InvalidRegion);
+        }
+        let sectors = (primary.end - primary.start) / F::ERASE_SIZE;
+        let records =
+            (status.end - status.start).saturating_sub(INSTALL_INFO_SIZE) / Self::record_size();
+        if records < 6 * sectors + SPARE_RECORDS {
+            return Err(Error::InvalidRegion);
+        }
+        let mut update = Update {
+            flash,
+            primary,
+            staging,
+            scratch: scratch.start,
+            status,
+            public_key,
+            decryption,
+            state: State::Ready,
+            next_record: 0,
+            written: 0,
+            pending: [0xFF; 16],
+        };
+        let log = update.read_log()?;
+        update.state = if log.failed || log.revert {
+            State::Failed
+        } else if log.trial {
+            State::Trial
+        } else if log.install {
+            State::Staged
+        } else {
+            State::Ready
+        };
+        Ok(update)
+    }
+
+    fn record_size() -> u32 {
+        F::WRITE_SIZE.max(8)
+    }
+
+    fn sectors(&self) -> u32 {
+        (self.primary.end - self.primary.start) / F::ERASE_SIZE
+    }
+
+    fn log_start(&self) -> u32 {
+        self.status.start + INSTALL_INFO_SIZE
+    }
+
+    fn read_log(&mut self) -> Result<Log, Error> {
+        let size = Self::record_size();
+        let mut log = Log::default();
+        let mut address = self.log_start();
+        while address + size <= self.status.end {
+            let mut record = [0; 16];
+            let record = &mut record[..size as usize];
+            self.flash.read(address, record)?;
+            if record.iter().all(|b| *b == 0xFF) {
+                break;
+            }
+            let tag = u32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+            let check = u32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+            // a record torn by a power failure is skipped
+            if check == !tag {
+                match tag {
+                    RECORD_INSTALL => {
+                        log.install = true;
+                        log.steps = 0;
+                    }
+                    RECORD_STEP => log.steps += 1,
+                    RECORD_TRIAL => log.trial = true,
+                    RECORD_REVERT => {
+                        log.revert = true;
+                        log.steps = 0;
+                    }
+                    RECORD_FAILED => log.failed = true,
+                    _ => {}
+                }
+            }
+            log.next += 1;
+            address += size;
+        }
+        self.next_record = log.next;
+        // a `clear_status` cut short after the first page leaves stale records in the pages past the end of the
+        // log, they are erased so new records aren't written over them
+        let offset = address - self.status.start;
+        let first =
+            self.status.start + (offset + F::ERASE_SIZE - 1) / F::ERASE_SIZE * F::ERASE_SIZE;
+        for page in (first..self.status.end).step_by(F::ERASE_SIZE as usize) {
+            if !self.erased(page)? {
+                self.flash.erase(page)?;
+            }
+        }
+        Ok(log)
+    }
+
+    /// Returns true if the page at `address` is blank
+    fn erased(&self, address: u32) -> Result<bool, Error> {
+        let chunk = (CHUNK as u32).min(F::ERASE_SIZE);
+        let mut buf = [0; CHUNK];
+        for offset in (0..F::ERASE_SIZE).step_by(chunk as usize) {
+            let buf = &mut buf[..chunk as usize];
+            self.flash.read(address + offset, buf)?;
+            if buf.iter().any(|b| *b != 0xFF) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn append(&mut self, tag: u32) -> Result<(), Error> {
+        let size = Self::record_size();
+        let address = self.log_start() + self.next_record * size;
+        if address + size > self.status.end {
+            return Err(Error::InsufficientStorage);
+        }
+        let mut record = [0xFF; 16];
+        record[..4].copy_from_slice(&tag.to_le_bytes());
+        record[4..8].copy_from_slice(&(!tag).to_le_bytes());
+        self.next_record += 1;
+        self.flash.write(address, &record[..size as usize])?;
+        Ok(())
+    }
+
+    /// Erases the status log. The first page goes first, so the log reads as empty if this is interrupted, and
+    /// `read_log` erases whatever is left in the later pages.
+    fn clear_status(&mut self) -> Result<(), Error> {
+        for page in (self.status.start..self.status.end).step_by(F::ERASE_SIZE as usize) {
+            self.flash.erase(page)?;
+        }
+        self.next_record = 0;
+        Ok(())
+    }
+
+    /// Writes the staged image's header and wrapped content key to the install info, the status region has to be
+    /// erased
+    fn write_install_info(&mut self, header: &Header) -> Result<(), Error> {
+        let mut info = [0xFF; INSTALL_INFO_SIZE as usize];
+        self.flash
+            .read(self.staging.start, &mut info[..HEADER_SIZE as usize])?;
+        let mut wrapped = [0; MAX_WRAPPED_KEY_SIZE];
+        let len = match (encrypted::key_size(header), self.decryption) {
+            (Ok(Some(_)), Some(unwrap)) => {
+                encrypted::wrapped_key(&self.flash, &self.staging, header, unwrap, &mut wrapped)
+                    .map_err(Error::Image)?
+            }
+            _ => 0,
+        };
+        let start = HEADER_SIZE as usize + 4;
+        info[HEADER_SIZE as usize..start].copy_from_slice(&(len as u32).to_le_bytes());
+        info[start..start + len].copy_from_slice(&wrapped[..len]);
+        self.flash.write(self.status.start, &info)?;
+        Ok(())
+    }
+
+    /// Reads the staged image's header from the install info, and unwraps its content key if it is encrypted
+    fn read_install_info(&self) -> Result<(Header, Option<ContentKey>), Error> {
+        let mut info = [0; INSTALL_INFO_SIZE as usize];
+        self.flash.read(self.status.start, &mut info)?;
+        let mut bytes = [0; HEADER_SIZE as usize];
+        bytes.copy_from_slice(&info[..HEADER_SIZE as usize]);
+        let header = Header::parse(&bytes).map_err(Error::Image)?;
+        if encrypted::key_size(&header)
+            .map_err(Error::Image)?
+            .is_none()
+        {
+            return Ok((header, None));
+        }
+        let start = HEADER_SIZE as usize + 4;
+        let mut len = [0; 4];
+        len.copy_from_slice(&info[HEADER_SIZE as usize..start]);
+        let wrapped = info
+            .get(start..start + u32::from_le_bytes(len) as usize)
+            .ok_or(Error::Image(image::Error::Decryption))?;
+        let unwrap = self
+            .decryption
+            .ok_or(Error::Image(image::Error::Decryption))?;
+        let key = encrypted::unwrap_content_key(&header, wrapped, unwrap).map_err(Error::Image)?;
+        Ok((header, Some(key)))
+    }
+
+    /// Copies a sector, decrypting it with `decrypt` if set, where `offset` is the sector's offset in its slot
+    fn copy_sector(
+        &mut self,
+        from: u32,
+        to: u32,
+        offset: u32,
+        decrypt: Option<(&Header, &ContentKey)>,
+    ) -> Result<(), Error> {
+        self.flash.erase(to)?;
+        let chunk = (CHUNK as u32).min(F::ERASE_SIZE);
+        let mut buf = [0; CHUNK];
+        let buf = &mut buf[..chunk as usize];
+        for i in (0..F::ERASE_SIZE).step_by(chunk as usize) {
+            self.flash.read(from + i, buf)?;
+            if let Some((header, key)) = decrypt {
+                key.decrypt_payload(header, offset + i, buf);
+            }
+            if buf.iter().any(|b| *b != 0xFF) {
+                self.flash.write(to + i, buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Swaps the slots, starting at `step`. Each sector is moved in three steps: staging to scratch, primary to
+    /// staging, then scratch to primary. A step's source is left intact until the step is recorded, so an
+    /// interrupted step can be redone. An encrypted image is decrypted as it is moved from staging to scratch.
+    fn swap(&mut self, step: u32, decrypt: Option<(&Header, &ContentKey)>) -> Result<(), Error> {
+        for step in step..3 * self.sectors() {
+            let offset = step / 3 * F::ERASE_SIZE;
+            let (from, to, decrypt) = match step % 3 {
+                0 => (self.staging.start + offset, self.scratch, decrypt),
+                1 => (
+                    self.primary.start + offset,
+                    self.staging.start + offset,
+                    None,
+                ),
+                _ => (self.scratch, self.primary.start + offset, None),
+            };
+            self.copy_sector(from, to, offset, decrypt)?;
+            self.append(RECORD_STEP)?;
+        }
+        Ok(())
+    }
+
+    /// Installs a staged image, finishes an interrupted swap, or swaps back an image that wasn't accepted during its
+    /// trial. This should be called on every reset, before `boot`.
+    pub fn install(&mut self) -> Result<State, Error> {
+        let log = self.read_log()?;
+        if log.failed {
+            self.state = State::Failed;
+        } else if log.trial || log.revert {
+            // the trial image wasn't accepted before the reset
+            let step = if log.revert {
+                log.steps
+            } else {
+                defmt::warn!("trial image wasn't accepted, reverting");
+                self.append(RECORD_REVERT)?;
+                0
+            };
+            self.swap(step, None)?;
+            self.append(RECORD_FAILED)?;
+            self.state = State::Failed;
+        } else if log.install {
+            // the staged image is checked again before the first step, in case the slot changed since `finish`
+            let verified = if log.steps == 0 {
+                self.verify_staged().map(|_| ())
+            } else {
+                Ok(())
+            };
+            let install = verified.and_then(|_| self.read_install_info());
+            let (header, key) = match install {
+                Ok(install) => install,
+                // past the first step the primary slot is already partly overwritten, so this is left to retry
+                Err(Error::Image(err)) if log.steps == 0 => {
+                    defmt::error!("staged image failed verification: {}", err);
+                    self.append(RECORD_FAILED)?;
+                    self.state = State::Failed;
+                    return Ok(self.state);
+                }
+                Err(err) => return Err(err),
+            };
+            self.swap(log.steps, key.as_ref().map(|key| (&header, key)))?;
+            self.append(RECORD_TRIAL)?;
+            self.state = State::Trial;
+        }
+        Ok(self.state)
+    }
+
+    /// Starts writing a new image, erasing the staging slot
+    pub fn begin(&mut self) -> Result<(), Error> {
+        match self.state {
+            State::Ready | State::Writing | State::Failed => {}
+            State::Staged | State::Trial => return Err(Error::BadState),
+        }
+        if self.next_record != 0 {
+            self.clear_status()?;
+        }
+        self.state = State::Failed;
+        for sector in (self.staging.start..self.staging.end).step_by(F::ERASE_SIZE as usize) {
+            self.flash.erase(sector)?;
+        }
+        self.written = 0;
+        self.pending = [0xFF; 16];
+        self.state = State::Writing;
+        Ok(())
+    }
+
+    /// Writes the next part of the image, `offset` must be the number of bytes written so far
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+        if self.state != State::Writing {
+            return Err(Error::BadState);
+        }
+        if offset != self.written {
+            return Err(Error::InvalidArgument);
+        }
+        if data.len() as u32 > self.staging.end - self.staging.start - self.written {
+            return Err(Error::InsufficientStorage);
+        }
+        self.write_staging(data).map_err(|err| {
+            self.state = State::Failed;
+            err
+        })
+    }
+
+    fn write_staging(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        let unit = F::WRITE_SIZE as usize;
+        while !data.is_empty() {
+            let buffered = self.written as usize % unit;
+            let n = if buffered == 0 && data.len() >= unit {
+                // whole units are written straight away
+                let n = data.len() - data.len() % unit;
+                self.flash
+                    .write(self.staging.start + self.written, &data[..n])?;
+                n
+            } else {
+                let n = (unit - buffered).min(data.len());
+                self.pending[buffered..buffered + n].copy_from_slice(&data[..n]);
+                if buffered + n == unit {
+                    let address = self.staging.start + self.written - buffered as u32;
+                    self.flash.write(address, &self.pending[..unit])?;
+                    self.pending = [0xFF; 16];
+                }
+                n
+            };
+            self.written += n as u32;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    /// Finishes writing the image and verifies it. A valid image is installed on the next reset.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.state != State::Writing {
+            return Err(Error::BadState);
+        }
+        self.state = State::Failed;
+        let buffered = self.written % F::WRITE_SIZE;
+        if buffered != 0 {
+            let address = self.staging.start + self.written - buffered;
+            self.flash
+                .write(address, &self.pending[..F::WRITE_SIZE as usize])?;
+            self.pending = [0xFF; 16];
+        }
+        let header = self.verify_staged()?;
+        self.clear_status()?;
+        self.write_install_info(&header)?;
+        self.append(RECORD_INSTALL)?;
+        self.state = State::Staged;
+        Ok(())
+    }
+
+    /// Verifies the image in the staging slot, decrypting it first if it is encrypted
+    fn verify_staged(&self) -> Result<Header, Error> {
+        let unwrap = self.decryption.map(|unwrap| unwrap as &dyn KeyUnwrap);
+        encrypted::verify(&self.flash, &self.staging, self.public_key, unwrap)
+            .map(|(header, _)| header)
+            .map_err(Error::Image)
+    }
+
+    /// Abandons the image being written or staged, or clears a failure
+    pub fn cancel(&mut self) -> Result<(), Error> {
+        match self.state {
+            State::Ready => return Ok(()),
+            State::Trial => return Err(Error::BadState),
+            State::Writing | State::Staged | State::Failed => {}
+        }
+        self.clear_status()?;
+        // erasing the header makes sure the image can't be installed later
+        self.flash.erase(self.staging.start)?;
+        self.written = 0;
+        self.state = State::Ready;
+        Ok(())
+    }
+
+    /// Accepts the image on trial, making it permanent
+    pub fn accept(&mut self) -> Result<(), Error> {
+        if self.state != State::Trial {
+            return Err(Error::BadState);
+        }
+        self.clear_status()?;
+        self.state = State::Ready;
+        Ok(())
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn info(&self) -> Info {
+        let version = |slot: &Range<u32>| {
+            Header::read(&self.flash, slot)
+                .and_then(|header| {
+                    image::total_size(&self.flash, slot, &header).map(|_| header.version)
+                })
+                .unwrap_or_default()
+        };
+        let staging_version = match self.state {
+            State::Writing => Version::default(),
+            _ => version(&self.staging),
+        };
+        Info {
+            state: self.state as u32,
+            version: version(&self.primary),
+            staging_version,
+            written: self.written,
+        }
+    }
+}
+
+/// Service shares an [`Update`] between the NSC veneers, a request made while another is being handled fails with
+/// [`Error::BadState`].
+pub type Service<'a, F> = nsc::Shared<Update<'a, F>>;
+
+/// A request from the non-secure world, passed to the veneers by pointer.
+/// Fields that aren't used by an operation are ignored.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Request {
+    /// Offset into the image for `write`
+    pub offset: u32,
+    /// The image data for `write`, and an [`Info`] for `query`
+    pub data: *mut u8,
+    pub data_length: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Operation {
+    Begin,
+    Write,
+    Finish,
+    Cancel,
+    Accept,
+    Query,
+}
+
+/// Handles a request from the non-secure world, returning a PSA status code. This is called by the veneers
+/// generated by [`update_veneers`](crate::update_veneers).
+// the request, and the buffer it points to, are checked against the non-secure world's permissions before use
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn handle<F: Flash>(
+    service: &Service<'_, F>,
+    operation: Operation,
+    request: *mut Request,
+) -> i32 {
+    let result = match unsafe { nsc::read(request) } {
+        Some(req) => service
+            .with(|update| handle_request(update, operation, req))
+            .unwrap_or(Err(Error::BadState)),
+        None => Err(Error::InvalidArgument),
+    };
+    match result {
+        Ok(()) => status::SUCCESS,
+        Err(err) => err.status(),
+    }
+}
+
+fn handle_request<F: Flash>(
+    update: &mut Update<'_, F>,
+    operation: Operation,
+    req: Request,
+) -> Result<(), Error> {
+    let len = req.data_length as usize;
+    match operation {
+        Operation::Begin => update.begin(),
+        Operation::Write => {
+            let data = unsafe { nsc::slice(req.data, len) }.ok_or(Error::InvalidArgument)?;
+            update.write(req.offset, data)
+        }
+        Operation::Finish => update.finish(),
+        Operation::Cancel => update.cancel(),
+        Operation::Accept => update.accept(),
+        Operation::Query => {
+            let ptr = req.data as *mut Info;
+            if len < core::mem::size_of::<Info>() {
+                return Err(Error::InvalidArgument);
+            }
+            unsafe { nsc::write(ptr, update.info()) }.ok_or(Error::InvalidArgument)
+        }
+    }
+}
+
+/// Generates the update service's NSC veneers for `$service`, a `static` [`update::Service`](crate::update::Service).
+/// The crate using this needs `#![feature(cmse_nonsecure_entry)]`.
+///
+/// Each veneer takes a pointer to a [`update::Request`](crate::update::Request) in non-secure memory, and returns a
+/// PSA status code: `frumsceaft_update_begin`, `frumsceaft_update_write`, `frumsceaft_update_finish`,
+/// `frumsceaft_update_cancel`, `frumsceaft_update_accept`, and `frumsceaft_update_query`.
+/// ```ignore
+/// static UPDATE: frumsceaft::update::Service<frumsceaft::nrf53::flash::Nvmc> = frumsceaft::update::Service::new();
+/// frumsceaft::update_veneers!(UPDATE);
+/// ```
+#[macro_export]
+macro_rules! update_veneers {
+    ($service:path) => {
+        $crate::update_veneers!(@veneer $service, frumsceaft_update_begin, Begin);
+        $crate::update_veneers!(@veneer $service, frumsceaft_update_write, Write);
+        $crate::update_veneers!(@veneer $service, frumsceaft_update_finish, Finish);
+        $crate::update_veneers!(@veneer $service, frumsceaft_update_cancel, Cancel);
+        $crate::update_veneers!(@veneer $service, frumsceaft_update_accept, Accept);
+        $crate::update_veneers!(@veneer $service, frumsceaft_update_query, Query);
+    };
+    (@veneer $service:path, $name:ident, $operation:ident) => {
+        #[no_mangle]
+        #[cmse_nonsecure_entry]
+        pub extern "C" fn $name(request: *mut $crate::update::Request) -> i32 {
+            $crate::update::handle(&$service, $crate::update::Operation::$operation, request)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::test_image;
+    use crate::testing::{power_cuts, power_loss_tests, Ram, PAGE_SIZE};
+    use p256::elliptic_curve::sec1::ToEncodedPoint;
+
+    const PRIMARY: Range<u32> = 0..4 * PAGE_SIZE;
+    const STAGING: Range<u32> = 4 * PAGE_SIZE..8 * PAGE_SIZE;
+    const SCRATCH: Range<u32> = 8 * PAGE_SIZE..9 * PAGE_SIZE;
+    const STATUS: Range<u32> = 9 * PAGE_SIZE..13 * PAGE_SIZE;
+    const PAGES: u32 = 13;
+
+    fn open<'a, const W: u32>(flash: &Ram<W>, public_key: &'a [u8]) -> Update<'a, Ram<W>> {
+        Update::new(
+            flash.clone(),
+            PRIMARY,
+            STAGING,
+            SCRATCH,
+            STATUS,
+            public_key,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn slot<const W: u32>(flash: &Ram<W>, slot: Range<u32>, len: usize) -> Vec<u8> {
+        flash.bytes(slot.start..slot.start + len as u32)
+    }
+
+    /// Stages `image` in odd sized writes
+    fn stage<const W: u32>(update: &mut Update<'_, Ram<W>>, image: &[u8]) -> Result<(), Error> {
+        update.begin()?;
+        let mut offset = 0;
+        for chunk in image.chunks(13) {
+            update.write(offset, chunk)?;
+            offset += chunk.len() as u32;
+        }
+        update.finish()
+    }
+
+    /// Runs `f` with the power cut after every write and erase it does, and returns the flash once it goes through.
+    /// After each cut, `check` is called with the flash once the next `install` has finished the job, and again
+    /// when that `install` was cut short as well.
+    fn interrupt<const W: u32>(
+        start: &Ram<W>,
+        public_key: &[u8],
+        mut f: impl FnMut(&mut Update<'_, Ram<W>>) -> Result<State, Error>,
+        mut check: impl FnMut(&Ram<W>, u32),
+    ) -> Ram<W> {
+        power_cuts(
+            start,
+            |flash| f(&mut open(flash, public_key)).is_ok(),
+            |flash, cut, done| {
+                if done {
+                    return;
+                }
+                // the install that resumes the swap is cut short as well, half as far in
+                let resumed = flash.snapshot();
+                resumed.cut_after(cut / 2);
+                let done = Update::new(
+                    resumed.clone(),
+                    PRIMARY,
+                    STAGING,
+                    SCRATCH,
+                    STATUS,
+                    public_key,
+                    None,
+                )
+                .and_then(|mut update| update.install())
+                .is_ok();
+                resumed.restore();
+                if !done {
+                    open(&resumed, public_key).install().unwrap();
+                }
+                check(&resumed, cut);
+                open(flash, public_key).install().unwrap();
+                check(flash, cut);
+            },
+        )
+    }
+
+    #[test]
+    fn staging() {
+        let public_key = test_image::public_key();
+        let image = test_image::build(2, 800, true, false);
+        let flash = Ram::<4>::new(PAGES);
+        let mut update = open(&flash, &public_key);
+        assert_eq!(update.install(), Ok(State::Ready));
+        assert_eq!(update.finish(), Err(Error::BadState));
+        update.begin().unwrap();
+        assert_eq!(update.write(1, &image[..10]), Err(Error::InvalidArgument));
+        let too_large = vec![0; 4 * PAGE_SIZE as usize + 1];
+        assert_eq!(update.write(0, &too_large), Err(Error::InsufficientStorage));
+
+        for (offset, expected) in [
+            (100, image::Error::HashMismatch),
+            (image.len() - 5, image::Error::InvalidSignature),
+        ] {
+            let mut corrupt = image.clone();
+            corrupt[offset] ^= 1;
+            assert_eq!(stage(&mut update, &corrupt), Err(Error::Image(expected)));
+            assert_eq!(update.state(), State::Failed);
+        }
+        let other_key = p256::SecretKey::from_be_bytes(&[9; 32])
+            .unwrap()
+            .public_key()
+            .to_encoded_point(false);
+        let mut other = Update::new(
+            flash.clone(),
+            PRIMARY,
+            STAGING,
+            SCRATCH,
+            STATUS,
+            other_key.as_bytes(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            stage(&mut other, &image),
+            Err(Error::Image(image::Error::InvalidSignature))
+        );
+
+        stage(&mut update, &image).unwrap();
+        assert_eq!(update.state(), State::Staged);
+        assert_eq!(update.info().staging_version.major, 2);
+        assert_eq!(update.begin(), Err(Error::BadState));
+        update.cancel().unwrap();
+        assert_eq!(open(&flash, &public_key).install(), Ok(State::Ready));
+    }
+
+    fn power_loss<const W: u32>() {
+        let public_key = test_image::public_key();
+        let old = test_image::build(1, 500, false, false);
+        let new = test_image::build(2, 800, true, true);
+        let flash = Ram::<W>::new(PAGES);
+        flash.set_bytes(PRIMARY.start, &old);
+        let mut update = open(&flash, &public_key);
+        assert_eq!(update.install(), Ok(State::Ready));
+        stage(&mut update, &new).unwrap();
+        let staged = flash.snapshot();
+
+        // an interrupted swap is resumed, and the new image runs on trial
+        let trial = interrupt(
+            &staged,
+            &public_key,
+            |update| update.install(),
+            |flash, cut| {
+                match open(flash, &public_key).state() {
+                    State::Trial => {
+                        assert_eq!(slot(flash, PRIMARY, new.len()), new, "cut after {}", cut);
+                        assert_eq!(slot(flash, STAGING, old.len()), old, "cut after {}", cut);
+                    }
+                    // the trial record made it before the power was cut, so the trial failed and was swapped back
+                    state => {
+                        assert_eq!(state, State::Failed, "cut after {}", cut);
+                        assert_eq!(slot(flash, PRIMARY, old.len()), old, "cut after {}", cut);
+                    }
+                }
+            },
+        );
+        assert_eq!(open(&trial, &public_key).state(), State::Trial);
+        assert_eq!(slot(&trial, PRIMARY, new.len()), new);
+
+        // a trial that isn't accepted is swapped back
+        interrupt(
+            &trial,
+            &public_key,
+            |update| update.install(),
+            |flash, cut| {
+                assert_eq!(slot(flash, PRIMARY, old.len()), old, "cut after {}", cut);
+                assert_eq!(open(flash, &public_key).state(), State::Failed);
+            },
+        );
+
+        // accepting clears the log, the next update mustn't trip over records left by an interrupted clear
+        power_cuts(
+            &trial,
+            |flash| open(flash, &public_key).accept().is_ok(),
+            |flash, cut, _| {
+                let mut update = open(flash, &public_key);
+                match update.state() {
+                    State::Trial => update.accept().unwrap(),
+                    state => assert_eq!(state, State::Ready, "cut after {}", cut),
+                }
+                assert!(
+                    flash.bytes(STATUS).iter().all(|b| *b == 0xFF),
+                    "cut after {}",
+                    cut
+                );
+                stage(&mut update, &old).unwrap();
+                assert_eq!(update.install(), Ok(State::Trial));
+                assert_eq!(slot(flash, PRIMARY, old.len()), old);
+                assert_eq!(slot(flash, STAGING, new.len()), new);
+            },
+        );
+    }
+
+    power_loss_tests!(power_loss);
+}